@@ -0,0 +1,100 @@
+//! Throughput of reference parsing, template validation, expansion, and
+//! tree listing over fixed fixtures.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::path::Path;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use tempfile::TempDir;
+use textcon::{Engine, EngineOptions, parse_reference};
+
+/// Ten files of 100 KiB each, 1000 bytes to a line.
+fn write_large_files(root: &Path) {
+    let line = format!("{}\n", "x".repeat(999));
+    let content = line.repeat(100 * 1024 / 1000 + 1);
+    fs::create_dir(root.join("large")).unwrap();
+    for index in 0..10 {
+        fs::write(root.join(format!("large/{index}.txt")), &content).unwrap();
+    }
+}
+
+/// A tree five levels deep with 500 files, 100 per level.
+fn write_deep_tree(root: &Path) {
+    let mut directory = root.join("deep");
+    for level in 0..5 {
+        directory = directory.join(format!("level{level}"));
+        fs::create_dir_all(&directory).unwrap();
+        for index in 0..100 {
+            fs::write(directory.join(format!("file{index}.rs")), "").unwrap();
+        }
+    }
+}
+
+fn engine(root: &Path) -> Engine {
+    Engine::new(EngineOptions {
+        base_dir: root.to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap()
+}
+
+fn benchmarks(criterion: &mut Criterion) {
+    let temporary = TempDir::new().unwrap();
+    let root = temporary.path();
+    write_large_files(root);
+    write_deep_tree(root);
+    let engine = engine(root);
+
+    criterion.bench_function("parse_reference", |bencher| {
+        bencher.iter(|| {
+            parse_reference(black_box(
+                r"{{ @[src, a\,b.rs] | markdown | grep=fn | head=20 }}",
+            ))
+            .unwrap()
+        });
+    });
+
+    let mut template = String::new();
+    for index in 0..100 {
+        writeln!(
+            template,
+            "line {index}: {{{{ @large/{}.txt }}}}",
+            index % 10
+        )
+        .unwrap();
+    }
+    criterion.bench_function("validate_100_references", |bencher| {
+        bencher.iter(|| {
+            engine.validate_template(&mut template.as_bytes()).unwrap();
+        });
+    });
+
+    let bytes: u64 = fs::read_dir(root.join("large"))
+        .unwrap()
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum();
+    criterion
+        .benchmark_group("expand")
+        .throughput(Throughput::Bytes(bytes))
+        .bench_function("ten_files_1mb", |bencher| {
+            bencher.iter(|| {
+                engine
+                    .expand_template(&mut &b"{{ @large | markdown }}"[..], &mut io::sink())
+                    .unwrap();
+            });
+        });
+
+    criterion.bench_function("tree_500_files_5_levels", |bencher| {
+        bencher.iter(|| {
+            engine
+                .expand_template(&mut &b"{{ @deep | tree }}"[..], &mut io::sink())
+                .unwrap();
+        });
+    });
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+#![no_main]
+
+use std::path::{Component, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use libfuzzer_sys::fuzz_target;
+use tempfile::TempDir;
+use textcon::{Engine, EngineOptions, ProgressEvent};
+
+/// A small fixed tree for references to find, shared by every run.
+static PROJECT: LazyLock<TempDir> = LazyLock::new(|| {
+    let project = TempDir::new().unwrap();
+    std::fs::create_dir(project.path().join("src")).unwrap();
+    std::fs::write(
+        project.path().join("src/lib.rs"),
+        "//! Library.\npub fn f() {}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        project.path().join("notes.md"),
+        "# Notes\n\n{{ @src/lib.rs }}\n",
+    )
+    .unwrap();
+    project
+});
+
+// Arbitrary template bytes expand or fail without panicking, and under the
+// sandbox every file read stays beneath the base directory.
+fuzz_target!(|data: &[u8]| {
+    let mut engine = Engine::new(EngineOptions {
+        base_dir: PROJECT.path().to_path_buf(),
+        sandbox: true,
+        max_total_output_bytes: Some(1 << 20),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    let read: Arc<Mutex<Vec<PathBuf>>> = Arc::default();
+    let sink = Arc::clone(&read);
+    engine.set_progress(move |event| {
+        if let ProgressEvent::ReadFile { path, .. } = event {
+            sink.lock().unwrap().push(path);
+        }
+    });
+
+    let mut output = Vec::new();
+    let _ = engine.expand_template(&mut &data[..], &mut output);
+    for path in read.lock().unwrap().iter() {
+        assert!(
+            path.components()
+                .all(|component| matches!(component, Component::Normal(_))),
+            "{} escapes the base directory",
+            path.display()
+        );
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use textcon::parse_reference;
+
+// Any input either parses into one reference with at least one path and a
+// position inside the input, or is rejected with an error; it never panics.
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    if let Ok(reference) = parse_reference(&source) {
+        assert!(!reference.paths.is_empty());
+        assert!(reference.offset < source.len() as u64);
+        assert!(reference.line >= 1 && reference.column >= 1);
+    }
+});
@@ -0,0 +1,198 @@
+#![allow(clippy::redundant_pub_crate)]
+
+use std::path::Path;
+
+use crate::parser::ParsedReference;
+
+/// Prefix of reference paths naming a zip archive.
+const ARCHIVE_PREFIX: &str = "zip:";
+
+/// The archive named by `reference`, which must be a single `zip:PATH` path.
+/// `@./zip:PATH` names a file instead.
+pub(crate) fn archive_path(reference: &ParsedReference) -> Option<&Path> {
+    let [path] = reference.paths.as_slice() else {
+        return None;
+    };
+    path.to_str()?
+        .strip_prefix(ARCHIVE_PREFIX)
+        .filter(|archive| !archive.is_empty())
+        .map(Path::new)
+}
+
+#[cfg(feature = "archive")]
+pub(crate) use zip_archive::{Archive, Member};
+
+#[cfg(feature = "archive")]
+mod zip_archive {
+    use std::collections::BTreeMap;
+    use std::ffi::OsString;
+    use std::fs::File;
+    use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+
+    use zip::ZipArchive;
+
+    use crate::error::{Result, TextconError};
+    use crate::render::{Source, encode_path};
+
+    /// An opened zip archive and its members in name order.
+    pub(crate) struct Archive {
+        zip: ZipArchive<File>,
+        /// Index, enclosed name, and directory flag of every member.
+        entries: Vec<(usize, PathBuf, bool)>,
+        reversed: bool,
+    }
+
+    impl Archive {
+        /// Read the central directory of `file`. Members whose names would
+        /// escape the archive, such as absolute or `..` paths, are ignored.
+        pub(crate) fn open(file: File, logical: &Path) -> Result<Self> {
+            let mut zip = ZipArchive::new(file).map_err(|error| archive_error(logical, &error))?;
+            let mut entries = Vec::with_capacity(zip.len());
+            for index in 0..zip.len() {
+                let member = zip
+                    .by_index_raw(index)
+                    .map_err(|error| archive_error(logical, &error))?;
+                if let Some(name) = member.enclosed_name()
+                    && name.components().next().is_some()
+                {
+                    entries.push((index, name, member.is_dir()));
+                }
+            }
+            entries.sort_by(|left, right| left.1.cmp(&right.1));
+            Ok(Self {
+                zip,
+                entries,
+                reversed: false,
+            })
+        }
+
+        /// Visit members in descending rather than ascending name order.
+        pub(crate) fn reverse(&mut self) {
+            self.entries.reverse();
+            self.reversed = !self.reversed;
+        }
+
+        /// Indices and names of the file members, in visiting order.
+        pub(crate) fn files(&self) -> Vec<(usize, PathBuf)> {
+            self.entries
+                .iter()
+                .filter(|(_, _, is_dir)| !is_dir)
+                .map(|(index, name, _)| (*index, name.clone()))
+                .collect()
+        }
+
+        /// Decompress the member at `index`, labelled `logical` in errors.
+        pub(crate) fn read(&mut self, index: usize, logical: &Path) -> Result<Vec<u8>> {
+            let mut member = self
+                .zip
+                .by_index(index)
+                .map_err(|error| archive_error(logical, &error))?;
+            let mut content = Vec::with_capacity(usize::try_from(member.size()).unwrap_or(0));
+            member
+                .read_to_end(&mut content)
+                .map_err(|error| TextconError::path_io("read archive member", logical, error))?;
+            Ok(content)
+        }
+
+        /// Write a box-drawn listing of every member beneath `logical`, with
+        /// directories, including those only implied by member names,
+        /// suffixed by `/`.
+        pub(crate) fn write_tree<W: Write>(&self, logical: &Path, output: &mut W) -> Result<()> {
+            let mut root = Node::default();
+            for (_, name, is_dir) in &self.entries {
+                let mut node = &mut root;
+                let mut components = name.iter().peekable();
+                while let Some(component) = components.next() {
+                    node = node.children.entry(component.to_owned()).or_default();
+                    node.directory |= *is_dir || components.peek().is_some();
+                }
+            }
+            writeln!(output, "{}", encode_path(logical.as_os_str()))
+                .map_err(TextconError::output)?;
+            root.write_children("", self.reversed, output)
+                .map_err(TextconError::output)
+        }
+    }
+
+    #[derive(Default)]
+    struct Node {
+        children: BTreeMap<OsString, Self>,
+        directory: bool,
+    }
+
+    impl Node {
+        fn write_children<W: Write>(
+            &self,
+            prefix: &str,
+            reverse: bool,
+            output: &mut W,
+        ) -> io::Result<()> {
+            let mut children: Vec<_> = self.children.iter().collect();
+            if reverse {
+                children.reverse();
+            }
+            let count = children.len();
+            for (position, (name, child)) in children.into_iter().enumerate() {
+                let last = position + 1 == count;
+                writeln!(
+                    output,
+                    "{prefix}{}{}{}",
+                    if last { "└── " } else { "├── " },
+                    encode_path(name),
+                    if child.directory { "/" } else { "" }
+                )?;
+                if child.directory {
+                    let nested = format!("{prefix}{}", if last { "    " } else { "│   " });
+                    child.write_children(&nested, reverse, output)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// A decompressed member, rendered like a file modified with its archive.
+    pub(crate) struct Member {
+        content: Cursor<Vec<u8>>,
+        modified: SystemTime,
+    }
+
+    impl Member {
+        pub(crate) const fn new(content: Vec<u8>, modified: SystemTime) -> Self {
+            Self {
+                content: Cursor::new(content),
+                modified,
+            }
+        }
+    }
+
+    impl Read for Member {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.content.read(buffer)
+        }
+    }
+
+    impl Seek for Member {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            self.content.seek(position)
+        }
+    }
+
+    impl Source for Member {
+        fn modified(&self) -> io::Result<SystemTime> {
+            Ok(self.modified)
+        }
+
+        fn size(&self) -> io::Result<u64> {
+            Ok(self.content.get_ref().len() as u64)
+        }
+    }
+
+    fn archive_error(logical: &Path, error: &zip::result::ZipError) -> TextconError {
+        TextconError::Archive {
+            path: logical.to_path_buf(),
+            message: error.to_string(),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+#![allow(clippy::redundant_pub_crate)]
+
+use std::env;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use crate::error::{Result, TextconError};
+use crate::parser::ParsedReference;
+use crate::render::format_utc;
+
+/// Prefix of reference paths naming a built-in value rather than a file. No
+/// ordinary relative path starts with it.
+const BUILTIN_PREFIX: &str = ":";
+
+/// Prefix of a built-in name naming a date format.
+const DATE_PREFIX: &str = "date:";
+
+/// A value about the current run named by a `{{ @:date:FORMAT }}`,
+/// `{{ @:hostname }}`, `{{ @:username }}`, or `{{ @:cwd }}` reference. None
+/// of them reads the filesystem beneath a base directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Builtin {
+    /// `:date:FORMAT`: the current UTC time in a `strftime`-style format.
+    Date(String),
+    /// `:hostname`: the name of this machine.
+    Hostname,
+    /// `:username`: the user named by `USER`, or `USERNAME` on Windows.
+    Username,
+    /// `:cwd`: the process working directory.
+    Cwd,
+}
+
+impl Builtin {
+    /// The value named by `reference`, which must be a single path. Use
+    /// `@./:hostname` and the like for files with these names.
+    pub(crate) fn from_reference(reference: &ParsedReference) -> Option<Self> {
+        let [path] = reference.paths.as_slice() else {
+            return None;
+        };
+        match path.to_str()?.strip_prefix(BUILTIN_PREFIX)? {
+            "hostname" => Some(Self::Hostname),
+            "username" => Some(Self::Username),
+            "cwd" => Some(Self::Cwd),
+            name => name
+                .strip_prefix(DATE_PREFIX)
+                .filter(|format| !format.is_empty())
+                .map(|format| Self::Date(format.to_owned())),
+        }
+    }
+
+    /// The reference path as written, for errors.
+    pub(crate) fn path(&self) -> PathBuf {
+        let name = match self {
+            Self::Date(format) => format!("{DATE_PREFIX}{format}"),
+            Self::Hostname => "hostname".to_owned(),
+            Self::Username => "username".to_owned(),
+            Self::Cwd => "cwd".to_owned(),
+        };
+        PathBuf::from(format!("{BUILTIN_PREFIX}{name}"))
+    }
+
+    /// The current value, without a line ending. `offset` locates the
+    /// reference for a date format error, and `allow_commands` lets the host
+    /// name come from the `hostname` program.
+    pub(crate) fn value(&self, offset: u64, allow_commands: bool) -> Result<Vec<u8>> {
+        match self {
+            Self::Date(format) => format_utc(SystemTime::now(), format)
+                .map(String::into_bytes)
+                .map_err(|specifier| TextconError::TemplateSyntax {
+                    offset,
+                    message: format!("unsupported date specifier {specifier}"),
+                }),
+            Self::Hostname => match hostname(allow_commands) {
+                Ok(Some(name)) => Ok(name),
+                Ok(None) => Err(TextconError::CommandsDisabled { path: self.path() }),
+                Err(source) => Err(TextconError::Input {
+                    name: "hostname".into(),
+                    source,
+                }),
+            },
+            Self::Username => ["USER", "USERNAME"]
+                .into_iter()
+                .find_map(|name| env::var_os(name).filter(|value| !value.is_empty()))
+                .map(std::ffi::OsString::into_encoded_bytes)
+                .ok_or_else(|| TextconError::Input {
+                    name: "username".into(),
+                    source: io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "neither USER nor USERNAME is set",
+                    ),
+                }),
+            Self::Cwd => env::current_dir()
+                .map(|directory| directory.into_os_string().into_encoded_bytes())
+                .map_err(|source| TextconError::Input {
+                    name: "current directory".into(),
+                    source,
+                }),
+        }
+    }
+}
+
+/// The kernel's host name on Linux, then `COMPUTERNAME` or `HOSTNAME`, and
+/// otherwise the output of `hostname`, or `None` when that may not run.
+fn hostname(allow_commands: bool) -> io::Result<Option<Vec<u8>>> {
+    #[cfg(target_os = "linux")]
+    if let Ok(mut name) = std::fs::read("/proc/sys/kernel/hostname") {
+        name.truncate(name.trim_ascii_end().len());
+        return Ok(Some(name));
+    }
+    if let Some(name) = ["COMPUTERNAME", "HOSTNAME"]
+        .into_iter()
+        .find_map(|name| env::var_os(name).filter(|value| !value.is_empty()))
+    {
+        return Ok(Some(name.into_encoded_bytes()));
+    }
+    if !allow_commands {
+        return Ok(None);
+    }
+    let output = Command::new("hostname")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "hostname exited with {}",
+            output.status
+        )));
+    }
+    let mut name = output.stdout;
+    name.truncate(name.trim_ascii_end().len());
+    Ok(Some(name))
+}
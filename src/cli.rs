@@ -1,9 +1,36 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
 
 use clap::Parser;
 use ignore::gitignore::GitignoreBuilder;
 
-use crate::RenderMode;
+use crate::render::encode_path;
+use crate::{
+    DEFAULT_MAX_TEMPLATE_DEPTH, DEFAULT_SECTION_MARKER_PREFIX, FileMarkers, LineEnding, RenderMode,
+    SkipReason, WalkOrder,
+};
+
+/// File name of the project template used when no input is given.
+pub const DEFAULT_TEMPLATE_NAME: &str = "textcon.template";
+
+/// Content of the default template that `--init` creates.
+pub const STARTER_TEMPLATE: &str = "\
+# Project context
+
+{{ @README.md }}
+
+## Layout
+
+{{ @. | tree }}
+
+## Source
+
+{{ @src }}
+";
 
 const LONG_HELP: &str = r"Examples:
   # Bundle selected files with H1 path labels
@@ -19,13 +46,28 @@ const LONG_HELP: &str = r"Examples:
   textcon --template context.md
   printf '{{ @README.md }}' | textcon --template -
 
+  # Expand ./textcon.template
+  textcon
+
 References:
   {{ @file }}              Include one file
   {{ @directory }}         Include selected descendants without labels
   {{ @directory | markdown }} Include descendants with H1 path labels
-  {{ @path | raw }}        Disable Markdown adaptation for this reference";
+  {{ @path | raw }}        Disable Markdown adaptation for this reference
+  {{ @directory | tree }}  List selected descendants as an indented tree
+  {{ @directory | mermaid }} Draw selected descendants as a Mermaid graph
+  {{ @directory | summary }} Count selected files, bytes, and the newest date
+  {{ @file | template }}   Expand the file's own references and variables
+  {{ @directory | reverse }} Visit directory entries in descending name order
+  {{ @file | grep=REGEX }} Keep only lines matching REGEX; grep-v= drops them
+  {{ @file | tail=N }}     Keep only the last N lines; head=N keeps the first
+  {{ @file | section=NAME }} Keep only lines between // === BEGIN NAME === and
+                           // === END NAME === markers
+  {{ @path | base=NAME }}  Resolve path beneath the --base-alias NAME=DIR directory
+  {{ $name }}              Substitute the value of --replace name=VALUE";
 
 /// Streaming text composition for code and LLM context.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Parser)]
 #[command(
     name = "textcon",
@@ -35,33 +77,71 @@ References:
 )]
 pub struct Cli {
     /// Files and directories to compose; use '-' once for stdin.
-    #[arg(
-        value_name = "INPUT",
-        required_unless_present = "template",
-        conflicts_with = "template"
-    )]
+    #[arg(value_name = "INPUT", conflicts_with = "template")]
     pub inputs: Vec<PathBuf>,
 
-    /// Stream-expand a template file; use '-' for stdin.
+    /// Stream-expand a template file; use '-' for stdin. Repeat to expand
+    /// several templates in order.
     #[arg(short, long, value_name = "FILE", conflicts_with = "inputs")]
-    pub template: Option<PathBuf>,
+    pub template: Vec<PathBuf>,
+
+    /// Template expanded from the base directory when no input is given.
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_TEMPLATE_NAME)]
+    pub default_template: PathBuf,
+
+    /// Create a starter default template in the base directory and exit.
+    #[arg(long, conflicts_with_all = ["inputs", "template", "fmt", "validate"])]
+    pub init: bool,
 
     /// Rendering inherited by operands and bare template references.
     #[arg(long, value_enum, default_value_t = RenderMode::Markdown)]
     pub render: RenderMode,
 
+    /// Check that every template reference resolves, without writing output.
+    #[arg(long, conflicts_with = "inputs")]
+    pub validate: bool,
+
+    /// Print the templates with canonically spaced placeholders, without
+    /// resolving references.
+    #[arg(long, conflicts_with_all = ["inputs", "validate"])]
+    pub fmt: bool,
+
     /// Base directory for relative template references.
-    #[arg(short, long, value_name = "DIR", requires = "template")]
+    #[arg(short, long, value_name = "DIR", conflicts_with = "inputs")]
     pub base_dir: Option<PathBuf>,
 
+    /// Resolve template references beneath the template file's directory.
+    #[arg(long, requires = "template", conflicts_with = "base_dir")]
+    pub template_relative: bool,
+
+    /// Name DIR for `| base=NAME` references; repeat for more.
+    #[arg(
+        long = "base-alias",
+        value_name = "NAME=DIR",
+        action = clap::ArgAction::Append,
+        value_parser = parse_base_alias,
+        conflicts_with = "inputs"
+    )]
+    pub base_aliases: Vec<(String, PathBuf)>,
+
     /// Confine template references beneath the base directory.
-    #[arg(long, requires = "template")]
+    #[arg(long, conflicts_with = "inputs")]
     pub sandbox: bool,
 
     /// Maximum descendant depth; the requested directory is depth zero.
     #[arg(short = 'd', long, value_name = "N")]
     pub max_depth: Option<usize>,
 
+    /// Fail a directory walk that would select more than N files.
+    #[arg(long, value_name = "N")]
+    pub max_files: Option<usize>,
+
+    /// Fail rather than write more than SIZE bytes of rendered content, such
+    /// as 10M; K, M, and G are powers of 1000, and Ki, Mi, and Gi of 1024,
+    /// in any case.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_total_size: Option<u64>,
+
     /// Gitignore-style selection rule; repeat in precedence order.
     #[arg(
         short = 'x',
@@ -72,13 +152,398 @@ pub struct Cli {
     )]
     pub excludes: Vec<String>,
 
+    /// Select only discovered files with this extension; may be repeated.
+    #[arg(long = "include-extension", value_name = "EXT", action = clap::ArgAction::Append)]
+    pub include_extensions: Vec<String>,
+
+    /// Skip discovered files with this extension, besides compiled binaries;
+    /// may be repeated.
+    #[arg(long = "exclude-extension", value_name = "EXT", action = clap::ArgAction::Append)]
+    pub exclude_extensions: Vec<String>,
+
     /// Disable `.gitignore` processing during directory discovery.
     #[arg(long)]
     pub no_gitignore: bool,
 
+    /// Order of each directory's entries during discovery.
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = WalkOrder::Name)]
+    pub walk_order: WalkOrder,
+
+    /// Discover only files tracked by Git, when inside a repository.
+    #[arg(long)]
+    pub git_tracked: bool,
+
     /// Include dot-prefixed descendants during directory discovery.
     #[arg(long)]
     pub hidden: bool,
+
+    /// Select only discovered files modified within DURATION, such as 7d or 12h.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub modified_within: Option<Duration>,
+
+    /// Write each path that directory selection skipped, with its reason, to FILE.
+    #[arg(long, value_name = "FILE")]
+    pub skipped_report: Option<PathBuf>,
+
+    /// Write the SHA-256 digest of every file the templates reference to
+    /// FILE, in the format `sha256sum --check` reads.
+    #[arg(long, value_name = "FILE", conflicts_with = "inputs")]
+    pub manifest: Option<PathBuf>,
+
+    /// Annotate tree directories with the first line of their README.md.
+    #[arg(long)]
+    pub readme_summaries: bool,
+
+    /// Append the leading Rust or Python doc comment to file headings and tree entries.
+    #[arg(long)]
+    pub doc_summaries: bool,
+
+    /// Wrap Markdown-rendered source files in language-tagged code fences.
+    #[arg(long)]
+    pub code_fences: bool,
+
+    /// Follow each labelled file with a SHA-256 checksum comment.
+    #[arg(long)]
+    pub checksums: bool,
+
+    /// Convert the line endings of included files to LF, CRLF, or the
+    /// platform's native ending.
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = LineEnding::Preserve)]
+    pub line_endings: LineEnding,
+
+    /// Remove trailing whitespace from every line of included files.
+    #[arg(long)]
+    pub strip_trailing_whitespace: bool,
+
+    /// End every nonempty included file with a line feed.
+    #[arg(long)]
+    pub ensure_trailing_newline: bool,
+
+    /// Fail on an included file that looks binary instead of copying it.
+    #[arg(long)]
+    pub reject_binary: bool,
+
+    /// Annotate each labelled file with its modification time.
+    #[arg(long)]
+    pub modified_times: bool,
+
+    /// Collapse tree directories with more than N selected entries into a summary.
+    #[arg(long, value_name = "N")]
+    pub summarize_breadth_over: Option<usize>,
+
+    /// List symlinks in trees with their targets instead of skipping them.
+    #[arg(long)]
+    pub symlink_targets: bool,
+
+    /// Follow each file in tree listings with its size.
+    #[arg(long)]
+    pub file_sizes: bool,
+
+    /// Render at most N tree listings; later ones become a one-line note.
+    #[arg(long, value_name = "N")]
+    pub max_trees: Option<usize>,
+
+    /// Substitute VALUE for `{{ $KEY }}` template variables; repeat for more.
+    #[arg(
+        long = "replace",
+        value_name = "KEY=VALUE",
+        action = clap::ArgAction::Append,
+        value_parser = parse_replacement,
+        conflicts_with = "inputs"
+    )]
+    pub replacements: Vec<(String, String)>,
+
+    /// Let @git:NAME references, and @:hostname where the host name is not
+    /// otherwise available, run programs.
+    #[arg(long, conflicts_with = "inputs")]
+    pub allow_commands: bool,
+
+    /// Copy undefined `{{ $KEY }}` variables literally instead of failing.
+    #[arg(long, conflicts_with = "inputs")]
+    pub allow_undefined: bool,
+
+    /// Write STRING in place of a template reference whose path does not
+    /// exist, with {path} replaced by the reference's paths, instead of failing.
+    #[arg(
+        long,
+        value_name = "STRING",
+        allow_hyphen_values = true,
+        value_parser = parse_escapes,
+        conflicts_with = "inputs"
+    )]
+    pub missing_placeholder: Option<String>,
+
+    /// Resolve every template reference before writing anything, and list
+    /// each one that fails.
+    #[arg(long, conflicts_with = "inputs")]
+    pub strict: bool,
+
+    /// Print file, directory, byte, estimated token, and time totals to stderr.
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Show the template lines around a failing reference.
+    #[arg(long, conflicts_with = "inputs")]
+    pub show_context: bool,
+
+    /// Maximum nesting of `| template` references.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_TEMPLATE_DEPTH)]
+    pub max_template_depth: usize,
+
+    /// Comment leader of `| section=NAME` marker lines.
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        default_value = DEFAULT_SECTION_MARKER_PREFIX,
+        conflicts_with = "inputs"
+    )]
+    pub section_marker_prefix: String,
+
+    /// Write STRING between consecutive included files; accepts \n, \r, \t,
+    /// and \\ escapes.
+    #[arg(
+        long,
+        value_name = "STRING",
+        allow_hyphen_values = true,
+        value_parser = parse_escapes
+    )]
+    pub separator: Option<String>,
+
+    /// Wrap every included file in begin and end marker lines of STYLE.
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub file_markers: Option<FileMarkers>,
+
+    /// Prefix every line of included content with STRING, such as '> '.
+    #[arg(
+        long,
+        value_name = "STRING",
+        allow_hyphen_values = true,
+        value_parser = parse_escapes
+    )]
+    pub indent: Option<String>,
+
+    /// Write STRING before all output; accepts the same escapes as --separator.
+    #[arg(
+        long,
+        value_name = "STRING",
+        allow_hyphen_values = true,
+        value_parser = parse_escapes,
+        conflicts_with = "validate"
+    )]
+    pub header: Option<String>,
+
+    /// Copy FILE before all output.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["header", "validate"]
+    )]
+    pub header_file: Option<PathBuf>,
+
+    /// Write STRING after all output; accepts the same escapes as --separator.
+    #[arg(
+        long,
+        value_name = "STRING",
+        allow_hyphen_values = true,
+        value_parser = parse_escapes,
+        conflicts_with = "validate"
+    )]
+    pub footer: Option<String>,
+
+    /// Copy FILE after all output.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["footer", "validate"]
+    )]
+    pub footer_file: Option<PathBuf>,
+
+    /// Print a shell completion script and exit.
+    #[cfg(feature = "completions")]
+    #[arg(long, value_name = "SHELL", exclusive = true)]
+    pub completions: Option<clap_complete::Shell>,
+}
+
+/// Sidecar listing of skipped entries, one `reason<TAB>path` line each,
+/// written as directory selection reports them.
+pub struct SkippedReport {
+    state: Mutex<ReportState>,
+}
+
+struct ReportState {
+    writer: BufWriter<File>,
+    error: Option<io::Error>,
+}
+
+impl SkippedReport {
+    /// Write the report to an already-created file.
+    #[must_use]
+    pub fn new(file: File) -> Self {
+        Self {
+            state: Mutex::new(ReportState {
+                writer: BufWriter::new(file),
+                error: None,
+            }),
+        }
+    }
+
+    /// Append one skipped entry. The first write failure is kept for
+    /// [`SkippedReport::finish`] and later entries are dropped.
+    pub fn record(&self, path: &Path, reason: SkipReason) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.error.is_none() {
+            let line = format!("{}\t{}\n", reason.as_str(), encode_path(path.as_os_str()));
+            if let Err(error) = state.writer.write_all(line.as_bytes()) {
+                state.error = Some(error);
+            }
+        }
+    }
+
+    /// Flush the report, returning the first failure.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from writing or flushing the report.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(error) = state.error.take() {
+            return Err(error);
+        }
+        state.writer.flush()
+    }
+}
+
+/// Write `manifest` as `sha256sum` lines, `HEX  PATH`, in path order. As in
+/// GNU coreutils, a path containing a backslash or line break has those
+/// escaped and its line prefixed by a backslash.
+///
+/// # Errors
+///
+/// Returns the first error from writing to `output`.
+pub fn write_manifest<W: io::Write>(
+    manifest: &BTreeMap<PathBuf, String>,
+    output: &mut W,
+) -> io::Result<()> {
+    for (path, digest) in manifest {
+        let path = path.as_os_str().as_encoded_bytes();
+        let escape = path
+            .iter()
+            .any(|byte| matches!(byte, b'\\' | b'\n' | b'\r'));
+        if escape {
+            output.write_all(b"\\")?;
+        }
+        output.write_all(digest.as_bytes())?;
+        output.write_all(b"  ")?;
+        if escape {
+            for &byte in path {
+                match byte {
+                    b'\\' => output.write_all(b"\\\\")?,
+                    b'\n' => output.write_all(b"\\n")?,
+                    b'\r' => output.write_all(b"\\r")?,
+                    byte => output.write_all(&[byte])?,
+                }
+            }
+        } else {
+            output.write_all(path)?;
+        }
+        output.write_all(b"\n")?;
+    }
+    output.flush()
+}
+
+fn parse_replacement(value: &str) -> Result<(String, String), String> {
+    let (key, replacement) = value
+        .split_once('=')
+        .ok_or_else(|| "expected KEY=VALUE".to_owned())?;
+    if !crate::parser::is_variable_name(key.as_bytes()) {
+        return Err(format!(
+            "variable name '{key}' must be an ASCII letter or underscore followed by letters, digits, or underscores"
+        ));
+    }
+    Ok((key.to_owned(), replacement.to_owned()))
+}
+
+fn parse_escapes(value: &str) -> Result<String, String> {
+    let mut separator = String::with_capacity(value.len());
+    let mut characters = value.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            separator.push(character);
+            continue;
+        }
+        separator.push(match characters.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('\\') => '\\',
+            Some(other) => return Err(format!("unknown escape '\\{other}'")),
+            None => return Err("trailing backslash".to_owned()),
+        });
+    }
+    Ok(separator)
+}
+
+fn parse_base_alias(value: &str) -> Result<(String, PathBuf), String> {
+    let (name, directory) = value
+        .split_once('=')
+        .ok_or_else(|| "expected NAME=DIR".to_owned())?;
+    if !crate::parser::is_variable_name(name.as_bytes()) {
+        return Err(format!(
+            "alias name '{name}' must be an ASCII letter or underscore followed by letters, digits, or underscores"
+        ));
+    }
+    if directory.is_empty() {
+        return Err(format!("alias '{name}' needs a directory"));
+    }
+    Ok((name.to_owned(), PathBuf::from(directory)))
+}
+
+/// Parse a whole number followed by one unit: `s`, `m`, `h`, `d`, or `w`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err("expected a number followed by s, m, h, d, or w".to_owned()),
+    };
+    count
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration count '{count}'"))
+}
+
+fn parse_size(value: &str) -> Result<u64, String> {
+    let split = value
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" => 1,
+        "k" => 1_000,
+        "m" => 1_000_000,
+        "g" => 1_000_000_000,
+        "ki" => 1 << 10,
+        "mi" => 1 << 20,
+        "gi" => 1 << 30,
+        _ => {
+            return Err(
+                "expected a number optionally followed by K, M, G, Ki, Mi, or Gi".to_owned(),
+            );
+        }
+    };
+    count
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size count '{count}'"))
 }
 
 fn validate_exclude(value: &str) -> Result<String, String> {
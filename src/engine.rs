@@ -1,6 +1,9 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{self, Cursor, Read, Write};
 use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use cap_std::ambient_authority;
 use cap_std::fs::Dir;
@@ -8,13 +11,33 @@ use clap::ValueEnum;
 use ignore::gitignore::GitignoreBuilder;
 use same_file::Handle;
 
+use crate::archive::archive_path;
+#[cfg(feature = "archive")]
+use crate::archive::{Archive, Member};
+use crate::builtin::Builtin;
 use crate::error::{Result, TextconError};
-use crate::parser::{self, ParsedReference, ReferenceProcessor};
-use crate::render::{is_markdown_path, write_body, write_markdown_record};
-use crate::selector::Selector;
+use crate::git::GitQuery;
+use crate::json;
+use crate::lines::{FilteredLines, SectionMarkers};
+use crate::parser::{
+    self, ParsedReference, ParsedVariable, Placeholder, ReferenceOptions, ReferenceProcessor,
+};
+use crate::render::{
+    CrlfLineEndings, IndentWriter, LfLineEndings, MarkedWriter, SkipBom, Source, TerminateLastLine,
+    TrimTrailingWhitespace, choose_fence, encode_path, is_markdown_path, sha256_hex, starts_binary,
+    write_body, write_markdown_record,
+};
+use crate::selector::{Child, Directory, Selector, Visitor};
+use crate::summary::{doc_summary, has_doc_syntax, read_prefix};
+use crate::tree::{DirectoryTree, MermaidWriter, SummaryWriter, TreeBuilder, TreeWriter};
 
 /// Rendering applied to direct inputs and inherited by template references.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum RenderMode {
     /// Emit H1-labelled input records and adapt Markdown document headings.
     #[default]
@@ -23,8 +46,126 @@ pub enum RenderMode {
     Raw,
 }
 
+/// Line endings of included bodies.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum LineEnding {
+    /// Keep every line ending as read.
+    #[default]
+    Preserve,
+    /// Convert CRLF and lone CR endings to LF.
+    Lf,
+    /// Convert CRLF, lone CR, and lone LF endings to CRLF.
+    Crlf,
+    /// CRLF on Windows and LF elsewhere.
+    Native,
+}
+
+impl LineEnding {
+    /// Whether bodies are converted to CRLF rather than LF, or `None` when
+    /// they are preserved.
+    const fn crlf(self) -> Option<bool> {
+        match self {
+            Self::Preserve => None,
+            Self::Lf => Some(false),
+            Self::Crlf => Some(true),
+            Self::Native => Some(cfg!(windows)),
+        }
+    }
+}
+
+/// Line style of the begin and end markers written around each included file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum FileMarkers {
+    /// `<!-- File: src/main.rs -->` and `<!-- End: src/main.rs -->`
+    HtmlComment,
+    /// `/* File: src/main.rs */` and `/* End: src/main.rs */`
+    CStyle,
+    /// `# --- File: src/main.rs ---` and `# --- End: src/main.rs ---`
+    Hash,
+    /// `=== File: src/main.rs ===` and `=== End: src/main.rs ===`
+    Banner,
+    /// `<document index="1">`, `<source>src/main.rs</source>`, and
+    /// `<document_content>` before the file, and `</document_content>` and
+    /// `</document>` after it, numbered from 1 in the order an engine writes
+    /// them. The encoded path needs no escaping, and file content is not
+    /// escaped.
+    Xml,
+}
+
+impl FileMarkers {
+    /// The marker lines written before the `index`th file.
+    pub(crate) fn begin(self, label: &str, index: u64) -> String {
+        match self.delimiters() {
+            Some((open, close)) => format!("{open}File: {label}{close}\n"),
+            None => format!(
+                "<document index=\"{index}\">\n<source>{label}</source>\n<document_content>\n"
+            ),
+        }
+    }
+
+    /// The marker lines written after a file.
+    pub(crate) fn end(self, label: &str) -> String {
+        match self.delimiters() {
+            Some((open, close)) => format!("{open}End: {label}{close}\n"),
+            None => "</document_content>\n</document>\n".to_owned(),
+        }
+    }
+
+    /// The text around `File: PATH` or `End: PATH` in a single-line style.
+    const fn delimiters(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Self::HtmlComment => Some(("<!-- ", " -->")),
+            Self::CStyle => Some(("/* ", " */")),
+            Self::Hash => Some(("# --- ", " ---")),
+            Self::Banner => Some(("=== ", " ===")),
+            Self::Xml => None,
+        }
+    }
+}
+
+/// Order in which a directory walk visits each directory's entries.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum WalkOrder {
+    /// Ascending byte order of entry names.
+    #[default]
+    Name,
+    /// Oldest modification time first.
+    ModifiedAsc,
+    /// Newest modification time first.
+    ModifiedDesc,
+    /// Smallest entry first.
+    SizeAsc,
+    /// Largest entry first.
+    SizeDesc,
+    /// The order the filesystem reports, which need not be reproducible.
+    None,
+}
+
+/// Compiled binary extensions that directory discovery skips by default.
+pub const DEFAULT_EXCLUDED_EXTENSIONS: &[&str] = &["o", "a", "so", "dylib", "dll", "exe", "pyc"];
+
 /// Directory discovery behavior shared by operands and directory references.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct SelectionOptions {
     /// Maximum descendant depth, where the requested root is depth zero.
     pub max_depth: Option<usize>,
@@ -34,6 +175,23 @@ pub struct SelectionOptions {
     pub use_gitignore: bool,
     /// Ordered gitignore-style selection overrides.
     pub excludes: Vec<String>,
+    /// Select only discovered files modified within this long before the
+    /// walk starts.
+    pub modified_within: Option<Duration>,
+    /// Select only discovered files with one of these extensions, written
+    /// with or without a leading dot. Empty selects every extension.
+    pub include_extensions: Vec<String>,
+    /// Never select discovered files with these extensions, unless they are
+    /// also listed in `include_extensions`.
+    pub exclude_extensions: Vec<String>,
+    /// Fail a directory walk that would select more than this many files.
+    pub max_files: Option<usize>,
+    /// Order of each directory's entries; modification time and size ties
+    /// are broken by name.
+    pub order: WalkOrder,
+    /// Select only discovered files that Git tracks, when the walked
+    /// directory is inside a repository and `git` can be run.
+    pub git_tracked_only: bool,
 }
 
 impl Default for SelectionOptions {
@@ -43,21 +201,132 @@ impl Default for SelectionOptions {
             hidden: false,
             use_gitignore: true,
             excludes: Vec::new(),
+            modified_within: None,
+            include_extensions: Vec::new(),
+            exclude_extensions: DEFAULT_EXCLUDED_EXTENSIONS
+                .iter()
+                .map(|&extension| extension.to_owned())
+                .collect(),
+            max_files: None,
+            order: WalkOrder::Name,
+            git_tracked_only: false,
         }
     }
 }
 
+/// Presentation of `| tree` reference listings.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct TreeOptions {
+    /// Annotate directories with the first line of their `README.md`.
+    pub readme_summaries: bool,
+    /// Collapse a directory with more selected entries than this into a
+    /// one-line count of its entries and dominant extensions.
+    pub summarize_breadth_over: Option<usize>,
+    /// Render at most this many `| tree` listings per template expansion,
+    /// replacing later ones with a one-line note.
+    pub max_trees: Option<usize>,
+    /// List discovered symlinks, which are otherwise skipped, as
+    /// `name -> target` without following them.
+    pub symlink_targets: bool,
+    /// Follow each listed file with its size, such as `(4.2 KB)`.
+    pub file_sizes: bool,
+}
+
 /// Validated configuration for a streaming engine.
+///
+/// With the `serde` feature, options serialize field by field and omitted
+/// fields take their [`Default`] values when deserialized.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct EngineOptions {
     /// Default renderer.
     pub render: RenderMode,
     /// Base directory for relative template references.
     pub base_dir: PathBuf,
-    /// Confine template references beneath `base_dir` using capability I/O.
+    /// Named directories that `| base=NAME` references resolve beneath.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
+    pub base_aliases: HashMap<String, PathBuf>,
+    /// Confine template references beneath `base_dir`, or beneath their
+    /// alias directory, using capability I/O.
     pub sandbox: bool,
+    /// Let `{{ @git:NAME }}` references, and `{{ @:hostname }}` where the
+    /// host name is not otherwise available, run programs during expansion.
+    /// Otherwise they fail with [`TextconError::CommandsDisabled`].
+    pub allow_commands: bool,
     /// Shared directory selection policy.
     pub selection: SelectionOptions,
+    /// Directory listing presentation.
+    pub tree: TreeOptions,
+    /// Maximum number of nested `| template` expansions.
+    pub max_template_depth: usize,
+    /// Append the first line of a Rust or Python file's leading documentation
+    /// to its heading or tree entry.
+    pub doc_summaries: bool,
+    /// Wrap Markdown-rendered bodies of non-Markdown files in fenced code
+    /// blocks tagged with a language inferred from the file name.
+    pub code_fences: bool,
+    /// Follow each labelled Markdown record with a `<!-- sha256: ... -->`
+    /// line holding the digest of the file's bytes.
+    pub checksums: bool,
+    /// Follow each labelled Markdown heading with a `<!-- modified: ... -->`
+    /// line holding the file's modification time in RFC 3339 UTC.
+    pub modified_times: bool,
+    /// Line endings of included bodies.
+    pub line_endings: LineEnding,
+    /// Drop trailing ASCII whitespace from every line of included bodies.
+    pub strip_trailing_whitespace: bool,
+    /// End every nonempty included body with an LF, adding one when missing.
+    pub ensure_trailing_newline: bool,
+    /// Fail with [`TextconError::BinaryFile`] instead of including a file
+    /// that holds a NUL byte near its start.
+    pub reject_binary: bool,
+    /// Fail with [`TextconError::TotalSizeExceeded`] rather than let the
+    /// engine write more than this many bytes in total, across all of its
+    /// render and expansion calls. `None` writes without limit.
+    pub max_total_output_bytes: Option<u64>,
+    /// Comment leader of `| section=NAME` markers, such as `//` in
+    /// `// === BEGIN NAME ===`.
+    pub section_marker_prefix: String,
+    /// Bytes written between consecutive files of one reference, and of the
+    /// inputs rendered by one call. Empty writes nothing.
+    pub separator: String,
+    /// Prefix for every line of included content, such as `> ` to quote it.
+    /// Empty indents nothing.
+    pub content_indent: String,
+    /// Wrap every included file in begin and end marker lines of this style.
+    pub file_markers: Option<FileMarkers>,
+    /// Literal values substituted for `{{ $name }}` template variables.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
+    pub variables: HashMap<String, String>,
+    /// Copy undefined variable placeholders verbatim instead of failing.
+    pub allow_undefined_variables: bool,
+    /// Write this text in place of a template reference whose path does not
+    /// exist, with `{path}` replaced by the reference's paths, instead of
+    /// failing. `None` fails.
+    pub missing_placeholder: Option<String>,
+    /// Attach the template lines around a failing reference to its
+    /// [`TextconError::Located`] error, including each one
+    /// [`EngineOptions::strict_references`] collects.
+    pub show_context: bool,
+    /// Before writing anything, resolve every reference of a template and
+    /// fail with [`TextconError::UnresolvedReferences`] listing each one that
+    /// cannot be resolved. The template is held in memory for the second
+    /// pass.
+    pub strict_references: bool,
+    /// Resolve the relative references of each file expanded by
+    /// [`Engine::expand_template_files`] beneath that file's directory
+    /// instead of `base_dir`.
+    pub template_relative: bool,
 }
 
 impl Default for EngineOptions {
@@ -65,25 +334,311 @@ impl Default for EngineOptions {
         Self {
             render: RenderMode::Markdown,
             base_dir: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            base_aliases: HashMap::new(),
             sandbox: false,
+            allow_commands: false,
             selection: SelectionOptions::default(),
+            tree: TreeOptions::default(),
+            max_template_depth: DEFAULT_MAX_TEMPLATE_DEPTH,
+            doc_summaries: false,
+            code_fences: false,
+            checksums: false,
+            modified_times: false,
+            line_endings: LineEnding::Preserve,
+            strip_trailing_whitespace: false,
+            ensure_trailing_newline: false,
+            reject_binary: false,
+            max_total_output_bytes: None,
+            section_marker_prefix: DEFAULT_SECTION_MARKER_PREFIX.to_owned(),
+            separator: String::new(),
+            content_indent: String::new(),
+            file_markers: None,
+            variables: HashMap::new(),
+            allow_undefined_variables: false,
+            missing_placeholder: None,
+            show_context: false,
+            strict_references: false,
+            template_relative: false,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl EngineOptions {
+    /// Parse options from a TOML document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TextconError::Config`] for invalid TOML or unknown values.
+    pub fn from_toml_str(source: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|error| TextconError::Config(error.to_string()))
+    }
+
+    /// Serialize options as a TOML document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TextconError::Config`] when a value has no TOML form, such
+    /// as a non-UTF-8 base directory.
+    pub fn to_toml_string(&self) -> Result<String> {
+        toml::to_string(self).map_err(|error| TextconError::Config(error.to_string()))
+    }
+}
+
+/// Default limit for nested `| template` expansion.
+pub const DEFAULT_MAX_TEMPLATE_DEPTH: usize = 5;
+
+/// Default comment leader of `| section=NAME` markers.
+pub const DEFAULT_SECTION_MARKER_PREFIX: &str = "//";
+
+/// Rendering progress, reported synchronously in output order. Reference
+/// events and [`ProgressEvent::Done`] come only from template expansion.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProgressEvent {
+    /// A reference is about to be rendered.
+    StartReference {
+        /// Reference targets as written, separated by `, ` for lists.
+        reference: String,
+    },
+    /// A reference finished rendering.
+    FinishReference {
+        /// Reference targets as written, separated by `, ` for lists.
+        reference: String,
+        /// Bytes written for the reference.
+        bytes: u64,
+    },
+    /// A referenced directory, or a selected descendant directory, is entered.
+    StartDirectory {
+        /// Logical directory path.
+        path: PathBuf,
+    },
+    /// The content of a file is about to be included.
+    ReadFile {
+        /// Logical file path.
+        path: PathBuf,
+        /// Size of the file in bytes, before any line filters.
+        bytes: u64,
+    },
+    /// The template was expanded completely.
+    Done {
+        /// Bytes written for the whole template, including literal text.
+        total_bytes: u64,
+    },
+}
+
+type ProgressCallback = Box<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Totals counted from the [`ProgressEvent`]s passed to
+/// [`ExpansionStats::record`], usually from an [`Engine::set_progress`]
+/// callback. One value may be shared by several expansions.
+///
+/// ```
+/// use std::sync::Arc;
+/// use textcon::{Engine, EngineOptions, ExpansionStats};
+///
+/// let mut engine = Engine::new(EngineOptions::default())?;
+/// let stats = Arc::new(ExpansionStats::default());
+/// let recorder = Arc::clone(&stats);
+/// engine.set_progress(move |event| recorder.record(&event));
+/// engine.expand_template(&mut &b"{{ @Cargo.toml }}"[..], &mut Vec::new())?;
+/// assert_eq!(stats.files_read(), 1);
+/// # Ok::<(), textcon::TextconError>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct ExpansionStats {
+    references: AtomicU64,
+    files_read: AtomicU64,
+    directories_walked: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl ExpansionStats {
+    /// Add `event` to the totals.
+    pub fn record(&self, event: &ProgressEvent) {
+        let (counter, amount) = match event {
+            ProgressEvent::FinishReference { .. } => (&self.references, 1),
+            ProgressEvent::StartDirectory { .. } => (&self.directories_walked, 1),
+            ProgressEvent::ReadFile { bytes, .. } => {
+                self.bytes_read.fetch_add(*bytes, Ordering::Relaxed);
+                (&self.files_read, 1)
+            }
+            ProgressEvent::Done { total_bytes } => (&self.bytes_written, *total_bytes),
+            ProgressEvent::StartReference { .. } => return,
+        };
+        counter.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    /// References rendered by template expansion.
+    #[must_use]
+    pub fn references(&self) -> u64 {
+        self.references.load(Ordering::Relaxed)
+    }
+
+    /// Files whose content was included.
+    #[must_use]
+    pub fn files_read(&self) -> u64 {
+        self.files_read.load(Ordering::Relaxed)
+    }
+
+    /// Directories entered, referenced or descendant.
+    #[must_use]
+    pub fn directories_walked(&self) -> u64 {
+        self.directories_walked.load(Ordering::Relaxed)
+    }
+
+    /// Combined size of the included files.
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written by completed template expansions, literal text included.
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+}
+
+/// A non-fatal condition noticed while rendering, reported synchronously.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// An included file holds a NUL byte near its start, so it is probably
+    /// binary. Its bytes are still included unchanged unless
+    /// [`EngineOptions::reject_binary`] is set.
+    BinaryContent {
+        /// Logical file path.
+        path: PathBuf,
+    },
+    /// A referenced directory selected no files.
+    EmptyDirectory {
+        /// Logical directory path.
+        path: PathBuf,
+    },
+}
+
+type WarningCallback = Box<dyn Fn(Warning) + Send + Sync>;
+
+/// Why directory discovery did not select an entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    /// A dot-prefixed name without `hidden`.
+    Hidden,
+    /// A discovered symlink, which is never followed.
+    Symlink,
+    /// Neither a regular file nor a directory.
+    SpecialFile,
+    /// Matched by a `.gitignore` rule.
+    Gitignored,
+    /// Matched by an exclusion from [`SelectionOptions::excludes`].
+    Excluded,
+    /// Beyond [`SelectionOptions::max_depth`].
+    Depth,
+    /// Older than [`SelectionOptions::modified_within`].
+    Unmodified,
+    /// Outside [`SelectionOptions::include_extensions`], or in
+    /// [`SelectionOptions::exclude_extensions`].
+    Extension,
+    /// Not tracked by Git, with [`SelectionOptions::git_tracked_only`].
+    Untracked,
+    /// A zip archive member whose content looks binary.
+    Binary,
+    /// The file currently receiving output.
+    Output,
+}
+
+impl SkipReason {
+    /// Stable lowercase name of the reason.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Hidden => "hidden",
+            Self::Symlink => "symlink",
+            Self::SpecialFile => "special",
+            Self::Gitignored => "gitignored",
+            Self::Excluded => "excluded",
+            Self::Depth => "depth",
+            Self::Unmodified => "unmodified",
+            Self::Extension => "extension",
+            Self::Untracked => "untracked",
+            Self::Binary => "binary",
+            Self::Output => "output",
         }
     }
 }
 
+/// Receives the logical path of each discovered entry that selection leaves
+/// out, and why, as registered with [`Engine::set_skipped`].
+pub type SkipCallback = dyn Fn(&Path, SkipReason) + Send + Sync;
+
+/// Serialize a map in key order so generated documents are reproducible.
+#[cfg(feature = "serde")]
+fn serialize_sorted<V: serde::Serialize + Ord, S: serde::Serializer>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_unstable();
+    serializer.collect_map(entries)
+}
+
 struct Sandbox {
     configured_root: PathBuf,
     canonical_root: PathBuf,
     directory: Dir,
 }
 
+/// A directory that relative references resolve beneath: the base directory
+/// or a named alias, with its sandbox when sandboxing is enabled.
+struct Root {
+    base_dir: PathBuf,
+    /// Prefix of target labels: the alias directory as configured, or empty
+    /// for the base directory.
+    label: PathBuf,
+    sandbox: Option<Sandbox>,
+}
+
+impl Root {
+    fn open(base_dir: PathBuf, label: PathBuf, sandboxed: bool) -> Result<Self> {
+        let sandbox = if sandboxed {
+            let canonical_root = base_dir
+                .canonicalize()
+                .map_err(|error| TextconError::path_io("open sandbox root", &base_dir, error))?;
+            let directory =
+                Dir::open_ambient_dir(&canonical_root, ambient_authority()).map_err(|error| {
+                    TextconError::path_io("open sandbox root", &canonical_root, error)
+                })?;
+            Some(Sandbox {
+                configured_root: base_dir.clone(),
+                canonical_root,
+                directory,
+            })
+        } else {
+            None
+        };
+        Ok(Self {
+            base_dir,
+            label,
+            sandbox,
+        })
+    }
+}
+
 /// Reusable, payload-streaming text composer and template expander.
 pub struct Engine {
     options: EngineOptions,
     current_dir: PathBuf,
-    base_dir: PathBuf,
-    sandbox: Option<Sandbox>,
-    output_identity: Option<Handle>,
+    root: Root,
+    aliases: HashMap<String, Root>,
+    output_identities: Vec<Handle>,
+    /// Bytes written by all calls, for [`EngineOptions::max_total_output_bytes`].
+    written: AtomicU64,
+    /// Files wrapped in [`EngineOptions::file_markers`] so far.
+    marked: AtomicU64,
+    progress: Option<ProgressCallback>,
+    skipped: Option<Box<SkipCallback>>,
+    warning: Option<WarningCallback>,
 }
 
 impl Engine {
@@ -98,30 +653,35 @@ impl Engine {
             .map_err(|error| TextconError::path_io("read current directory", ".", error))?;
         let base_dir = absolute_from(&current_dir, &options.base_dir);
         validate_excludes(&base_dir, &options.selection.excludes)?;
+        validate_variables(&options.variables)?;
 
-        let sandbox = if options.sandbox {
-            let canonical_root = base_dir
-                .canonicalize()
-                .map_err(|error| TextconError::path_io("open sandbox root", &base_dir, error))?;
-            let directory =
-                Dir::open_ambient_dir(&canonical_root, ambient_authority()).map_err(|error| {
-                    TextconError::path_io("open sandbox root", &canonical_root, error)
-                })?;
-            Some(Sandbox {
-                configured_root: base_dir.clone(),
-                canonical_root,
-                directory,
+        validate_base_aliases(&options.base_aliases)?;
+
+        let root = Root::open(base_dir, PathBuf::new(), options.sandbox)?;
+        let aliases = options
+            .base_aliases
+            .iter()
+            .map(|(name, directory)| {
+                Root::open(
+                    absolute_from(&current_dir, directory),
+                    clean_logical_path(directory),
+                    options.sandbox,
+                )
+                .map(|root| (name.clone(), root))
             })
-        } else {
-            None
-        };
+            .collect::<Result<_>>()?;
 
         Ok(Self {
             options,
             current_dir,
-            base_dir,
-            sandbox,
-            output_identity: None,
+            root,
+            aliases,
+            output_identities: Vec::new(),
+            written: AtomicU64::new(0),
+            marked: AtomicU64::new(0),
+            progress: None,
+            skipped: None,
+            warning: None,
         })
     }
 
@@ -136,8 +696,54 @@ impl Engine {
         P: AsRef<Path>,
         W: Write,
     {
+        self.render_paths(inputs, None, output)
+    }
+
+    /// Render paths in argument order like [`Engine::render_inputs`], reading
+    /// each `-` operand from `stdin` as [`Engine::render_reader`] does, with
+    /// separators between all of them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error on discovery, input, rendering, or output failure.
+    pub fn render_operands<I, P, R, W>(
+        &self,
+        inputs: I,
+        stdin: &mut R,
+        output: &mut W,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        R: Read,
+        W: Write,
+    {
+        self.render_paths(inputs, Some(stdin), output)
+    }
+
+    fn render_paths<I, P, W>(
+        &self,
+        inputs: I,
+        mut stdin: Option<&mut dyn Read>,
+        output: &mut W,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        W: Write,
+    {
+        let mut limited = self.limited(output);
+        let mut output = IndentWriter::new(&mut limited, &self.options.content_indent);
+        let mut separator = self.separator();
         for input in inputs {
-            self.render_input_path(input.as_ref(), output)?;
+            let input = input.as_ref();
+            let rendered = match stdin.as_mut() {
+                Some(stdin) if input == Path::new("-") => separator
+                    .write(&mut output)
+                    .and_then(|()| self.render_marked_reader(input, stdin, &mut output)),
+                _ => self.render_input_path(input, &mut separator, &mut output),
+            };
+            rendered.map_err(limit_error)?;
         }
         Ok(())
     }
@@ -153,33 +759,636 @@ impl Engine {
         input: &mut R,
         output: &mut W,
     ) -> Result<()> {
+        let mut limited = self.limited(output);
+        let mut indented = IndentWriter::new(&mut limited, &self.options.content_indent);
+        self.render_marked_reader(logical_name, input, &mut indented)
+            .map_err(limit_error)
+    }
+
+    /// Write the begin marker, if any, of the next included file.
+    fn begin_marked<'a, W: Write>(
+        &self,
+        logical_path: &Path,
+        output: &'a mut W,
+    ) -> Result<MarkedWriter<'a, W>> {
+        let index = match self.options.file_markers {
+            Some(_) => self.marked.fetch_add(1, Ordering::Relaxed) + 1,
+            None => 0,
+        };
+        MarkedWriter::begin(self.options.file_markers, logical_path, index, output)
+    }
+
+    fn render_marked_reader<R: Read, W: Write>(
+        &self,
+        logical_name: &Path,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<()> {
+        let mut output = self.begin_marked(logical_name, output)?;
         match self.options.render {
-            RenderMode::Markdown => {
-                write_markdown_record(logical_name, input, is_markdown_path(logical_name), output)
-            }
-            RenderMode::Raw => write_body(logical_name, input, false, output),
+            RenderMode::Markdown => write_markdown_record(
+                logical_name,
+                &mut self.normalized(SkipBom::new(input)),
+                is_markdown_path(logical_name),
+                None,
+                None,
+                None,
+                self.options.checksums,
+                &mut output,
+            )?,
+            RenderMode::Raw => write_body(
+                logical_name,
+                &mut self.normalized(input),
+                false,
+                None,
+                &mut output,
+            )?,
         }
+        output.finish()
     }
 
-    /// Expand references from a template stream in one pass.
+    /// Expand references and variables from a template stream in one pass.
     ///
     /// # Errors
     ///
-    /// Returns an error for malformed references, denied paths, filesystem
-    /// failures, or output failures. Previously written bytes remain visible.
+    /// Returns an error for malformed references, undefined variables, denied
+    /// paths, filesystem failures, or output failures. Previously written
+    /// bytes remain visible.
     pub fn expand_template<R: Read, W: Write>(&self, input: &mut R, output: &mut W) -> Result<()> {
-        parser::expand(input, output, "template input", |reference, writer| {
-            self.render_reference(reference, writer)
+        self.expand(input, output, &HashMap::new(), Scope::top())
+    }
+
+    /// Expand template files in order into one stream, writing
+    /// [`EngineOptions::separator`] between them. Each template is expanded
+    /// independently, as by [`Engine::expand_template`], while the opened base
+    /// directory and aliases are shared. Relative template paths resolve from
+    /// the working directory captured by [`Engine::new`].
+    ///
+    /// With [`EngineOptions::template_relative`], relative references resolve
+    /// beneath each template's directory and are labelled relative to
+    /// `base_dir`. A template outside `base_dir` resolves them by absolute
+    /// path, which [`EngineOptions::sandbox`] denies.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TextconError::Input`] for a template that cannot be read,
+    /// and otherwise the first error [`Engine::expand_template`] returns;
+    /// later templates are not expanded.
+    pub fn expand_template_files<I, P, W>(&self, templates: I, output: &mut W) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        W: Write,
+    {
+        let mut separator = self.separator();
+        self.each_template_file(templates, |file, scope| {
+            separator.write(output)?;
+            self.expand(file, output, &HashMap::new(), scope)
         })
     }
 
+    /// Open each template file in order and hand it to `each` with the scope
+    /// its references resolve in, per [`EngineOptions::template_relative`].
+    fn each_template_file<I, P>(
+        &self,
+        templates: I,
+        mut each: impl FnMut(&mut io::BufReader<File>, Scope<'_>) -> Result<()>,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        for template in templates {
+            let template = template.as_ref();
+            let path = absolute_from(&self.current_dir, template);
+            let file = File::open(&path).map_err(|source| TextconError::Input {
+                name: template.display().to_string(),
+                source,
+            })?;
+            let directory = if self.options.template_relative {
+                let parent = path.parent().unwrap_or_else(|| Path::new(""));
+                parent
+                    .strip_prefix(&self.root.base_dir)
+                    .unwrap_or(parent)
+                    .to_path_buf()
+            } else {
+                PathBuf::new()
+            };
+            let scope = Scope {
+                base: None,
+                directory: &directory,
+            };
+            each(&mut io::BufReader::new(file), scope)?;
+        }
+        Ok(())
+    }
+
+    /// Expand a template like [`Engine::expand_template`], substituting
+    /// `variables` for this call only.
+    ///
+    /// A name present in `variables` takes precedence over
+    /// [`EngineOptions::variables`]; other names fall back to the engine's
+    /// values, so one engine can expand the same template for several callers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TextconError::Config`] for an invalid variable name, and
+    /// otherwise the errors [`Engine::expand_template`] returns.
+    pub fn expand_template_with_variables<R: Read, W: Write>(
+        &self,
+        input: &mut R,
+        output: &mut W,
+        variables: &HashMap<String, String>,
+    ) -> Result<()> {
+        validate_variables(variables)?;
+        self.expand(input, output, variables, Scope::top())
+    }
+
+    /// Render one reference, as from [`parse_reference`], exactly as it would
+    /// render alone in a template: a file as its content, a directory as the
+    /// bodies of its selected files, `| markdown` as labelled records,
+    /// `| tree` as the listing only, and so on for every processor and option.
+    ///
+    /// Relative paths resolve from the engine's base directory, and
+    /// [`EngineOptions::max_total_output_bytes`] counts these bytes too.
+    ///
+    /// [`parse_reference`]: crate::parse_reference
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors [`Engine::expand_template`] returns for the same
+    /// reference.
+    pub fn render_reference<W: Write>(
+        &self,
+        reference: &ParsedReference,
+        output: &mut W,
+    ) -> Result<()> {
+        let variables = HashMap::new();
+        let mut expansion = Expansion {
+            variables: &variables,
+            stack: Vec::new(),
+            trees: 0,
+        };
+        let mut limited = self.limited(output);
+        self.render_scoped_reference(reference, Scope::top(), &mut expansion, &mut limited)
+            .map_err(limit_error)
+    }
+
+    /// Select the files and directories beneath `path`, relative to the base
+    /// directory, as `{{ @path | tree }}` would list them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when `path` cannot be resolved or a selected entry
+    /// cannot be inspected.
+    pub fn directory_tree(&self, path: impl AsRef<Path>) -> Result<DirectoryTree> {
+        let target = Target::new(None, &self.root, path.as_ref().to_path_buf());
+        let mut builder = TreeBuilder::default();
+        self.visit_reference(
+            &target,
+            &ReferenceOptions::default(),
+            &mut self.reporting(&mut builder),
+        )?;
+        Ok(builder.finish())
+    }
+
+    /// Expand a template, first checking every reference when
+    /// [`EngineOptions::strict_references`] is set.
+    fn expand<R: Read, W: Write>(
+        &self,
+        input: &mut R,
+        output: &mut W,
+        variables: &HashMap<String, String>,
+        scope: Scope<'_>,
+    ) -> Result<()> {
+        if !self.options.strict_references {
+            return self.expand_stream(input, output, variables, scope);
+        }
+        let mut template = Vec::new();
+        input
+            .read_to_end(&mut template)
+            .map_err(|source| TextconError::Input {
+                name: "template input".to_owned(),
+                source,
+            })?;
+        let (total, errors) = self.resolve_all(&template, variables, scope)?;
+        if !errors.is_empty() {
+            return Err(TextconError::UnresolvedReferences { total, errors });
+        }
+        self.expand_stream(&mut template.as_slice(), output, variables, scope)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "expand_template",
+            skip_all,
+            fields(template_len = tracing::field::Empty)
+        )
+    )]
+    fn expand_stream<R: Read, W: Write>(
+        &self,
+        input: &mut R,
+        output: &mut W,
+        variables: &HashMap<String, String>,
+        scope: Scope<'_>,
+    ) -> Result<()> {
+        let mut limited = self.limited(output);
+        let mut counted = CountingWriter {
+            inner: &mut limited,
+            count: 0,
+        };
+        let mut expansion = Expansion {
+            variables,
+            stack: Vec::new(),
+            trees: 0,
+        };
+        let template_len = parser::expand(
+            input,
+            &mut counted,
+            "template input",
+            self.options.show_context,
+            |placeholder, writer| match placeholder {
+                Placeholder::Reference(reference) => {
+                    let Some(progress) = &self.progress else {
+                        return self.render_scoped_reference(
+                            &reference,
+                            scope,
+                            &mut expansion,
+                            writer,
+                        );
+                    };
+                    let name = reference
+                        .paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    progress(ProgressEvent::StartReference {
+                        reference: name.clone(),
+                    });
+                    let start = writer.count;
+                    self.render_scoped_reference(&reference, scope, &mut expansion, writer)?;
+                    progress(ProgressEvent::FinishReference {
+                        reference: name,
+                        bytes: writer.count - start,
+                    });
+                    Ok(())
+                }
+                Placeholder::Variable(variable) => {
+                    self.render_variable(&variable, expansion.variables, writer)
+                }
+            },
+        )
+        .map_err(limit_error)?;
+        if let Some(progress) = &self.progress {
+            progress(ProgressEvent::Done {
+                total_bytes: counted.count,
+            });
+        }
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("template_len", template_len);
+        #[cfg(not(feature = "tracing"))]
+        let _ = template_len;
+        Ok(())
+    }
+
+    /// Expand each template reference into its own file in `directory`,
+    /// returning the written paths in template order.
+    ///
+    /// A file is named after the reference's paths, with `/` replaced by `_`
+    /// and list members joined by `,`, so `{{ @src/main.rs }}` is written to
+    /// `src_main.rs`. Literal template text is discarded; variables are still
+    /// checked. `directory` is created if needed. Keep it outside referenced
+    /// directories, since files written earlier would otherwise be selected.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error expansion would return, an error when two
+    /// references share a file name, or an error creating or writing a file.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn expand_template_to_directory<R: Read>(
+        &self,
+        input: &mut R,
+        directory: &Path,
+    ) -> Result<Vec<PathBuf>> {
+        let directory = absolute_from(&self.current_dir, directory);
+        fs::create_dir_all(&directory)
+            .map_err(|error| TextconError::path_io("create output directory", &directory, error))?;
+        let variables = HashMap::new();
+        let mut expansion = Expansion {
+            variables: &variables,
+            stack: Vec::new(),
+            trees: 0,
+        };
+        let mut written = Vec::new();
+        parser::expand(
+            input,
+            &mut io::sink(),
+            "template input",
+            self.options.show_context,
+            |placeholder, writer| match placeholder {
+                Placeholder::Reference(reference) => {
+                    let path = directory.join(split_file_name(&reference));
+                    if written.contains(&path) {
+                        return Err(TextconError::Config(format!(
+                            "two references are written to {}",
+                            path.display()
+                        ))
+                        .located(&reference));
+                    }
+                    let file = File::create(&path).map_err(|error| {
+                        TextconError::path_io("create", &path, error).located(&reference)
+                    })?;
+                    let mut output = io::BufWriter::new(file);
+                    self.render_scoped_reference(
+                        &reference,
+                        Scope::top(),
+                        &mut expansion,
+                        &mut self.limited(&mut output),
+                    )?;
+                    output.flush().map_err(TextconError::output)?;
+                    written.push(path);
+                    Ok(())
+                }
+                Placeholder::Variable(variable) => {
+                    self.render_variable(&variable, &variables, writer)
+                }
+            },
+        )
+        .map_err(limit_error)?;
+        Ok(written)
+    }
+
+    /// Check a template without reading reference content or writing output.
+    ///
+    /// Every reference is resolved and every selected file is opened, so
+    /// syntax errors, undefined variables, sandbox denials, and missing or
+    /// unreadable paths are reported exactly as expansion would report them.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error expansion of the same template would return.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn validate_template<R: Read>(&self, input: &mut R) -> Result<()> {
+        self.validate(input, Scope::top())
+    }
+
+    /// Validate template files in order like [`Engine::validate_template`],
+    /// resolving references as [`Engine::expand_template_files`] would.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TextconError::Input`] for a template that cannot be read,
+    /// and otherwise the first error [`Engine::validate_template`] returns.
+    pub fn validate_template_files<I, P>(&self, templates: I) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.each_template_file(templates, |file, scope| self.validate(file, scope))
+    }
+
+    fn validate<R: Read>(&self, input: &mut R, scope: Scope<'_>) -> Result<()> {
+        parser::expand(
+            input,
+            &mut io::sink(),
+            "template input",
+            self.options.show_context,
+            |placeholder, writer| match placeholder {
+                Placeholder::Reference(reference) => self
+                    .validate_reference(&reference, scope)
+                    .map_err(|error| error.located(&reference)),
+                Placeholder::Variable(variable) => {
+                    self.render_variable(&variable, &HashMap::new(), writer)
+                }
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Resolve every reference of a template like
+    /// [`Engine::validate_template`], but collect each reference's failure
+    /// instead of stopping at the first.
+    ///
+    /// Returns the failures in template order, each wrapped in
+    /// [`TextconError::Located`]. An empty list means every reference
+    /// resolves.
+    ///
+    /// # Errors
+    ///
+    /// Returns syntax errors and undefined variables, which end the check,
+    /// or [`TextconError::Input`] when the template cannot be read.
+    pub fn unresolved_references<R: Read>(&self, input: &mut R) -> Result<Vec<TextconError>> {
+        let mut template = Vec::new();
+        input
+            .read_to_end(&mut template)
+            .map_err(|source| TextconError::Input {
+                name: "template input".to_owned(),
+                source,
+            })?;
+        self.resolve_all(&template, &HashMap::new(), Scope::top())
+            .map(|(_, errors)| errors)
+    }
+
+    /// Count the references of a template and collect those that fail to
+    /// resolve within `scope`, with the template lines around each under
+    /// [`EngineOptions::show_context`].
+    fn resolve_all(
+        &self,
+        template: &[u8],
+        variables: &HashMap<String, String>,
+        scope: Scope<'_>,
+    ) -> Result<(usize, Vec<TextconError>)> {
+        let mut total = 0;
+        let mut errors = Vec::new();
+        parser::expand(
+            &mut &template[..],
+            &mut io::sink(),
+            "template input",
+            self.options.show_context,
+            |placeholder, writer| match placeholder {
+                Placeholder::Reference(reference) => {
+                    total += 1;
+                    if let Err(error) = self.validate_reference(&reference, scope) {
+                        let error = error.located(&reference);
+                        errors.push(if self.options.show_context {
+                            error.with_context(parser::context_snippet(template, reference.line))
+                        } else {
+                            error
+                        });
+                    }
+                    Ok(())
+                }
+                Placeholder::Variable(variable) => {
+                    self.render_variable(&variable, variables, writer)
+                }
+            },
+        )?;
+        Ok((total, errors))
+    }
+
+    /// Resolve `reference` and open each selected file without reading it. A
+    /// missing path passes when it would be replaced by a placeholder.
+    fn validate_reference(&self, reference: &ParsedReference, scope: Scope<'_>) -> Result<()> {
+        for target in self.file_targets(reference, scope)? {
+            match self.visit_reference(&target, &reference.options, &mut |_: &Path, _: File| Ok(()))
+            {
+                Err(error)
+                    if self.options.missing_placeholder.is_some()
+                        && error.is_missing_reference() => {}
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Map every file a template references, including selected directory
+    /// descendants, to the SHA-256 digest of its bytes without writing output.
+    ///
+    /// Keys are logical paths as they would be labelled, so a file referenced
+    /// twice has one entry. Files included by `| template` references are
+    /// hashed as files; their own references are not followed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error expansion of the same template would return,
+    /// or a read failure while hashing.
+    pub fn checksum_manifest<R: Read>(&self, input: &mut R) -> Result<BTreeMap<PathBuf, String>> {
+        self.checksums(input, Scope::top(), |_, logical| logical.to_path_buf())
+    }
+
+    /// Map every file the template files reference to its digest like
+    /// [`Engine::checksum_manifest`], resolving references as
+    /// [`Engine::expand_template_files`] would and keying each file instead
+    /// by the path it is opened at: relative to the working directory
+    /// captured by [`Engine::new`] when beneath it, and otherwise absolute. A
+    /// `sha256sum` manifest of these paths verifies from that directory
+    /// whatever [`EngineOptions::base_dir`] is.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TextconError::Input`] for a template that cannot be read,
+    /// and otherwise the errors [`Engine::checksum_manifest`] returns.
+    pub fn checksum_manifest_paths<I, P>(&self, templates: I) -> Result<BTreeMap<PathBuf, String>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut manifest = BTreeMap::new();
+        self.each_template_file(templates, |file, scope| {
+            manifest.append(&mut self.checksums(file, scope, |target, logical| {
+                let mut physical = target.root.base_dir.join(&target.path);
+                let beneath = logical.strip_prefix(&target.logical).unwrap_or(logical);
+                if !beneath.as_os_str().is_empty() {
+                    physical.push(beneath);
+                }
+                physical
+                    .strip_prefix(&self.current_dir)
+                    .map_or_else(|_| physical.clone(), Path::to_path_buf)
+            })?);
+            Ok(())
+        })?;
+        Ok(manifest)
+    }
+
+    fn checksums<R: Read>(
+        &self,
+        input: &mut R,
+        scope: Scope<'_>,
+        key: impl Fn(&Target<'_>, &Path) -> PathBuf,
+    ) -> Result<BTreeMap<PathBuf, String>> {
+        let mut manifest = BTreeMap::new();
+        parser::expand(
+            input,
+            &mut io::sink(),
+            "template input",
+            self.options.show_context,
+            |placeholder, writer| match placeholder {
+                Placeholder::Reference(reference) => {
+                    let mut hash = || {
+                        for target in self.file_targets(&reference, scope)? {
+                            self.visit_reference(
+                                &target,
+                                &reference.options,
+                                &mut |logical: &Path, mut file: File| {
+                                    let digest = sha256_hex(&mut file).map_err(|error| {
+                                        TextconError::path_io("read", logical, error)
+                                    })?;
+                                    manifest.insert(key(&target, logical), digest);
+                                    Ok(())
+                                },
+                            )?;
+                        }
+                        Ok(())
+                    };
+                    hash().map_err(|error: TextconError| error.located(&reference))
+                }
+                Placeholder::Variable(variable) => {
+                    self.render_variable(&variable, &HashMap::new(), writer)
+                }
+            },
+        )?;
+        Ok(manifest)
+    }
+
+    /// Wrap `output` so that it counts toward, and is stopped by,
+    /// [`EngineOptions::max_total_output_bytes`].
+    const fn limited<'a, W: Write>(&'a self, output: &'a mut W) -> LimitedWriter<'a, W> {
+        LimitedWriter {
+            inner: output,
+            limit: self.options.max_total_output_bytes,
+            written: &self.written,
+        }
+    }
+
+    /// Report template expansion progress to `callback`, synchronously and
+    /// in output order, replacing any previous callback.
+    pub fn set_progress<F>(&mut self, callback: F)
+    where
+        F: Fn(ProgressEvent) + Send + Sync + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+    }
+
+    /// Report each discovered entry that directory selection leaves out,
+    /// with its logical path, replacing any previous callback.
+    pub fn set_skipped<F>(&mut self, callback: F)
+    where
+        F: Fn(&Path, SkipReason) + Send + Sync + 'static,
+    {
+        self.skipped = Some(Box::new(callback));
+    }
+
+    /// Report non-fatal conditions such as binary-looking files to
+    /// `callback`, replacing any previous callback. Rendering continues
+    /// unchanged.
+    pub fn set_warning<F>(&mut self, callback: F)
+    where
+        F: Fn(Warning) + Send + Sync + 'static,
+    {
+        self.warning = Some(Box::new(callback));
+    }
+
     /// Record the process stdout identity so recursive discovery cannot ingest
     /// a regular file currently receiving redirected output.
     pub fn protect_stdout(&mut self) {
-        self.output_identity = Handle::stdout().ok();
+        self.output_identities.extend(Handle::stdout().ok());
     }
 
-    fn render_input_path<W: Write>(&self, input: &Path, output: &mut W) -> Result<()> {
+    /// Protect another file that receives output during processing, such as a
+    /// report, in the same way as [`Engine::protect_stdout`].
+    pub fn protect_output_file(&mut self, file: &File) {
+        self.output_identities
+            .extend(file.try_clone().and_then(Handle::from_file).ok());
+    }
+
+    fn render_input_path<W: Write>(
+        &self,
+        input: &Path,
+        separator: &mut Separator<'_>,
+        output: &mut W,
+    ) -> Result<()> {
         let physical = absolute_from(&self.current_dir, input);
         let metadata = fs::metadata(&physical)
             .map_err(|error| TextconError::path_io("inspect input", &physical, error))?;
@@ -188,38 +1397,459 @@ impl Engine {
             let file = File::open(&physical)
                 .map_err(|error| TextconError::path_io("open input", &physical, error))?;
             self.reject_output_file(&file, &physical)?;
-            return Self::render_file(logical.as_path(), file, self.options.render, true, output);
+            separator.write(output)?;
+            return self.render_file(
+                logical.as_path(),
+                file,
+                self.options.render,
+                true,
+                &ReferenceOptions::default(),
+                output,
+            );
         }
         if metadata.is_dir() {
             let (selected_root, policy_root) =
                 ambient_selection_roots(&physical, &self.current_dir)?;
-            let selector = Selector::new(&self.options.selection, self.output_identity.as_ref());
+            let selector = self.selector(false);
             return selector.select_ambient(
                 &selected_root,
                 &logical,
                 &policy_root,
-                &mut |path, file| Self::render_file(path, file, self.options.render, true, output),
+                &mut self.reporting(&mut |path: &Path, file| {
+                    separator.write(output)?;
+                    self.render_file(
+                        path,
+                        file,
+                        self.options.render,
+                        true,
+                        &ReferenceOptions::default(),
+                        output,
+                    )
+                }),
             );
         }
         Err(TextconError::UnsupportedFileType { path: physical })
     }
 
-    fn render_reference<W: Write>(&self, reference: ParsedReference, output: &mut W) -> Result<()> {
+    /// Write the value of `variable`, looking in the per-call `variables`
+    /// before the engine's own.
+    fn render_variable<W: Write>(
+        &self,
+        variable: &ParsedVariable,
+        variables: &HashMap<String, String>,
+        output: &mut W,
+    ) -> Result<()> {
+        let value = match variables
+            .get(&variable.name)
+            .or_else(|| self.options.variables.get(&variable.name))
+        {
+            Some(value) => value.as_bytes(),
+            None if self.options.allow_undefined_variables => &variable.source,
+            None => {
+                return Err(TextconError::UndefinedVariable {
+                    name: variable.name.clone(),
+                    offset: variable.offset,
+                });
+            }
+        };
+        output.write_all(value).map_err(TextconError::output)
+    }
+
+    /// Render a reference whose relative targets resolve beneath `scope`, the
+    /// directory of the enclosing `| template` file or empty at top level.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "render_reference",
+            skip_all,
+            fields(offset = reference.offset, processor = ?reference.processor)
+        )
+    )]
+    fn render_scoped_reference<W: Write>(
+        &self,
+        reference: &ParsedReference,
+        scope: Scope<'_>,
+        expansion: &mut Expansion<'_>,
+        output: &mut W,
+    ) -> Result<()> {
+        // Nested references are already inside their template's indentation,
+        // and their errors are located at the top-level `| template` reference.
+        if !expansion.stack.is_empty() {
+            let result = self.render_targets(reference, scope, expansion, output);
+            return self.replace_missing(result, reference, output);
+        }
+        let result = if self.options.content_indent.is_empty() {
+            let result = self.render_targets(reference, scope, expansion, output);
+            self.replace_missing(result, reference, output)
+        } else {
+            let mut indented =
+                IndentWriter::new(output as &mut dyn Write, &self.options.content_indent);
+            let result = self.render_targets(reference, scope, expansion, &mut indented);
+            self.replace_missing(result, reference, &mut indented)
+        };
+        result.map_err(|error| error.located(reference))
+    }
+
+    /// Write [`EngineOptions::missing_placeholder`] in place of a reference
+    /// that failed because its path does not exist.
+    fn replace_missing<W: Write>(
+        &self,
+        result: Result<()>,
+        reference: &ParsedReference,
+        output: &mut W,
+    ) -> Result<()> {
+        match (result, &self.options.missing_placeholder) {
+            (Err(error), Some(placeholder)) if error.is_missing_reference() => {
+                let paths = reference
+                    .paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                output
+                    .write_all(placeholder.replace("{path}", &paths).as_bytes())
+                    .map_err(TextconError::output)
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn render_targets<W: Write>(
+        &self,
+        reference: &ParsedReference,
+        scope: Scope<'_>,
+        expansion: &mut Expansion<'_>,
+        output: &mut W,
+    ) -> Result<()> {
+        if let Some(query) = GitQuery::from_reference(reference) {
+            return self.render_git(&query, reference, scope, output);
+        }
+        if let Some(builtin) = Builtin::from_reference(reference) {
+            return self.render_builtin(&builtin, reference, output);
+        }
+        if let Some(archive) = archive_path(reference) {
+            return self.render_archive(archive, reference, scope, expansion, output);
+        }
+        let targets = self.targets(reference, scope)?;
         let render = match reference.processor {
+            ReferenceProcessor::Template => {
+                for target in &targets {
+                    let mut templates = TemplateWriter {
+                        engine: self,
+                        base: target.base,
+                        expansion,
+                        output,
+                    };
+                    self.visit_reference(
+                        target,
+                        &reference.options,
+                        &mut self.reporting(&mut templates),
+                    )?;
+                }
+                return Ok(());
+            }
+            ReferenceProcessor::Tree => {
+                for target in &targets {
+                    if self
+                        .options
+                        .tree
+                        .max_trees
+                        .is_some_and(|limit| expansion.trees >= limit)
+                    {
+                        writeln!(
+                            output,
+                            "<!-- tree of {} omitted (max_trees reached) -->",
+                            encode_path(target.logical.as_os_str())
+                        )
+                        .map_err(TextconError::output)?;
+                        continue;
+                    }
+                    expansion.trees += 1;
+                    let mut tree =
+                        TreeWriter::new(&self.options.tree, self.options.doc_summaries, output);
+                    self.visit_reference(
+                        target,
+                        &reference.options,
+                        &mut self.reporting(&mut tree),
+                    )?;
+                }
+                return Ok(());
+            }
+            ReferenceProcessor::Mermaid => {
+                for target in &targets {
+                    let mut diagram = MermaidWriter::new(output);
+                    self.visit_reference(
+                        target,
+                        &reference.options,
+                        &mut self.reporting(&mut diagram),
+                    )?;
+                }
+                return Ok(());
+            }
+            ReferenceProcessor::Summary => {
+                for target in &targets {
+                    let mut summary = SummaryWriter::new(output);
+                    self.visit_reference(
+                        target,
+                        &reference.options,
+                        &mut self.reporting(&mut summary),
+                    )?;
+                }
+                return Ok(());
+            }
             ReferenceProcessor::Inherit => self.options.render,
             ReferenceProcessor::Markdown => RenderMode::Markdown,
             ReferenceProcessor::Raw => RenderMode::Raw,
         };
-        let label_directory = reference.processor == ReferenceProcessor::Markdown;
-        let logical = clean_logical_path(&reference.path);
+        let labelled = reference.processor == ReferenceProcessor::Markdown;
+        let mut bodies = BodyWriter {
+            engine: self,
+            render,
+            label_descendants: labelled,
+            label_members: labelled && reference.list,
+            lines: &reference.options,
+            separator: self.separator(),
+            depth: 0,
+            output,
+        };
+        for target in &targets {
+            self.visit_reference(target, &reference.options, &mut self.reporting(&mut bodies))?;
+        }
+        Ok(())
+    }
+
+    fn reporting<'a, V>(&'a self, inner: &'a mut V) -> Reporting<'a, V> {
+        Reporting {
+            progress: self.progress.as_deref(),
+            inner,
+        }
+    }
+
+    /// Write the answer to a `{{ @git:NAME }}` query, run in the reference's
+    /// directory, through its line filters.
+    fn render_git<W: Write>(
+        &self,
+        query: &GitQuery,
+        reference: &ParsedReference,
+        scope: Scope<'_>,
+        output: &mut W,
+    ) -> Result<()> {
+        let path = query.path();
+        check_generated_stages(reference, &path)?;
+        if !self.options.allow_commands {
+            return Err(TextconError::CommandsDisabled { path });
+        }
+        let (base, directory) = scope.resolve(reference);
+        let root = self.root(base)?;
+        if root.sandbox.is_some() {
+            return Err(TextconError::SandboxDenied {
+                path,
+                reason: "git queries read outside the sandbox root".into(),
+            });
+        }
+        let answer = query.run(&root.base_dir.join(directory))?;
+        write_generated(reference, answer, output)
+    }
+
+    /// Write the value of a `{{ @:date:FORMAT }}`, `{{ @:hostname }}`,
+    /// `{{ @:username }}`, or `{{ @:cwd }}` reference through its line
+    /// filters.
+    fn render_builtin<W: Write>(
+        &self,
+        builtin: &Builtin,
+        reference: &ParsedReference,
+        output: &mut W,
+    ) -> Result<()> {
+        check_generated_stages(reference, &builtin.path())?;
+        let value = builtin.value(reference.offset, self.options.allow_commands)?;
+        write_generated(reference, value, output)
+    }
+
+    /// The files `reference` reads: none for a `{{ @git:NAME }}` query or a
+    /// built-in value, whose stages are still checked, the archive for a
+    /// `{{ @zip:PATH }}` reference, and otherwise its targets.
+    fn file_targets<'a>(
+        &'a self,
+        reference: &'a ParsedReference,
+        scope: Scope<'a>,
+    ) -> Result<Vec<Target<'a>>> {
+        if let Some(query) = GitQuery::from_reference(reference) {
+            let path = query.path();
+            check_generated_stages(reference, &path)?;
+            if !self.options.allow_commands {
+                return Err(TextconError::CommandsDisabled { path });
+            }
+            return Ok(Vec::new());
+        }
+        if let Some(builtin) = Builtin::from_reference(reference) {
+            check_generated_stages(reference, &builtin.path())?;
+            return Ok(Vec::new());
+        }
+        if let Some(archive) = archive_path(reference) {
+            let (base, directory) = scope.resolve(reference);
+            return Ok(vec![Target::new(
+                base,
+                self.root(base)?,
+                directory.join(archive),
+            )]);
+        }
+        self.targets(reference, scope)
+    }
+
+    /// Write a `{{ @zip:PATH }}` reference: a listing of the archive's
+    /// members with `| tree`, and otherwise their bodies as though they were
+    /// the files of a directory. Binary-looking members are skipped.
+    #[cfg(feature = "archive")]
+    fn render_archive<W: Write>(
+        &self,
+        archive: &Path,
+        reference: &ParsedReference,
+        scope: Scope<'_>,
+        expansion: &mut Expansion<'_>,
+        output: &mut W,
+    ) -> Result<()> {
+        let render = match reference.processor {
+            ReferenceProcessor::Inherit | ReferenceProcessor::Tree => self.options.render,
+            ReferenceProcessor::Markdown => RenderMode::Markdown,
+            ReferenceProcessor::Raw => RenderMode::Raw,
+            ReferenceProcessor::Mermaid
+            | ReferenceProcessor::Summary
+            | ReferenceProcessor::Template => {
+                return Err(TextconError::TemplateSyntax {
+                    offset: reference.offset,
+                    message: format!(
+                        "zip:{} accepts only | markdown, | raw, or | tree",
+                        archive.display()
+                    ),
+                });
+            }
+        };
+        let (base, directory) = scope.resolve(reference);
+        let target = Target::new(base, self.root(base)?, directory.join(archive));
+        let mut open = |logical: &Path, file: File| {
+            if logical != target.logical {
+                return Err(TextconError::Archive {
+                    path: target.logical.clone(),
+                    message: "not a file".into(),
+                });
+            }
+            let modified = file
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .map_err(|error| TextconError::path_io("read modification time", logical, error))?;
+            let mut archive = Archive::open(file, logical)?;
+            if reference.options.reverse {
+                archive.reverse();
+            }
+            if reference.processor == ReferenceProcessor::Tree {
+                if self
+                    .options
+                    .tree
+                    .max_trees
+                    .is_some_and(|limit| expansion.trees >= limit)
+                {
+                    writeln!(
+                        output,
+                        "<!-- tree of {} omitted (max_trees reached) -->",
+                        encode_path(logical.as_os_str())
+                    )
+                    .map_err(TextconError::output)?;
+                    return Ok(());
+                }
+                expansion.trees += 1;
+                return archive.write_tree(logical, output);
+            }
+            let labelled = reference.processor == ReferenceProcessor::Markdown;
+            let mut separator = self.separator();
+            for (index, name) in archive.files() {
+                let member = logical.join(name);
+                let mut content = Member::new(archive.read(index, &member)?, modified);
+                if starts_binary(&mut content)
+                    .map_err(|error| TextconError::path_io("read", &member, error))?
+                {
+                    if let Some(skipped) = &self.skipped {
+                        skipped(&member, SkipReason::Binary);
+                    }
+                    continue;
+                }
+                separator.write(output)?;
+                self.render_file(
+                    &member,
+                    content,
+                    render,
+                    labelled,
+                    &reference.options,
+                    output,
+                )?;
+            }
+            Ok(())
+        };
+        self.visit_reference(&target, &reference.options, &mut open)
+    }
+
+    #[cfg(not(feature = "archive"))]
+    #[allow(clippy::unused_self)]
+    fn render_archive<W: Write>(
+        &self,
+        archive: &Path,
+        _reference: &ParsedReference,
+        _scope: Scope<'_>,
+        _expansion: &mut Expansion<'_>,
+        _output: &mut W,
+    ) -> Result<()> {
+        Err(TextconError::Archive {
+            path: archive.to_path_buf(),
+            message: "zip archives need textcon built with the `archive` feature".into(),
+        })
+    }
+
+    /// Resolve the targets of `reference` within `scope`. An explicit
+    /// `| base=NAME` resolves from the top of that alias instead.
+    fn targets<'a>(
+        &'a self,
+        reference: &'a ParsedReference,
+        scope: Scope<'a>,
+    ) -> Result<Vec<Target<'a>>> {
+        let (base, directory) = scope.resolve(reference);
+        let root = self.root(base)?;
+        Ok(reference
+            .paths
+            .iter()
+            .map(|path| Target::new(base, root, directory.join(path)))
+            .collect())
+    }
 
-        if let Some(sandbox) = &self.sandbox {
-            let relative = sandbox_relative(sandbox, &reference.path).map_err(|reason| {
-                TextconError::SandboxDenied {
-                    path: reference.path.clone(),
+    fn root(&self, base: Option<&str>) -> Result<&Root> {
+        base.map_or(Ok(&self.root), |alias| {
+            self.aliases
+                .get(alias)
+                .ok_or_else(|| TextconError::UnknownBaseAlias {
+                    alias: alias.to_owned(),
+                })
+        })
+    }
+
+    /// Resolve a reference target and hand a file, or a selected directory
+    /// walk, to `visitor`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %target.path.display()))
+    )]
+    fn visit_reference<V: Visitor>(
+        &self,
+        target: &Target<'_>,
+        options: &ReferenceOptions,
+        visitor: &mut V,
+    ) -> Result<()> {
+        let root = target.root;
+        let path = target.path.as_path();
+        let logical = target.logical.as_path();
+        if let Some(sandbox) = &root.sandbox {
+            let relative =
+                sandbox_relative(sandbox, path).map_err(|reason| TextconError::SandboxDenied {
+                    path: path.to_path_buf(),
                     reason,
-                }
-            })?;
+                })?;
             let metadata = sandbox.directory.metadata(&relative).map_err(|error| {
                 TextconError::path_io(
                     "inspect sandboxed reference",
@@ -228,6 +1858,7 @@ impl Engine {
                 )
             })?;
             if metadata.is_file() {
+                debug_event!(path = %relative.display(), "open sandboxed reference");
                 let file = sandbox
                     .directory
                     .open(&relative)
@@ -239,77 +1870,249 @@ impl Engine {
                         )
                     })?
                     .into_std();
-                self.reject_output_file(&file, &reference.path)?;
-                return Self::render_file(&logical, file, render, false, output);
+                self.reject_output_file(&file, path)?;
+                return visitor.file(logical, file, true);
             }
             if metadata.is_dir() {
-                let selector =
-                    Selector::new(&self.options.selection, self.output_identity.as_ref());
-                return selector.select_sandbox(
+                let selector = self.selector(options.reverse);
+                let mut counted = Counted {
+                    inner: visitor,
+                    files: 0,
+                };
+                selector.select_sandbox(
                     &sandbox.directory,
                     &relative,
-                    &logical,
+                    logical,
                     &sandbox.canonical_root,
-                    &mut |path, file| {
-                        Self::render_file(path, file, render, label_directory, output)
-                    },
-                );
+                    &mut counted,
+                )?;
+                self.warn_if_empty(logical, counted.files);
+                return Ok(());
             }
             return Err(TextconError::UnsupportedFileType {
-                path: reference.path,
+                path: path.to_path_buf(),
             });
         }
 
-        let physical = if reference.path.is_absolute() {
-            reference.path
+        let physical = if path.is_absolute() {
+            path.to_path_buf()
         } else {
-            self.base_dir.join(&reference.path)
+            root.base_dir.join(path)
         };
         let metadata = fs::metadata(&physical)
             .map_err(|error| TextconError::path_io("inspect reference", &physical, error))?;
         if metadata.is_file() {
+            debug_event!(path = %physical.display(), "open reference");
             let file = File::open(&physical)
                 .map_err(|error| TextconError::path_io("open reference", &physical, error))?;
             self.reject_output_file(&file, &physical)?;
-            return Self::render_file(&logical, file, render, false, output);
+            return visitor.file(logical, file, true);
         }
         if metadata.is_dir() {
-            let (selected_root, policy_root) = ambient_selection_roots(&physical, &self.base_dir)?;
-            let selector = Selector::new(&self.options.selection, self.output_identity.as_ref());
-            return selector.select_ambient(
-                &selected_root,
-                &logical,
-                &policy_root,
-                &mut |path, file| Self::render_file(path, file, render, label_directory, output),
-            );
+            let (selected_root, policy_root) = ambient_selection_roots(&physical, &root.base_dir)?;
+            let selector = self.selector(options.reverse);
+            let mut counted = Counted {
+                inner: visitor,
+                files: 0,
+            };
+            selector.select_ambient(&selected_root, logical, &policy_root, &mut counted)?;
+            self.warn_if_empty(logical, counted.files);
+            return Ok(());
         }
         Err(TextconError::UnsupportedFileType { path: physical })
     }
 
+    fn warn_if_empty(&self, logical: &Path, files: usize) {
+        if files == 0
+            && let Some(warning) = &self.warning
+        {
+            warning(Warning::EmptyDirectory {
+                path: logical.to_path_buf(),
+            });
+        }
+    }
+
+    /// Report a file about to be read to the progress and warning callbacks,
+    /// and reject it when it looks binary and binary files are rejected.
+    fn report_file(&self, logical_path: &Path, file: &mut impl Source) -> Result<()> {
+        if let Some(progress) = &self.progress {
+            progress(ProgressEvent::ReadFile {
+                path: logical_path.to_path_buf(),
+                bytes: file
+                    .size()
+                    .map_err(|error| TextconError::path_io("inspect", logical_path, error))?,
+            });
+        }
+        if (self.options.reject_binary || self.warning.is_some())
+            && starts_binary(file)
+                .map_err(|error| TextconError::path_io("read", logical_path, error))?
+        {
+            if self.options.reject_binary {
+                return Err(TextconError::BinaryFile {
+                    path: logical_path.to_path_buf(),
+                });
+            }
+            if let Some(warning) = &self.warning {
+                warning(Warning::BinaryContent {
+                    path: logical_path.to_path_buf(),
+                });
+            }
+        }
+        Ok(())
+    }
+
     fn render_file<W: Write>(
+        &self,
         logical_path: &Path,
-        mut file: File,
+        mut file: impl Source,
         render: RenderMode,
         labelled: bool,
+        lines: &ReferenceOptions,
         output: &mut W,
     ) -> Result<()> {
-        let adaptive = render == RenderMode::Markdown && is_markdown_path(logical_path);
-        if labelled && render == RenderMode::Markdown {
-            write_markdown_record(logical_path, &mut file, adaptive, output)
+        self.report_file(logical_path, &mut file)?;
+        let markers = lines
+            .section
+            .as_deref()
+            .map(|section| SectionMarkers::new(&self.options.section_marker_prefix, section));
+        if let Some(markers) = &markers {
+            let found = markers
+                .found_in(&mut file)
+                .map_err(|error| TextconError::path_io("read", logical_path, error))?;
+            if !found {
+                return Err(TextconError::SectionNotFound {
+                    path: logical_path.to_path_buf(),
+                    section: lines.section.clone().unwrap_or_default(),
+                });
+            }
+        }
+        let markdown = render == RenderMode::Markdown;
+        let adaptive = markdown && is_markdown_path(logical_path);
+        let fence = if self.options.code_fences && markdown && !adaptive {
+            Some(
+                choose_fence(&mut file)
+                    .map_err(|error| TextconError::path_io("read", logical_path, error))?,
+            )
+        } else {
+            None
+        };
+        let record = labelled && markdown;
+        let modified = if record && self.options.modified_times {
+            Some(file.modified().map_err(|error| {
+                TextconError::path_io("read modification time", logical_path, error)
+            })?)
+        } else {
+            None
+        };
+        let (summary, mut body): (_, Box<dyn Read>) =
+            if record && self.options.doc_summaries && has_doc_syntax(logical_path) {
+                let prefix = read_prefix(&mut file)
+                    .map_err(|error| TextconError::path_io("read summary", logical_path, error))?;
+                let summary = doc_summary(logical_path, &prefix);
+                (summary, Box::new(Cursor::new(prefix).chain(file)))
+            } else {
+                (None, Box::new(file))
+            };
+        if markdown {
+            body = Box::new(SkipBom::new(body));
+        }
+        if let Some(transform) = lines.transform {
+            body = Box::new(Cursor::new(json::transform(
+                &mut body,
+                transform,
+                logical_path,
+            )?));
+        }
+        let crlf = self.options.line_endings.crlf();
+        if crlf.is_some() {
+            body = Box::new(LfLineEndings::new(body));
+        }
+        if self.options.strip_trailing_whitespace {
+            body = Box::new(TrimTrailingWhitespace::new(body));
+        }
+        let mut body: Box<dyn Read> = Box::new(FilteredLines::new(
+            body,
+            lines.lines,
+            &lines.filters,
+            lines.limit,
+            markers.as_ref(),
+        ));
+        if self.options.ensure_trailing_newline {
+            body = Box::new(TerminateLastLine::new(body));
+        }
+        if crlf == Some(true) {
+            body = Box::new(CrlfLineEndings::new(body));
+        }
+        let mut output = self.begin_marked(logical_path, output)?;
+        if record {
+            write_markdown_record(
+                logical_path,
+                &mut body,
+                adaptive,
+                fence.as_deref(),
+                summary.as_deref(),
+                modified,
+                self.options.checksums,
+                &mut output,
+            )?;
         } else {
-            write_body(logical_path, &mut file, adaptive, output)
+            write_body(
+                logical_path,
+                &mut body,
+                adaptive,
+                fence.as_deref(),
+                &mut output,
+            )?;
+        }
+        output.finish()
+    }
+
+    /// Apply the enabled line-ending and whitespace normalizations to a
+    /// direct input.
+    fn normalized<'a, R: Read + 'a>(&self, input: R) -> Box<dyn Read + 'a> {
+        let mut body: Box<dyn Read + 'a> = Box::new(input);
+        let crlf = self.options.line_endings.crlf();
+        if crlf.is_some() {
+            body = Box::new(LfLineEndings::new(body));
+        }
+        if self.options.strip_trailing_whitespace {
+            body = Box::new(TrimTrailingWhitespace::new(body));
+        }
+        if self.options.ensure_trailing_newline {
+            body = Box::new(TerminateLastLine::new(body));
+        }
+        if crlf == Some(true) {
+            body = Box::new(CrlfLineEndings::new(body));
+        }
+        body
+    }
+
+    const fn separator(&self) -> Separator<'_> {
+        Separator {
+            text: self.options.separator.as_bytes(),
+            pending: false,
         }
     }
 
+    fn selector(&self, reverse: bool) -> Selector<'_> {
+        Selector::new(
+            &self.options.selection,
+            &self.output_identities,
+            self.skipped.as_deref(),
+            reverse,
+        )
+    }
+
     fn reject_output_file(&self, file: &File, path: &Path) -> Result<()> {
-        if self.output_identity.as_ref().is_some_and(|output| {
-            file.try_clone()
+        if !self.output_identities.is_empty()
+            && file
+                .try_clone()
                 .ok()
                 .and_then(|clone| Handle::from_file(clone).ok())
-                .is_some_and(|candidate| candidate == *output)
-        }) {
+                .is_some_and(|candidate| self.output_identities.contains(&candidate))
+        {
             return Err(TextconError::Config(format!(
-                "input {} is the same file as stdout",
+                "input {} is the same file as an output",
                 path.display()
             )));
         }
@@ -317,6 +2120,397 @@ impl Engine {
     }
 }
 
+/// Writes reference bodies, labelling directory descendants and list
+/// members on request.
+struct BodyWriter<'a, W> {
+    engine: &'a Engine,
+    render: RenderMode,
+    label_descendants: bool,
+    label_members: bool,
+    /// Line filters and limits applied to every file body.
+    lines: &'a ReferenceOptions,
+    separator: Separator<'a>,
+    depth: usize,
+    output: &'a mut W,
+}
+
+impl<W: Write> Visitor for BodyWriter<'_, W> {
+    fn file(&mut self, path: &Path, file: File, _last: bool) -> Result<()> {
+        let labelled = if self.depth > 0 {
+            self.label_descendants
+        } else {
+            self.label_members
+        };
+        self.separator.write(self.output)?;
+        self.engine
+            .render_file(path, file, self.render, labelled, self.lines, self.output)
+    }
+
+    fn enter_directory(
+        &mut self,
+        _path: &Path,
+        _directory: Directory<'_>,
+        _last: bool,
+    ) -> Result<()> {
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+/// Reject the stages that need a file from a reference whose content is
+/// generated rather than read.
+fn check_generated_stages(reference: &ParsedReference, path: &Path) -> Result<()> {
+    let rejected = match reference.processor {
+        ReferenceProcessor::Inherit | ReferenceProcessor::Markdown | ReferenceProcessor::Raw => {
+            if reference.options.section.is_some() {
+                Some("section")
+            } else {
+                reference.options.transform.map(|_| "transform")
+            }
+        }
+        ReferenceProcessor::Mermaid => Some("mermaid"),
+        ReferenceProcessor::Summary => Some("summary"),
+        ReferenceProcessor::Template => Some("template"),
+        ReferenceProcessor::Tree => Some("tree"),
+    };
+    rejected.map_or(Ok(()), |stage| {
+        Err(TextconError::TemplateSyntax {
+            offset: reference.offset,
+            message: format!("{} does not accept | {stage}", path.display()),
+        })
+    })
+}
+
+/// Write generated reference content through the reference's line filters.
+fn write_generated<W: Write>(
+    reference: &ParsedReference,
+    content: Vec<u8>,
+    output: &mut W,
+) -> Result<()> {
+    let mut body = FilteredLines::new(
+        Cursor::new(content),
+        reference.options.lines,
+        &reference.options.filters,
+        reference.options.limit,
+        None,
+    );
+    io::copy(&mut body, output).map_err(TextconError::output)?;
+    Ok(())
+}
+
+/// Writes [`EngineOptions::separator`] before every file but the first.
+struct Separator<'a> {
+    text: &'a [u8],
+    pending: bool,
+}
+
+impl Separator<'_> {
+    fn write<W: Write>(&mut self, output: &mut W) -> Result<()> {
+        if std::mem::replace(&mut self.pending, true) {
+            output.write_all(self.text).map_err(TextconError::output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the relative targets of a reference resolve: beneath `directory`
+/// of the base directory, or of the named alias.
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+    base: Option<&'a str>,
+    directory: &'a Path,
+}
+
+impl Scope<'_> {
+    /// The base alias and directory that `reference` resolves against: its
+    /// own `| base=NAME` alias when given, and otherwise this scope.
+    fn resolve<'a>(self, reference: &'a ParsedReference) -> (Option<&'a str>, &'a Path)
+    where
+        Self: 'a,
+    {
+        reference
+            .options
+            .base
+            .as_deref()
+            .map_or((self.base, self.directory), |alias| {
+                (Some(alias), Path::new(""))
+            })
+    }
+
+    /// The scope of references in a top-level template.
+    fn top() -> Scope<'static> {
+        Scope {
+            base: None,
+            directory: Path::new(""),
+        }
+    }
+}
+
+/// One resolved reference target.
+struct Target<'a> {
+    base: Option<&'a str>,
+    root: &'a Root,
+    /// Path beneath the root directory, or an absolute path.
+    path: PathBuf,
+    /// Label path, beneath the configured directory of an alias.
+    logical: PathBuf,
+}
+
+impl<'a> Target<'a> {
+    fn new(base: Option<&'a str>, root: &'a Root, path: PathBuf) -> Self {
+        Self {
+            base,
+            root,
+            logical: clean_logical_path(&root.label.join(&path)),
+            path,
+        }
+    }
+}
+
+/// State shared by one top-level expansion and its nested templates.
+struct Expansion<'a> {
+    /// Per-call values that take precedence over the engine's variables.
+    variables: &'a HashMap<String, String>,
+    /// Templates currently being expanded, outermost first.
+    stack: Vec<TemplateFrame>,
+    /// `| tree` listings rendered so far.
+    trees: usize,
+}
+
+/// A template file whose expansion is in progress.
+struct TemplateFrame {
+    identity: Handle,
+    path: PathBuf,
+}
+
+/// Expands selected files as nested templates, resolving their relative
+/// references beneath each file's own directory.
+struct TemplateWriter<'a, 'b, W> {
+    engine: &'a Engine,
+    /// Alias of the reference being expanded, inherited by nested references.
+    base: Option<&'a str>,
+    expansion: &'a mut Expansion<'b>,
+    output: &'a mut W,
+}
+
+impl<W: Write> Visitor for TemplateWriter<'_, '_, W> {
+    fn file(&mut self, path: &Path, file: File, _last: bool) -> Result<()> {
+        let limit = self.engine.options.max_template_depth;
+        if self.expansion.stack.len() >= limit {
+            return Err(TextconError::Config(format!(
+                "template {} exceeds the nesting limit of {limit}",
+                path.display()
+            )));
+        }
+        let identity = file
+            .try_clone()
+            .and_then(Handle::from_file)
+            .map_err(|error| TextconError::path_io("identify template", path, error))?;
+        if let Some(index) = self
+            .expansion
+            .stack
+            .iter()
+            .position(|frame| frame.identity == identity)
+        {
+            let mut cycle = self.expansion.stack[index..]
+                .iter()
+                .map(|frame| frame.path.clone())
+                .collect::<Vec<_>>();
+            cycle.push(path.to_path_buf());
+            return Err(TextconError::CircularReference {
+                path: path.to_path_buf(),
+                stack: cycle,
+            });
+        }
+
+        self.expansion.stack.push(TemplateFrame {
+            identity,
+            path: path.to_path_buf(),
+        });
+        let label = &self.engine.root(self.base)?.label;
+        let relative = path.strip_prefix(label).unwrap_or(path);
+        let scope = Scope {
+            base: self.base,
+            directory: relative.parent().unwrap_or_else(|| Path::new("")),
+        };
+        let engine = self.engine;
+        let expansion = &mut *self.expansion;
+        let result = parser::expand(
+            &mut &file,
+            self.output,
+            &encode_path(path.as_os_str()),
+            false,
+            |placeholder, writer| match placeholder {
+                Placeholder::Reference(reference) => {
+                    engine.render_scoped_reference(&reference, scope, expansion, writer)
+                }
+                Placeholder::Variable(variable) => {
+                    engine.render_variable(&variable, expansion.variables, writer)
+                }
+            },
+        );
+        self.expansion.stack.pop();
+        result.map(|_| ()).map_err(|error| match error {
+            TextconError::TemplateSyntax { offset, message } => TextconError::TemplateSyntax {
+                offset,
+                message: format!("{message} in {}", path.display()),
+            },
+            error => error,
+        })
+    }
+}
+
+/// Forwards a walk to `inner`, reporting each entered directory.
+struct Reporting<'a, V> {
+    progress: Option<&'a (dyn Fn(ProgressEvent) + Send + Sync)>,
+    inner: &'a mut V,
+}
+
+impl<V: Visitor> Visitor for Reporting<'_, V> {
+    fn file(&mut self, path: &Path, file: File, last: bool) -> Result<()> {
+        self.inner.file(path, file, last)
+    }
+
+    fn enter_directory(&mut self, path: &Path, directory: Directory<'_>, last: bool) -> Result<()> {
+        if let Some(progress) = self.progress {
+            progress(ProgressEvent::StartDirectory {
+                path: path.to_path_buf(),
+            });
+        }
+        self.inner.enter_directory(path, directory, last)
+    }
+
+    fn children(&mut self, children: &[Child<'_>]) -> Result<bool> {
+        self.inner.children(children)
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        self.inner.leave_directory()
+    }
+
+    fn lists_symlinks(&self) -> bool {
+        self.inner.lists_symlinks()
+    }
+
+    fn symlink(&mut self, path: &Path, target: &Path, last: bool) -> Result<()> {
+        self.inner.symlink(path, target, last)
+    }
+}
+
+/// Forwards a walk to `inner`, counting the files it selects.
+struct Counted<'a, V> {
+    inner: &'a mut V,
+    files: usize,
+}
+
+impl<V: Visitor> Visitor for Counted<'_, V> {
+    fn file(&mut self, path: &Path, file: File, last: bool) -> Result<()> {
+        self.files += 1;
+        self.inner.file(path, file, last)
+    }
+
+    fn enter_directory(&mut self, path: &Path, directory: Directory<'_>, last: bool) -> Result<()> {
+        self.inner.enter_directory(path, directory, last)
+    }
+
+    fn children(&mut self, children: &[Child<'_>]) -> Result<bool> {
+        self.inner.children(children)
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        self.inner.leave_directory()
+    }
+
+    fn lists_symlinks(&self) -> bool {
+        self.inner.lists_symlinks()
+    }
+
+    fn symlink(&mut self, path: &Path, target: &Path, last: bool) -> Result<()> {
+        self.inner.symlink(path, target, last)
+    }
+}
+
+/// Counts bytes accepted by the wrapped writer; errors pass through unchanged.
+struct CountingWriter<'a, W> {
+    inner: &'a mut W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.count = self.count.saturating_add(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Counts every byte an engine writes in `written`, refusing a write that
+/// would take the total past `limit`.
+struct LimitedWriter<'a, W> {
+    inner: &'a mut W,
+    limit: Option<u64>,
+    written: &'a AtomicU64,
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.written.load(Ordering::Relaxed);
+        if let Some(limit) = self.limit
+            && written.saturating_add(buffer.len() as u64) > limit
+        {
+            return Err(io::Error::other(OutputLimit { limit, written }));
+        }
+        let count = self.inner.write(buffer)?;
+        self.written.fetch_add(count as u64, Ordering::Relaxed);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The output error a [`LimitedWriter`] raises, carried to the public call
+/// through the writers in between.
+#[derive(Debug)]
+struct OutputLimit {
+    limit: u64,
+    written: u64,
+}
+
+impl std::fmt::Display for OutputLimit {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "output limit of {} bytes reached", self.limit)
+    }
+}
+
+impl std::error::Error for OutputLimit {}
+
+/// Turn an output failure raised by a [`LimitedWriter`] back into
+/// [`TextconError::TotalSizeExceeded`].
+fn limit_error(error: TextconError) -> TextconError {
+    if let TextconError::Output(source) = &error
+        && let Some(limit) = source
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<OutputLimit>())
+    {
+        return TextconError::TotalSizeExceeded {
+            limit: limit.limit,
+            written: limit.written,
+        };
+    }
+    error
+}
+
 fn validate_excludes(root: &Path, patterns: &[String]) -> Result<()> {
     let mut builder = GitignoreBuilder::new(root);
     for pattern in patterns {
@@ -334,6 +2528,30 @@ fn validate_excludes(root: &Path, patterns: &[String]) -> Result<()> {
     Ok(())
 }
 
+fn validate_base_aliases(aliases: &HashMap<String, PathBuf>) -> Result<()> {
+    if let Some(name) = aliases
+        .keys()
+        .find(|name| !parser::is_variable_name(name.as_bytes()))
+    {
+        return Err(TextconError::Config(format!(
+            "invalid base alias name '{name}'"
+        )));
+    }
+    Ok(())
+}
+
+fn validate_variables(variables: &HashMap<String, String>) -> Result<()> {
+    if let Some(name) = variables
+        .keys()
+        .find(|name| !parser::is_variable_name(name.as_bytes()))
+    {
+        return Err(TextconError::Config(format!(
+            "invalid variable name '{name}'"
+        )));
+    }
+    Ok(())
+}
+
 fn absolute_from(base: &Path, path: &Path) -> PathBuf {
     if path.is_absolute() {
         path.to_path_buf()
@@ -388,6 +2606,20 @@ fn canonicalize_for_matching(path: &Path) -> std::io::Result<PathBuf> {
     dunce::canonicalize(path)
 }
 
+/// The output file name of a reference split into its own file.
+fn split_file_name(reference: &ParsedReference) -> String {
+    let name = reference
+        .paths
+        .iter()
+        .map(|path| encode_path(clean_logical_path(path).as_os_str()).replace('/', "_"))
+        .collect::<Vec<_>>()
+        .join(",");
+    match name.as_str() {
+        "" | "." | ".." => format!("_{name}"),
+        _ => name,
+    }
+}
+
 fn clean_logical_path(path: &Path) -> PathBuf {
     let mut output = PathBuf::new();
     for component in path.components() {
@@ -482,6 +2714,118 @@ mod tests {
         assert_eq!(output, b"\0\xff");
     }
 
+    #[test]
+    fn xml_markers_number_documents_with_encoded_paths() {
+        let engine = Engine::new(EngineOptions {
+            render: RenderMode::Raw,
+            file_markers: Some(FileMarkers::Xml),
+            ..EngineOptions::default()
+        })
+        .unwrap();
+        let mut output = Vec::new();
+        for name in ["a<b>&c", "d"] {
+            engine
+                .render_reader(Path::new(name), &mut Cursor::new(b"x"), &mut output)
+                .unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<document index=\"1\">\n<source>a%3Cb%3E%26c</source>\n<document_content>\n\
+             x\n</document_content>\n</document>\n\
+             <document index=\"2\">\n<source>d</source>\n<document_content>\n\
+             x\n</document_content>\n</document>\n"
+        );
+    }
+
+    #[test]
+    fn per_call_variables_take_precedence_over_engine_variables() {
+        let mut options = EngineOptions::default();
+        options
+            .variables
+            .insert("PROJECT_NAME".to_owned(), "engine".to_owned());
+        options.variables.insert("TAG".to_owned(), "v1".to_owned());
+        let engine = Engine::new(options).unwrap();
+        let variables = HashMap::from([("PROJECT_NAME".to_owned(), "textcon".to_owned())]);
+        let expand = |template: &str| {
+            let mut output = Vec::new();
+            engine
+                .expand_template_with_variables(&mut Cursor::new(template), &mut output, &variables)
+                .map(|()| output)
+        };
+
+        assert_eq!(
+            expand("{{ $PROJECT_NAME }} {{ $TAG }}").unwrap(),
+            b"textcon v1"
+        );
+        let error = expand("x{{ $MISSING }}").unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                TextconError::UndefinedVariable { name, offset: 1 } if name == "MISSING"
+            ),
+            "{error}"
+        );
+        assert!(matches!(
+            engine.expand_template_with_variables(
+                &mut Cursor::new(""),
+                &mut Vec::new(),
+                &HashMap::from([("not-a-name".to_owned(), String::new())]),
+            ),
+            Err(TextconError::Config(_))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn options_round_trip_through_toml() {
+        let mut options = EngineOptions {
+            render: RenderMode::Raw,
+            ..EngineOptions::default()
+        };
+        options.selection.excludes.push("target/".to_owned());
+        options
+            .variables
+            .insert("task".to_owned(), "review".to_owned());
+        for options in [EngineOptions::default(), options] {
+            let source = options.to_toml_string().unwrap();
+            assert_eq!(EngineOptions::from_toml_str(&source).unwrap(), options);
+        }
+
+        let partial =
+            EngineOptions::from_toml_str("render = \"raw\"\n[selection]\nhidden = true\n").unwrap();
+        assert_eq!(partial.render, RenderMode::Raw);
+        assert!(partial.selection.hidden);
+        assert!(partial.selection.use_gitignore);
+        assert!(matches!(
+            EngineOptions::from_toml_str("render = \"html\""),
+            Err(TextconError::Config(_))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn toml_output_is_byte_identical_across_runs() {
+        let render = || {
+            let mut options = EngineOptions {
+                base_dir: PathBuf::from("/project"),
+                ..EngineOptions::default()
+            };
+            for index in 0..64 {
+                options
+                    .variables
+                    .insert(format!("key_{index}"), index.to_string());
+            }
+            options.to_toml_string().unwrap()
+        };
+        let first = render();
+        assert_eq!(first, render());
+        let keys = first
+            .lines()
+            .filter_map(|line| line.strip_prefix("key_"))
+            .collect::<Vec<_>>();
+        assert!(keys.is_sorted(), "{keys:?}");
+    }
+
     #[test]
     fn absolute_references_remain_absolute() {
         let temporary = TempDir::new().unwrap();
@@ -496,6 +2840,86 @@ mod tests {
         assert_eq!(output, b"absolute");
     }
 
+    #[test]
+    fn validation_resolves_references_without_output() {
+        let temporary = TempDir::new().unwrap();
+        fs::write(temporary.path().join("present"), b"content").unwrap();
+        let options = EngineOptions {
+            base_dir: temporary.path().to_path_buf(),
+            ..EngineOptions::default()
+        };
+        let engine = Engine::new(options).unwrap();
+        engine
+            .validate_template(&mut Cursor::new(b"{{ @present }} {{ @. | tree }}"))
+            .unwrap();
+        let error = engine
+            .validate_template(&mut Cursor::new(b"{{ @present }} {{ @missing }}"))
+            .unwrap_err();
+        assert!(matches!(error.unlocated(), TextconError::PathIo { .. }));
+    }
+
+    #[test]
+    fn validation_and_checksums_skip_generated_references() {
+        let temporary = TempDir::new().unwrap();
+        fs::write(temporary.path().join("present"), b"content").unwrap();
+        let mut options = EngineOptions {
+            base_dir: temporary.path().to_path_buf(),
+            ..EngineOptions::default()
+        };
+        let template = b"{{ @git:branch }} {{ @:hostname }} {{ @present }}";
+        let error = Engine::new(options.clone())
+            .unwrap()
+            .validate_template(&mut Cursor::new(template))
+            .unwrap_err();
+        assert!(matches!(
+            error.unlocated(),
+            TextconError::CommandsDisabled { .. }
+        ));
+        options.allow_commands = true;
+        let engine = Engine::new(options).unwrap();
+        engine
+            .validate_template(&mut Cursor::new(template))
+            .unwrap();
+        let manifest = engine
+            .checksum_manifest(&mut Cursor::new(template))
+            .unwrap();
+        assert_eq!(manifest.keys().collect::<Vec<_>>(), [Path::new("present")]);
+        let error = engine
+            .validate_template(&mut Cursor::new(b"{{ @:hostname | tree }}"))
+            .unwrap_err();
+        assert!(matches!(
+            error.unlocated(),
+            TextconError::TemplateSyntax { .. }
+        ));
+    }
+
+    #[test]
+    fn nested_templates_detect_cycles() {
+        let temporary = TempDir::new().unwrap();
+        fs::create_dir(temporary.path().join("docs")).unwrap();
+        fs::write(temporary.path().join("docs/a.md"), "{{ @b.md | template }}").unwrap();
+        fs::write(temporary.path().join("docs/b.md"), "{{ @a.md | template }}").unwrap();
+        let engine = Engine::new(EngineOptions {
+            base_dir: temporary.path().to_path_buf(),
+            ..EngineOptions::default()
+        })
+        .unwrap();
+        let error = engine
+            .expand_template(
+                &mut Cursor::new(b"{{ @docs/a.md | template }}"),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+        let TextconError::CircularReference { path, stack } = error.unlocated() else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(path, Path::new("docs/a.md"));
+        assert_eq!(
+            *stack,
+            ["docs/a.md", "docs/b.md", "docs/a.md"].map(PathBuf::from)
+        );
+    }
+
     #[test]
     fn sandbox_rejects_parent_escape() {
         let temporary = TempDir::new().unwrap();
@@ -508,7 +2932,10 @@ mod tests {
         let error = engine
             .expand_template(&mut Cursor::new(b"{{ @../outside }}"), &mut Vec::new())
             .unwrap_err();
-        assert!(matches!(error, TextconError::SandboxDenied { .. }));
+        assert!(matches!(
+            error.unlocated(),
+            TextconError::SandboxDenied { .. }
+        ));
     }
 
     #[cfg(unix)]
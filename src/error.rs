@@ -3,6 +3,8 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::parser::ParsedReference;
+
 /// Errors returned by the streaming textcon engine.
 #[derive(Debug, Error)]
 pub enum TextconError {
@@ -14,6 +16,53 @@ pub enum TextconError {
     #[error("template byte {offset}: {message}")]
     TemplateSyntax { offset: u64, message: String },
 
+    /// A `| grep=PATTERN` or `| grep-v=PATTERN` stage held an invalid regex.
+    #[error("template byte {offset}: invalid line filter pattern: {message}")]
+    Regex { offset: u64, message: String },
+
+    /// A `| base=NAME` reference named an alias that is not configured.
+    #[error("unknown base alias '{alias}'")]
+    UnknownBaseAlias { alias: String },
+
+    /// A `| section=NAME` reference selected a file without that section.
+    #[error("section '{section}' not found in {path}")]
+    SectionNotFound { path: PathBuf, section: String },
+
+    /// A directory walk would select more files than
+    /// [`SelectionOptions::max_files`](crate::SelectionOptions::max_files).
+    #[error("{path} selects {count} files, more than the limit of {limit}")]
+    TooManyFiles {
+        path: PathBuf,
+        count: usize,
+        limit: usize,
+    },
+
+    /// A top-level template reference failed; `line` and `column` locate its
+    /// opening `{{` in the caller's template. With
+    /// [`EngineOptions::show_context`](crate::EngineOptions::show_context),
+    /// `context` holds the numbered template lines around it, and is
+    /// otherwise empty.
+    #[error(
+        "{source} (template line {line}, col {column}){}",
+        display_context(.context)
+    )]
+    Located {
+        line: u64,
+        column: u64,
+        context: String,
+        #[source]
+        source: Box<Self>,
+    },
+
+    /// A `{{ $name }}` placeholder named a variable without a value.
+    #[error("template byte {offset}: undefined variable '{name}'")]
+    UndefinedVariable { name: String, offset: u64 },
+
+    /// A `| template` reference re-entered a template still being expanded,
+    /// or a directory traversal re-entered one of its ancestors.
+    #[error("circular reference {path} via {}", display_chain(.stack))]
+    CircularReference { path: PathBuf, stack: Vec<PathBuf> },
+
     /// A reference was denied by the configured sandbox.
     #[error("sandbox denied reference {path}: {reason}")]
     SandboxDenied { path: PathBuf, reason: String },
@@ -22,6 +71,43 @@ pub enum TextconError {
     #[error("unsupported filesystem object: {path}")]
     UnsupportedFileType { path: PathBuf },
 
+    /// A reference would run a program, such as `git`, while
+    /// [`EngineOptions::allow_commands`](crate::EngineOptions::allow_commands)
+    /// was not set.
+    #[error("reference {path} runs a program, which is not allowed without --allow-commands")]
+    CommandsDisabled { path: PathBuf },
+
+    /// A `{{ @git:NAME }}` query could not run `git`, or `git` failed.
+    #[error("{command} failed: {message}")]
+    Git { command: String, message: String },
+
+    /// A `{{ @zip:PATH }}` reference named something other than a readable
+    /// zip archive, or textcon was built without the `archive` feature.
+    #[error("cannot read archive {path}: {message}")]
+    Archive { path: PathBuf, message: String },
+
+    /// An included file held a NUL byte near its start while
+    /// [`EngineOptions::reject_binary`](crate::EngineOptions::reject_binary)
+    /// was set.
+    #[error("{path} looks binary: it holds a NUL byte near its start; exclude it to continue")]
+    BinaryFile { path: PathBuf },
+
+    /// Writing more output would exceed
+    /// [`EngineOptions::max_total_output_bytes`](crate::EngineOptions::max_total_output_bytes).
+    #[error("output would exceed the limit of {limit} bytes; {written} bytes were written")]
+    TotalSizeExceeded { limit: u64, written: u64 },
+
+    /// References that
+    /// [`EngineOptions::strict_references`](crate::EngineOptions::strict_references)
+    /// found unresolvable before expansion, each wrapped in
+    /// [`TextconError::Located`].
+    #[error("{} of {total} references cannot be resolved:{}", errors.len(), display_errors(errors))]
+    UnresolvedReferences { total: usize, errors: Vec<Self> },
+
+    /// A `| transform=NAME` reference included a file that is not valid JSON.
+    #[error("invalid JSON in {path}: {message}")]
+    Json { path: PathBuf, message: String },
+
     /// A contextual filesystem operation failed.
     #[error("cannot {operation} {path}: {source}")]
     PathIo {
@@ -61,10 +147,49 @@ impl TextconError {
         }
     }
 
+    /// Locate a reference failure in its template. Output failures belong to
+    /// the caller's writer rather than the reference, and stay unwrapped.
+    pub(crate) fn located(self, reference: &ParsedReference) -> Self {
+        match self {
+            Self::Output(_) | Self::Located { .. } => self,
+            source => Self::Located {
+                line: reference.line,
+                column: reference.column,
+                context: String::new(),
+                source: Box::new(source),
+            },
+        }
+    }
+
+    pub(crate) fn with_context(mut self, snippet: String) -> Self {
+        if let Self::Located { context, .. } = &mut self {
+            *context = snippet;
+        }
+        self
+    }
+
     pub(crate) const fn output(source: io::Error) -> Self {
         Self::Output(source)
     }
 
+    /// Whether a reference failed because its path does not exist.
+    pub(crate) fn is_missing_reference(&self) -> bool {
+        matches!(
+            self,
+            Self::PathIo { operation: "inspect reference" | "inspect sandboxed reference", source, .. }
+                if source.kind() == io::ErrorKind::NotFound
+        )
+    }
+
+    /// The error beneath any [`TextconError::Located`] wrapper.
+    #[must_use]
+    pub fn unlocated(&self) -> &Self {
+        match self {
+            Self::Located { source, .. } => source,
+            error => error,
+        }
+    }
+
     /// Returns true only for a broken caller-provided output stream.
     #[must_use]
     pub fn is_output_broken_pipe(&self) -> bool {
@@ -72,5 +197,29 @@ impl TextconError {
     }
 }
 
+fn display_context(context: &str) -> String {
+    if context.is_empty() {
+        String::new()
+    } else {
+        format!("\n{context}")
+    }
+}
+
+fn display_errors(errors: &[TextconError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("\n  {error}"))
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+fn display_chain(stack: &[PathBuf]) -> String {
+    stack
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
 /// Result type used by the textcon library.
 pub type Result<T> = std::result::Result<T, TextconError>;
@@ -0,0 +1,141 @@
+#![allow(clippy::redundant_pub_crate)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::error::{Result, TextconError};
+use crate::parser::ParsedReference;
+
+/// Prefix of reference paths answered by `git` rather than the filesystem.
+const GIT_PREFIX: &str = "git:";
+
+/// Repository metadata named by a `{{ @git:NAME }}` reference.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum GitQuery {
+    /// `git:branch`: the checked-out branch, or `HEAD` when detached.
+    Branch,
+    /// `git:commit`: the abbreviated hash of `HEAD`.
+    Commit,
+    /// `git:tag`: the most recent tag reachable from `HEAD`.
+    Tag,
+    /// `git:status`: the short-format working tree status.
+    Status,
+    /// `git:diff`: working tree changes against `HEAD`.
+    Diff,
+    /// `git:diff:staged`: changes staged for the next commit.
+    StagedDiff,
+    /// `git:log:N`: the last `N` commits, one line each.
+    Log(usize),
+    /// `git:log:PATH`: the commits that touched `PATH`, one line each.
+    FileLog(String),
+}
+
+impl GitQuery {
+    /// The query named by `reference`, which must be a single path. Unknown
+    /// `git:` names are left to resolve as ordinary paths, and `@./git:NAME`
+    /// always does.
+    pub(crate) fn from_reference(reference: &ParsedReference) -> Option<Self> {
+        let [path] = reference.paths.as_slice() else {
+            return None;
+        };
+        let query = match path.to_str()?.strip_prefix(GIT_PREFIX)? {
+            "branch" => Self::Branch,
+            "commit" => Self::Commit,
+            "tag" => Self::Tag,
+            "status" => Self::Status,
+            "diff" => Self::Diff,
+            "diff:staged" => Self::StagedDiff,
+            name => {
+                let target = name
+                    .strip_prefix("log:")
+                    .filter(|target| !target.is_empty())?;
+                if target.bytes().all(|byte| byte.is_ascii_digit()) {
+                    Self::Log(target.parse().ok()?)
+                } else {
+                    Self::FileLog(target.to_owned())
+                }
+            }
+        };
+        Some(query)
+    }
+
+    /// The reference path as written, for errors and progress reports.
+    pub(crate) fn path(&self) -> PathBuf {
+        let name = match self {
+            Self::Branch => "branch".to_owned(),
+            Self::Commit => "commit".to_owned(),
+            Self::Tag => "tag".to_owned(),
+            Self::Status => "status".to_owned(),
+            Self::Diff => "diff".to_owned(),
+            Self::StagedDiff => "diff:staged".to_owned(),
+            Self::Log(count) => format!("log:{count}"),
+            Self::FileLog(path) => format!("log:{path}"),
+        };
+        PathBuf::from(format!("{GIT_PREFIX}{name}"))
+    }
+
+    fn arguments(&self) -> Vec<String> {
+        let arguments: &[&str] = match self {
+            Self::Branch => &["rev-parse", "--abbrev-ref", "HEAD"],
+            Self::Commit => &["rev-parse", "--short", "HEAD"],
+            Self::Tag => &["describe", "--tags", "--abbrev=0"],
+            Self::Status => &["status", "--short"],
+            Self::Diff => &["diff", "HEAD"],
+            Self::StagedDiff => &["diff", "--cached"],
+            Self::Log(count) => {
+                return vec!["log".into(), "--oneline".into(), format!("-n{count}")];
+            }
+            Self::FileLog(path) => {
+                return vec!["log".into(), "--oneline".into(), "--".into(), path.clone()];
+            }
+        };
+        arguments
+            .iter()
+            .map(|&argument| argument.to_owned())
+            .collect()
+    }
+
+    /// Whether the answer is a single value whose line ending is dropped, so
+    /// that it reads naturally inline.
+    const fn inline(&self) -> bool {
+        matches!(self, Self::Branch | Self::Commit | Self::Tag)
+    }
+
+    /// Run the query in `directory` and return its output.
+    pub(crate) fn run(&self, directory: &Path) -> Result<Vec<u8>> {
+        let arguments = self.arguments();
+        debug_event!(directory = %directory.display(), ?arguments, "run git");
+        let command = || format!("git {}", arguments.join(" "));
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(&arguments)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|error| TextconError::Git {
+                command: command(),
+                message: error.to_string(),
+            })?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+            return Err(TextconError::Git {
+                command: command(),
+                message: if message.is_empty() {
+                    output.status.to_string()
+                } else {
+                    message
+                },
+            });
+        }
+        let mut stdout = output.stdout;
+        if self.inline() {
+            while stdout
+                .last()
+                .is_some_and(|&byte| byte == b'\n' || byte == b'\r')
+            {
+                stdout.pop();
+            }
+        }
+        Ok(stdout)
+    }
+}
@@ -0,0 +1,36 @@
+#![allow(clippy::redundant_pub_crate)]
+
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::{Result, TextconError};
+use crate::parser::Transform;
+
+/// Parse the rest of `reader` as one JSON value, ignoring a leading byte
+/// order mark, and write it back out per `transform`, followed by a line
+/// feed. Object keys keep their order and numbers keep their digits.
+pub(crate) fn transform<R: Read + ?Sized>(
+    reader: &mut R,
+    transform: Transform,
+    logical_path: &Path,
+) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    reader
+        .read_to_end(&mut content)
+        .map_err(|error| TextconError::path_io("read", logical_path, error))?;
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&content);
+    let invalid = |error: serde_json::Error| TextconError::Json {
+        path: logical_path.to_path_buf(),
+        message: error.to_string(),
+    };
+    let value: Value = serde_json::from_slice(content).map_err(invalid)?;
+    let mut output = match transform {
+        Transform::Pretty => serde_json::to_vec_pretty(&value),
+        Transform::Minify => serde_json::to_vec(&value),
+    }
+    .map_err(invalid)?;
+    output.push(b'\n');
+    Ok(output)
+}
@@ -5,12 +5,37 @@
 //! [`Engine::expand_template`] streams a template and substitutes `{{ @path }}`
 //! references without buffering the complete input or output.
 
+/// Emit a `tracing` debug event when the `tracing` feature is enabled.
+macro_rules! debug_event {
+    ($($argument:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($argument)*);
+    };
+}
+
+mod archive;
+mod builtin;
 pub mod cli;
 mod engine;
 pub mod error;
+mod git;
+mod json;
+mod lines;
 mod parser;
 mod render;
 mod selector;
+mod summary;
+mod tree;
 
-pub use engine::{Engine, EngineOptions, RenderMode, SelectionOptions};
+pub use engine::{
+    DEFAULT_EXCLUDED_EXTENSIONS, DEFAULT_MAX_TEMPLATE_DEPTH, DEFAULT_SECTION_MARKER_PREFIX, Engine,
+    EngineOptions, ExpansionStats, FileMarkers, LineEnding, ProgressEvent, RenderMode,
+    SelectionOptions, SkipReason, TreeOptions, WalkOrder, Warning,
+};
 pub use error::{Result, TextconError};
+pub use lines::{LineFilter, LineLimit, LineRange};
+pub use parser::{
+    ParsedReference, ReferenceKind, ReferenceOptions, ReferenceProcessor, Transform,
+    format_template, parse_reference,
+};
+pub use tree::{DirectoryTree, TreeNode};
@@ -0,0 +1,319 @@
+#![allow(clippy::redundant_pub_crate)]
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead as _, BufReader, Read, Seek};
+
+use regex::bytes::Regex;
+
+/// A `| grep=PATTERN` or `| grep-v=PATTERN` reference stage, keeping only
+/// the lines of included files that match, or that do not match, a regex.
+#[derive(Clone, Debug)]
+pub struct LineFilter {
+    regex: Regex,
+    inverted: bool,
+}
+
+impl LineFilter {
+    pub(crate) fn new(pattern: &str, inverted: bool) -> std::result::Result<Self, regex::Error> {
+        Ok(Self {
+            regex: Regex::new(pattern)?,
+            inverted,
+        })
+    }
+
+    /// The regular expression as written in the reference.
+    #[must_use]
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Whether matching lines are dropped rather than kept.
+    #[must_use]
+    pub const fn is_inverted(&self) -> bool {
+        self.inverted
+    }
+
+    /// Whether a line, without its line ending, passes the filter.
+    fn keeps(&self, line: &[u8]) -> bool {
+        self.regex.is_match(line) != self.inverted
+    }
+}
+
+impl PartialEq for LineFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern() == other.pattern() && self.inverted == other.inverted
+    }
+}
+
+impl Eq for LineFilter {}
+
+/// A `| head=N` or `| tail=N` reference stage, keeping only the first or
+/// last `N` lines that pass the line filters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineLimit {
+    /// `| head=N`
+    Head(usize),
+    /// `| tail=N`
+    Tail(usize),
+}
+
+/// A `| lines=N-M` reference stage or a `#LN-LM` path anchor, keeping only
+/// lines `start` through `end` of each included file, counted from one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LineRange {
+    /// First line kept.
+    pub start: usize,
+    /// Last line kept, never less than `start`.
+    pub end: usize,
+}
+
+/// The marker lines around a `| section=NAME` region, such as
+/// `// === BEGIN tests ===` and `// === END tests ===`.
+pub(crate) struct SectionMarkers {
+    begin: Vec<u8>,
+    end: Vec<u8>,
+}
+
+impl SectionMarkers {
+    pub(crate) fn new(prefix: &str, name: &str) -> Self {
+        Self {
+            begin: format!("{prefix} === BEGIN {name} ===").trim().into(),
+            end: format!("{prefix} === END {name} ===").trim().into(),
+        }
+    }
+
+    /// Whether `reader` contains a begin marker, rewinding it afterwards.
+    pub(crate) fn found_in<R: Read + Seek>(&self, reader: &mut R) -> io::Result<bool> {
+        let mut lines = BufReader::new(&mut *reader);
+        let mut line = Vec::new();
+        let found = loop {
+            line.clear();
+            if lines.read_until(b'\n', &mut line)? == 0 {
+                break false;
+            }
+            if strip_line_ending(&line).trim_ascii() == self.begin {
+                break true;
+            }
+        };
+        drop(lines);
+        reader.rewind()?;
+        Ok(found)
+    }
+}
+
+/// Yields the lines of `inner` inside the line range and section, if any,
+/// that pass every filter, with their original line endings, then applies
+/// the line limit. Memory is bounded by the longest line, or for a tail by
+/// the longest `N` consecutive kept lines.
+pub(crate) struct FilteredLines<'a, R> {
+    inner: BufReader<R>,
+    range: Option<LineRange>,
+    filters: &'a [LineFilter],
+    limit: Option<LineLimit>,
+    section: Option<&'a SectionMarkers>,
+    /// Lines read from `inner` so far.
+    number: usize,
+    /// Whether the previous line opened, or continued, a section region.
+    inside: bool,
+    line: Vec<u8>,
+    position: usize,
+    /// Lines kept so far under a head limit.
+    emitted: usize,
+    /// The last kept lines once a tail limit has consumed the input.
+    tail: Option<VecDeque<Vec<u8>>>,
+}
+
+impl<'a, R: Read> FilteredLines<'a, R> {
+    pub(crate) fn new(
+        inner: R,
+        range: Option<LineRange>,
+        filters: &'a [LineFilter],
+        limit: Option<LineLimit>,
+        section: Option<&'a SectionMarkers>,
+    ) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            range,
+            filters,
+            limit,
+            section,
+            number: 0,
+            inside: false,
+            line: Vec::new(),
+            position: 0,
+            emitted: 0,
+            tail: None,
+        }
+    }
+
+    /// Load the next line to emit, returning `false` at end of output.
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.position = 0;
+        match self.limit {
+            Some(LineLimit::Head(count)) if self.emitted == count => return Ok(false),
+            Some(LineLimit::Tail(count)) => {
+                if self.tail.is_none() {
+                    let mut kept = VecDeque::new();
+                    while self.next_kept_line()? {
+                        if count > 0 {
+                            if kept.len() == count {
+                                kept.pop_front();
+                            }
+                            kept.push_back(std::mem::take(&mut self.line));
+                        }
+                    }
+                    self.tail = Some(kept);
+                }
+                let next = self.tail.as_mut().and_then(VecDeque::pop_front);
+                let found = next.is_some();
+                self.line = next.unwrap_or_default();
+                return Ok(found);
+            }
+            _ => {}
+        }
+        let found = self.next_kept_line()?;
+        self.emitted += usize::from(found);
+        Ok(found)
+    }
+
+    /// Read lines until one lies inside the range and section and passes
+    /// the filters, returning `false` at end of input or past the range.
+    /// Marker lines are never kept.
+    fn next_kept_line(&mut self) -> io::Result<bool> {
+        loop {
+            self.line.clear();
+            self.position = 0;
+            if self.range.is_some_and(|range| self.number >= range.end)
+                || self.inner.read_until(b'\n', &mut self.line)? == 0
+            {
+                return Ok(false);
+            }
+            self.number += 1;
+            if self.range.is_some_and(|range| self.number < range.start) {
+                continue;
+            }
+            let content = strip_line_ending(&self.line);
+            if let Some(section) = self.section {
+                let marker = content.trim_ascii();
+                if !self.inside {
+                    self.inside = marker == section.begin;
+                    continue;
+                }
+                if marker == section.end {
+                    self.inside = false;
+                    continue;
+                }
+            }
+            if self.filters.iter().all(|filter| filter.keeps(content)) {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for FilteredLines<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.range.is_none()
+            && self.filters.is_empty()
+            && self.limit.is_none()
+            && self.section.is_none()
+        {
+            return self.inner.read(buffer);
+        }
+        if self.position == self.line.len() && !self.next_line()? {
+            return Ok(0);
+        }
+        let count = buffer.len().min(self.line.len() - self.position);
+        buffer[..count].copy_from_slice(&self.line[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_compose_and_keep_line_endings() {
+        let filters = [
+            LineFilter::new("^pub", false).unwrap(),
+            LineFilter::new(r"\(\)$", true).unwrap(),
+        ];
+        let input = b"pub fn a() {}\r\nfn b() {}\npub struct C;\npub fn d()\npub mod e;";
+        let mut output = Vec::new();
+        FilteredLines::new(&input[..], None, &filters, None, None)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"pub fn a() {}\r\npub struct C;\npub mod e;");
+    }
+
+    #[test]
+    fn no_filters_pass_bytes_through() {
+        let mut output = Vec::new();
+        FilteredLines::new(&b"a\nb"[..], None, &[], None, None)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"a\nb");
+    }
+
+    #[test]
+    fn limits_apply_after_filters() {
+        let input = b"1 ok\n2 skip\n3 ok\n4 ok\n5 ok";
+        let filters = [LineFilter::new("ok", false).unwrap()];
+        for (limit, expected) in [
+            (LineLimit::Head(2), &b"1 ok\n3 ok\n"[..]),
+            (LineLimit::Tail(2), &b"4 ok\n5 ok"[..]),
+            (LineLimit::Head(9), &b"1 ok\n3 ok\n4 ok\n5 ok"[..]),
+            (LineLimit::Tail(0), &b""[..]),
+        ] {
+            let mut output = Vec::new();
+            FilteredLines::new(&input[..], None, &filters, Some(limit), None)
+                .read_to_end(&mut output)
+                .unwrap();
+            assert_eq!(output, expected, "{limit:?}");
+        }
+    }
+
+    #[test]
+    fn ranges_count_input_lines_before_filters() {
+        let input = b"1 ok\n2 skip\n3 ok\n4 ok\n5 ok";
+        let filters = [LineFilter::new("ok", false).unwrap()];
+        let range = LineRange { start: 2, end: 4 };
+        let mut output = Vec::new();
+        FilteredLines::new(&input[..], Some(range), &filters, None, None)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"3 ok\n4 ok\n");
+
+        let range = LineRange { start: 5, end: 9 };
+        let mut output = Vec::new();
+        FilteredLines::new(&input[..], Some(range), &[], None, None)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"5 ok");
+    }
+
+    #[test]
+    fn sections_keep_only_named_regions() {
+        let input = b"a\n  // === BEGIN api ===\nb\n// === END api ===\n\
+            // === BEGIN tests ===\nc\r\n// === END tests ===\n// === BEGIN api ===\nd";
+        let markers = SectionMarkers::new("//", "api");
+        let mut output = Vec::new();
+        FilteredLines::new(&input[..], None, &[], None, Some(&markers))
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"b\nd");
+
+        let mut cursor = io::Cursor::new(&input[..]);
+        assert!(markers.found_in(&mut cursor).unwrap());
+        assert_eq!(cursor.position(), 0);
+        let missing = SectionMarkers::new("#", "api");
+        assert!(!missing.found_in(&mut cursor).unwrap());
+    }
+}
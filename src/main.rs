@@ -1,17 +1,27 @@
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Write as _};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use clap::{CommandFactory as _, Parser as _, error::ErrorKind};
-use textcon::cli::Cli;
-use textcon::{Engine, EngineOptions, Result, SelectionOptions, TextconError};
+use textcon::cli::{self, Cli, SkippedReport};
+use textcon::{
+    DEFAULT_EXCLUDED_EXTENSIONS, Engine, EngineOptions, ExpansionStats, Result, SelectionOptions,
+    TextconError, TreeOptions,
+};
+
+/// Bytes per token assumed by the `--stats` estimate, a common rule of thumb
+/// for English text and source code.
+const BYTES_PER_TOKEN: u64 = 4;
 
 fn main() -> ExitCode {
     let cli = Cli::parse();
     if cli
         .inputs
         .iter()
+        .chain(&cli.template)
         .filter(|path| path.as_path() == Path::new("-"))
         .count()
         > 1
@@ -34,43 +44,341 @@ fn main() -> ExitCode {
     }
 }
 
-fn run(cli: Cli) -> Result<()> {
-    let options = EngineOptions {
+fn run(mut cli: Cli) -> Result<()> {
+    let started = Instant::now();
+    #[cfg(feature = "completions")]
+    if let Some(shell) = cli.completions {
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut Cli::command(), "textcon", &mut script);
+        return io::stdout()
+            .lock()
+            .write_all(&script)
+            .map_err(TextconError::Output);
+    }
+
+    if cli.init {
+        return create_default_template(&cli);
+    }
+    resolve_template(&mut cli);
+    if cli.fmt {
+        return format_templates(&cli.template);
+    }
+
+    let mut engine = Engine::new(engine_options(&mut cli))?;
+    engine.protect_stdout();
+    let report = match cli.skipped_report {
+        Some(path) => Some((attach_skipped_report(&mut engine, &path)?, path)),
+        None => None,
+    };
+
+    let manifest = match cli.manifest {
+        Some(path) => Some((create_manifest(&mut engine, &path)?, path)),
+        None => None,
+    };
+    let stats = cli.stats.then(|| attach_stats(&mut engine));
+
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(CountingWriter {
+        inner: stdout.lock(),
+        bytes: 0,
+    });
+    write_framing(
+        cli.header.as_deref(),
+        cli.header_file.as_deref(),
+        &mut output,
+    )?;
+    render(
+        &engine,
+        &cli.template,
+        &cli.inputs,
+        cli.validate,
+        &mut output,
+    )?;
+    write_framing(
+        cli.footer.as_deref(),
+        cli.footer_file.as_deref(),
+        &mut output,
+    )?;
+    output.flush().map_err(TextconError::Output)?;
+    if let Some(stats) = stats {
+        let bytes = output.get_ref().bytes;
+        eprintln!(
+            "textcon: {}",
+            stats_summary(&stats, bytes, started.elapsed())
+        );
+    }
+    if let Some((file, path)) = manifest {
+        write_manifest(&engine, &cli.template, file, &path)?;
+    }
+    if let Some((report, path)) = report {
+        report.finish().map_err(|source| TextconError::PathIo {
+            operation: "write skipped report",
+            path,
+            source,
+        })?;
+    }
+    Ok(())
+}
+
+/// Move the engine settings out of the parsed command line.
+fn engine_options(cli: &mut Cli) -> EngineOptions {
+    EngineOptions {
         render: cli.render,
-        base_dir: cli.base_dir.unwrap_or_else(|| PathBuf::from(".")),
+        base_dir: cli.base_dir.take().unwrap_or_else(|| PathBuf::from(".")),
+        base_aliases: std::mem::take(&mut cli.base_aliases).into_iter().collect(),
         sandbox: cli.sandbox,
+        allow_commands: cli.allow_commands,
         selection: SelectionOptions {
             max_depth: cli.max_depth,
             hidden: cli.hidden,
             use_gitignore: !cli.no_gitignore,
-            excludes: cli.excludes,
+            excludes: std::mem::take(&mut cli.excludes),
+            include_extensions: std::mem::take(&mut cli.include_extensions),
+            max_files: cli.max_files,
+            order: cli.walk_order,
+            git_tracked_only: cli.git_tracked,
+            exclude_extensions: DEFAULT_EXCLUDED_EXTENSIONS
+                .iter()
+                .map(|&extension| extension.to_owned())
+                .chain(std::mem::take(&mut cli.exclude_extensions))
+                .collect(),
+            modified_within: cli.modified_within,
         },
-    };
-    let mut engine = Engine::new(options)?;
-    engine.protect_stdout();
+        tree: TreeOptions {
+            readme_summaries: cli.readme_summaries,
+            summarize_breadth_over: cli.summarize_breadth_over,
+            symlink_targets: cli.symlink_targets,
+            file_sizes: cli.file_sizes,
+            max_trees: cli.max_trees,
+        },
+        max_template_depth: cli.max_template_depth,
+        section_marker_prefix: std::mem::take(&mut cli.section_marker_prefix),
+        separator: cli.separator.take().unwrap_or_default(),
+        content_indent: cli.indent.take().unwrap_or_default(),
+        file_markers: cli.file_markers,
+        doc_summaries: cli.doc_summaries,
+        code_fences: cli.code_fences,
+        checksums: cli.checksums,
+        modified_times: cli.modified_times,
+        line_endings: cli.line_endings,
+        strip_trailing_whitespace: cli.strip_trailing_whitespace,
+        ensure_trailing_newline: cli.ensure_trailing_newline,
+        reject_binary: cli.reject_binary,
+        max_total_output_bytes: cli.max_total_size,
+        variables: std::mem::take(&mut cli.replacements).into_iter().collect(),
+        allow_undefined_variables: cli.allow_undefined,
+        missing_placeholder: cli.missing_placeholder.take(),
+        show_context: cli.show_context,
+        strict_references: cli.strict,
+        template_relative: cli.template_relative,
+    }
+}
+
+/// Expand the templates, or render the operands, in order with the engine's
+/// separators between them. With `validate`, templates are only checked.
+fn render<W: Write>(
+    engine: &Engine,
+    templates: &[PathBuf],
+    inputs: &[PathBuf],
+    validate: bool,
+    output: &mut W,
+) -> Result<()> {
+    let stdin = Path::new("-");
+    if validate {
+        return match templates {
+            [template] if template == stdin => engine.validate_template(&mut io::stdin().lock()),
+            templates => engine.validate_template_files(templates),
+        };
+    }
+    match templates {
+        [] => engine.render_operands(inputs, &mut io::stdin().lock(), output),
+        [template] if template == stdin => engine.expand_template(&mut io::stdin().lock(), output),
+        templates => engine.expand_template_files(templates, output),
+    }
+}
+
+/// Create the default template from the starter content, for `--init`,
+/// without replacing an existing file.
+fn create_default_template(cli: &Cli) -> Result<()> {
+    let base_dir = cli.base_dir.as_deref().unwrap_or_else(|| Path::new("."));
+    let path = base_dir.join(&cli.default_template);
+    File::create_new(&path)
+        .and_then(|mut file| file.write_all(cli::STARTER_TEMPLATE.as_bytes()))
+        .map_err(|source| TextconError::PathIo {
+            operation: "create default template",
+            path,
+            source,
+        })
+}
 
+/// Write each template to stdout in canonical form, for `--fmt`.
+fn format_templates(templates: &[PathBuf]) -> Result<()> {
     let stdout = io::stdout();
     let mut output = BufWriter::new(stdout.lock());
-    if let Some(template) = cli.template {
+    for template in templates {
         if template == Path::new("-") {
             let stdin = io::stdin();
-            engine.expand_template(&mut stdin.lock(), &mut output)?;
-        } else {
-            let file = File::open(&template).map_err(|source| TextconError::Input {
-                name: template.display().to_string(),
-                source,
-            })?;
-            engine.expand_template(&mut BufReader::new(file), &mut output)?;
+            textcon::format_template(&mut stdin.lock(), &mut output)?;
+            continue;
         }
-    } else {
-        for input in cli.inputs {
-            if input == Path::new("-") {
-                let stdin = io::stdin();
-                engine.render_reader(Path::new("-"), &mut stdin.lock(), &mut output)?;
-            } else {
-                engine.render_inputs(std::iter::once(input), &mut output)?;
+        let file = File::open(template).map_err(|source| TextconError::Input {
+            name: template.display().to_string(),
+            source,
+        })?;
+        textcon::format_template(&mut BufReader::new(file), &mut output)?;
+    }
+    output.flush().map_err(TextconError::Output)
+}
+
+/// Counts the bytes written through to `inner`, for `--stats`.
+struct CountingWriter<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Fall back to the default template when no input is given, and reject
+/// template combinations the engine cannot expand.
+fn resolve_template(cli: &mut Cli) {
+    if cli.inputs.is_empty() && cli.template.is_empty() {
+        let base_dir = cli.base_dir.as_deref().unwrap_or_else(|| Path::new("."));
+        let default = base_dir.join(&cli.default_template);
+        if !default.is_file() {
+            Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    format!(
+                        "no INPUT or --template given, and no default template {}",
+                        default.display()
+                    ),
+                )
+                .exit();
+        }
+        cli.template.push(default);
+    }
+
+    if cli.template_relative && cli.template.iter().any(|path| path == Path::new("-")) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--template-relative needs template files, not stdin",
+            )
+            .exit();
+    }
+
+    if cli.template.len() > 1 && cli.template.iter().any(|path| path == Path::new("-")) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "a template read from stdin cannot be repeated with other templates",
+            )
+            .exit();
+    }
+
+    if cli.manifest.is_some() && cli.template.iter().any(|path| path == Path::new("-")) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--manifest needs template files, not stdin",
+            )
+            .exit();
+    }
+}
+
+/// Write a `--header` or `--footer` string, or copy its file counterpart.
+fn write_framing<W: Write>(text: Option<&str>, file: Option<&Path>, output: &mut W) -> Result<()> {
+    if let Some(text) = text {
+        output
+            .write_all(text.as_bytes())
+            .map_err(TextconError::Output)?;
+    }
+    if let Some(path) = file {
+        let input_error = |source| TextconError::Input {
+            name: path.display().to_string(),
+            source,
+        };
+        let mut file = File::open(path).map_err(input_error)?;
+        let mut buffer = [0; 8192];
+        loop {
+            let count = file.read(&mut buffer).map_err(input_error)?;
+            if count == 0 {
+                break;
             }
+            output
+                .write_all(&buffer[..count])
+                .map_err(TextconError::Output)?;
         }
     }
-    output.flush().map_err(TextconError::Output)
+    Ok(())
+}
+
+/// Create the skipped-entry report at `path` and route the engine's skip
+/// events into it.
+fn attach_skipped_report(engine: &mut Engine, path: &Path) -> Result<Arc<SkippedReport>> {
+    let file = File::create(path).map_err(|source| TextconError::PathIo {
+        operation: "create skipped report",
+        path: path.to_path_buf(),
+        source,
+    })?;
+    engine.protect_output_file(&file);
+    let report = Arc::new(SkippedReport::new(file));
+    let sink = Arc::clone(&report);
+    engine.set_skipped(move |path, reason| sink.record(path, reason));
+    Ok(report)
+}
+
+/// Create the `--manifest` file before rendering, so that an unwritable path
+/// fails early and the manifest is never read back as a reference.
+fn create_manifest(engine: &mut Engine, path: &Path) -> Result<File> {
+    let file = File::create(path).map_err(|source| TextconError::PathIo {
+        operation: "create manifest",
+        path: path.to_path_buf(),
+        source,
+    })?;
+    engine.protect_output_file(&file);
+    Ok(file)
+}
+
+/// Hash every file the templates reference and write the digests to the
+/// `--manifest` file.
+fn write_manifest(engine: &Engine, templates: &[PathBuf], file: File, path: &Path) -> Result<()> {
+    let manifest = engine.checksum_manifest_paths(templates)?;
+    cli::write_manifest(&manifest, &mut BufWriter::new(file)).map_err(|source| {
+        TextconError::PathIo {
+            operation: "write manifest",
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+/// One `--stats` line for `bytes` of output written in `elapsed`.
+fn stats_summary(stats: &ExpansionStats, bytes: u64, elapsed: Duration) -> String {
+    format!(
+        "files read: {}, directories walked: {}, bytes read: {}, bytes written: {bytes}, estimated tokens: {}, time: {} ms",
+        stats.files_read(),
+        stats.directories_walked(),
+        stats.bytes_read(),
+        bytes.div_ceil(BYTES_PER_TOKEN),
+        elapsed.as_millis()
+    )
+}
+
+/// Route the engine's progress events into `--stats` counters.
+fn attach_stats(engine: &mut Engine) -> Arc<ExpansionStats> {
+    let stats = Arc::new(ExpansionStats::default());
+    let sink = Arc::clone(&stats);
+    engine.set_progress(move |event| sink.record(&event));
+    stats
 }
@@ -4,52 +4,357 @@ use std::collections::VecDeque;
 use std::io::{self, Read, Write};
 use std::path::PathBuf;
 
+use crate::archive::archive_path;
+use crate::builtin::Builtin;
 use crate::error::{Result, TextconError};
+use crate::git::GitQuery;
+use crate::lines::{LineFilter, LineLimit, LineRange};
 
 const INPUT_BUFFER_SIZE: usize = 64 * 1024;
 const LITERAL_BUFFER_SIZE: usize = 64 * 1024;
 pub(crate) const MAX_REFERENCE_BYTES: usize = 256 * 1024;
+/// Template lines kept for error context: the line before a failing
+/// reference, its own, the one after, and the start of the next.
+const CONTEXT_LINES: usize = 4;
+const CONTEXT_LINE_BYTES: usize = 200;
 
+/// The processor stage of a reference, which decides how its targets render.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub(crate) enum ReferenceProcessor {
+#[non_exhaustive]
+pub enum ReferenceProcessor {
+    /// No processor stage; the engine's render mode applies.
     Inherit,
+    /// `| markdown`
     Markdown,
+    /// `| mermaid`
+    Mermaid,
+    /// `| raw`
     Raw,
+    /// `| summary`
+    Summary,
+    /// `| template`
+    Template,
+    /// `| tree`
+    Tree,
+}
+
+/// A `{{ @path }}` reference as the template scanner understands it, before
+/// any target is resolved.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ParsedReference {
+    /// Targets in template order; a bracketed list may name several.
+    pub paths: Vec<PathBuf>,
+    /// Whether the targets were written as a bracketed list.
+    pub list: bool,
+    /// How the targets render, from the reference's processor stage.
+    pub processor: ReferenceProcessor,
+    /// Filters, limits, and other modifier stages applied to the targets.
+    pub options: ReferenceOptions,
+    /// Byte offset of the opening `{{` in its template.
+    pub offset: u64,
+    /// One-based line of the opening `{{` in its template.
+    pub line: u64,
+    /// One-based column, in characters, of the opening `{{` on its line.
+    pub column: u64,
+}
+
+impl ParsedReference {
+    /// What the reference reads, decided from its paths alone, as expansion
+    /// decides it, without touching the filesystem.
+    ///
+    /// ```
+    /// use textcon::{ReferenceKind, parse_reference};
+    ///
+    /// assert_eq!(parse_reference("{{ @src/ }}")?.kind(), ReferenceKind::Path);
+    /// assert_eq!(parse_reference("{{ @git:branch }}")?.kind(), ReferenceKind::Git);
+    /// assert_eq!(parse_reference("{{ @./git:branch }}")?.kind(), ReferenceKind::Path);
+    /// assert_eq!(parse_reference("{{ @:hostname }}")?.kind(), ReferenceKind::Builtin);
+    /// assert_eq!(parse_reference("{{ @hostname }}")?.kind(), ReferenceKind::Path);
+    /// assert_eq!(parse_reference("{{ @zip:docs.zip }}")?.kind(), ReferenceKind::Archive);
+    /// # Ok::<(), textcon::TextconError>(())
+    /// ```
+    #[must_use]
+    pub fn kind(&self) -> ReferenceKind {
+        if GitQuery::from_reference(self).is_some() {
+            ReferenceKind::Git
+        } else if Builtin::from_reference(self).is_some() {
+            ReferenceKind::Builtin
+        } else if archive_path(self).is_some() {
+            ReferenceKind::Archive
+        } else {
+            ReferenceKind::Path
+        }
+    }
+}
+
+/// What a reference reads, as returned by [`ParsedReference::kind`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReferenceKind {
+    /// Files and directories beneath the base directory or a base alias.
+    Path,
+    /// `git:NAME`: repository metadata answered by `git`.
+    Git,
+    /// `:date:FORMAT`, `:hostname`, `:username`, or `:cwd`: a value about
+    /// the current run.
+    Builtin,
+    /// `zip:PATH`: the members of a zip archive.
+    Archive,
+}
+
+/// Modifier stages that follow a reference path alongside its processor.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ReferenceOptions {
+    /// `| reverse`: visit directory entries in reverse name order.
+    pub reverse: bool,
+    /// `| grep=PATTERN` and `| grep-v=PATTERN` stages in reference order;
+    /// a line is included only when it passes all of them.
+    pub filters: Vec<LineFilter>,
+    /// `| lines=N-M` or a `#LN-LM` path anchor, applied before the line
+    /// filters.
+    pub lines: Option<LineRange>,
+    /// `| head=N` or `| tail=N`, applied after the line filters.
+    pub limit: Option<LineLimit>,
+    /// `| section=NAME`: include only the lines between the file's
+    /// `=== BEGIN NAME ===` and `=== END NAME ===` marker comments.
+    pub section: Option<String>,
+    /// `| base=NAME`: resolve relative paths beneath the named base alias.
+    pub base: Option<String>,
+    /// `| transform=pretty` or `| transform=minify`: re-serialize each
+    /// included JSON file before the line filters.
+    pub transform: Option<Transform>,
+}
+
+/// A `| transform=NAME` reference stage, which parses each included file as
+/// JSON and writes it back out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Transform {
+    /// `| transform=pretty`: indent nested values by two spaces per level.
+    Pretty,
+    /// `| transform=minify`: drop all insignificant whitespace.
+    Minify,
 }
 
 #[derive(Debug, Eq, PartialEq)]
-pub(crate) struct ParsedReference {
-    pub(crate) path: PathBuf,
-    pub(crate) processor: ReferenceProcessor,
+pub(crate) struct ParsedVariable {
+    pub(crate) name: String,
+    /// Original placeholder bytes, copied verbatim when undefined variables are allowed.
+    pub(crate) source: Vec<u8>,
     pub(crate) offset: u64,
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Placeholder {
+    Reference(ParsedReference),
+    Variable(ParsedVariable),
+}
+
+/// Where a template byte sits, as a byte offset and a one-based line and
+/// character column.
+#[derive(Clone, Copy, Debug)]
+struct Position {
+    offset: u64,
+    line: u64,
+    column: u64,
+}
+
+impl Position {
+    const START: Self = Self {
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+
+    /// The position of the byte after `byte`, which sits at `self`. UTF-8
+    /// continuation bytes do not start a new column.
+    const fn after(self, byte: u8) -> Self {
+        let offset = self.offset.saturating_add(1);
+        if byte == b'\n' {
+            Self {
+                offset,
+                line: self.line.saturating_add(1),
+                column: 1,
+            }
+        } else {
+            let column = if byte & 0xC0 == 0x80 {
+                self.column
+            } else {
+                self.column.saturating_add(1)
+            };
+            Self {
+                offset,
+                line: self.line,
+                column,
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Candidate {
-    start: u64,
+    start: Position,
     bytes: Vec<u8>,
     reference_like: bool,
 }
 
+/// Parse one complete `{{ @path ... }}` reference exactly as template
+/// expansion would, without resolving its targets.
+///
+/// ```
+/// use std::path::PathBuf;
+/// use textcon::{ReferenceProcessor, parse_reference};
+///
+/// let reference = parse_reference(r"{{ @[logs, a\,b.rs] | markdown | reverse }}")?;
+/// assert_eq!(reference.paths, [PathBuf::from("logs"), PathBuf::from("a,b.rs")]);
+/// assert!(reference.list);
+/// assert_eq!(reference.processor, ReferenceProcessor::Markdown);
+/// assert!(reference.options.reverse);
+/// assert!(parse_reference("{{ @src | tree | raw }}").is_err());
+/// # Ok::<(), textcon::TextconError>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns [`TextconError::TemplateSyntax`] when the reference is malformed,
+/// or when `reference` holds anything besides a single reference placeholder.
+pub fn parse_reference(reference: &str) -> Result<ParsedReference> {
+    let mut literal = Vec::new();
+    let mut placeholders = Vec::new();
+    expand(
+        &mut reference.as_bytes(),
+        &mut literal,
+        "reference",
+        false,
+        |placeholder, _| {
+            placeholders.push(placeholder);
+            Ok(())
+        },
+    )?;
+    match placeholders.pop() {
+        Some(Placeholder::Reference(parsed)) if placeholders.is_empty() && literal.is_empty() => {
+            Ok(parsed)
+        }
+        _ => Err(TextconError::TemplateSyntax {
+            offset: 0,
+            message: "expected exactly one {{ @path }} reference".to_owned(),
+        }),
+    }
+}
+
+/// Copy a template from `input` to `output` with every placeholder written
+/// in canonical form.
+///
+/// A canonical placeholder has one space inside the braces, none after `@`
+/// or `$`, and one space around each stage separator, as in
+/// `{{ @src | grep=TODO | tree }}`. Paths, stages, and literal text are
+/// otherwise copied unchanged, so the template expands exactly as before.
+///
+/// ```
+/// let mut output = Vec::new();
+/// textcon::format_template(&mut &b"A {{@file.txt}} {{  $name}} {{ @ src  |tree }}"[..], &mut output)?;
+/// assert_eq!(output, b"A {{ @file.txt }} {{ $name }} {{ @src | tree }}");
+/// # Ok::<(), textcon::TextconError>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns [`TextconError::TemplateSyntax`] for a malformed placeholder, and
+/// input and output failures.
+pub fn format_template<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    let mut template = Vec::new();
+    input
+        .read_to_end(&mut template)
+        .map_err(|source| TextconError::Input {
+            name: "template input".to_owned(),
+            source,
+        })?;
+    let mut spans = Vec::new();
+    expand(
+        &mut template.as_slice(),
+        &mut io::sink(),
+        "template input",
+        false,
+        |placeholder, _| {
+            match placeholder {
+                Placeholder::Reference(reference) => {
+                    let start = usize::try_from(reference.offset).unwrap_or(usize::MAX);
+                    let (length, canonical) = canonical_reference(&template[start..]);
+                    spans.push((start, start + length, canonical));
+                }
+                Placeholder::Variable(variable) => {
+                    let start = usize::try_from(variable.offset).unwrap_or(usize::MAX);
+                    let canonical = [b"{{ $", variable.name.as_bytes(), b" }}"].concat();
+                    spans.push((start, start + variable.source.len(), canonical));
+                }
+            }
+            Ok(())
+        },
+    )?;
+    let mut copied = 0;
+    for (start, end, canonical) in spans {
+        output
+            .write_all(&template[copied..start])
+            .and_then(|()| output.write_all(&canonical))
+            .map_err(TextconError::output)?;
+        copied = end;
+    }
+    output
+        .write_all(&template[copied..])
+        .map_err(TextconError::output)
+}
+
+/// The length and canonical form of the reference that begins `template`.
+fn canonical_reference(template: &[u8]) -> (usize, Vec<u8>) {
+    let close = (2..template.len())
+        .find(|&index| template[index..].starts_with(b"}}") && !is_escaped(template, index))
+        .unwrap_or(template.len());
+    let inner = trim_ascii(&template[2..close]);
+    let inner = &inner[1..];
+    let mut canonical = b"{{ @".to_vec();
+    let mut segment = 0;
+    for index in 0..inner.len() {
+        if inner[index] == b'|' && !is_escaped(inner, index) && index > 0 && is_ws(inner[index - 1])
+        {
+            canonical.extend_from_slice(trim_ascii(&inner[segment..index]));
+            canonical.extend_from_slice(b" | ");
+            segment = index + 1;
+        }
+    }
+    canonical.extend_from_slice(trim_ascii(&inner[segment..]));
+    canonical.extend_from_slice(b" }}");
+    (close + 2, canonical)
+}
+
+/// Copy `reader` to `writer`, handing each placeholder to `on_placeholder`.
+///
+/// With `show_context`, a [`TextconError::Located`] failure stops expansion
+/// but not reading: the input is read on to the end of the line after the
+/// failing reference, and the surrounding lines are attached to the error.
 pub(crate) fn expand<R, W, F>(
     reader: &mut R,
     writer: &mut W,
     input_name: &str,
-    mut on_reference: F,
-) -> Result<()>
+    show_context: bool,
+    mut on_placeholder: F,
+) -> Result<u64>
 where
     R: Read,
     W: Write,
-    F: FnMut(ParsedReference, &mut W) -> Result<()>,
+    F: FnMut(Placeholder, &mut W) -> Result<()>,
 {
-    let mut scanner = Scanner::new(writer, &mut on_reference);
+    let mut scanner = Scanner::new(writer, &mut on_placeholder);
+    let mut context = show_context.then(Context::new);
+    let mut failure = None;
     let mut buffer = vec![0_u8; INPUT_BUFFER_SIZE].into_boxed_slice();
-    let mut offset = 0_u64;
-    loop {
+    let mut position = Position::START;
+    'reading: loop {
         let count = match reader.read(&mut buffer) {
             Ok(0) => break,
             Ok(count) => count,
             Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) if failure.is_some() => break,
             Err(source) => {
                 return Err(TextconError::Input {
                     name: input_name.to_owned(),
@@ -58,31 +363,116 @@ where
             }
         };
         for &byte in &buffer[..count] {
-            scanner.feed(byte, offset)?;
-            offset = offset.saturating_add(1);
+            if let Some((_, line)) = failure
+                && position.line > line + 1
+            {
+                break 'reading;
+            }
+            if let Some(context) = &mut context {
+                context.record(byte, position);
+                if failure.is_none() {
+                    match scanner.feed(byte, position) {
+                        Err(error @ TextconError::Located { line, .. }) => {
+                            failure = Some((error, line));
+                        }
+                        result => result?,
+                    }
+                }
+            } else {
+                scanner.feed(byte, position)?;
+            }
+            position = position.after(byte);
+        }
+    }
+    if let (Some((error, line)), Some(context)) = (failure, context) {
+        return Err(error.with_context(context.snippet(line)));
+    }
+    scanner.finish()?;
+    Ok(position.offset)
+}
+
+/// The numbered lines of `template` around `line`, as [`expand`] attaches
+/// them to a failure with `show_context`.
+pub(crate) fn context_snippet(template: &[u8], line: u64) -> String {
+    let mut context = Context::new();
+    let mut position = Position::START;
+    for &byte in template {
+        if position.line > line + 1 {
+            break;
         }
+        context.record(byte, position);
+        position = position.after(byte);
+    }
+    context.snippet(line)
+}
+
+/// The most recent template lines, kept to show around a failing reference.
+struct Context {
+    /// Line numbers and leading bytes, oldest first.
+    lines: VecDeque<(u64, Vec<u8>)>,
+}
+
+impl Context {
+    fn new() -> Self {
+        Self {
+            lines: VecDeque::from([(1, Vec::new())]),
+        }
+    }
+
+    fn record(&mut self, byte: u8, position: Position) {
+        if byte == b'\n' {
+            if self.lines.len() == CONTEXT_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back((position.line + 1, Vec::new()));
+        } else if let Some((_, text)) = self.lines.back_mut()
+            && text.len() <= CONTEXT_LINE_BYTES
+        {
+            text.push(byte);
+        }
+    }
+
+    /// Number the lines before, at, and after `line`, marking `line`.
+    fn snippet(&self, line: u64) -> String {
+        let width = (line + 1).to_string().len().max(3);
+        self.lines
+            .iter()
+            .filter(|(number, _)| number.abs_diff(line) <= 1)
+            .map(|(number, text)| {
+                let (text, ellipsis) = if text.len() > CONTEXT_LINE_BYTES {
+                    (&text[..CONTEXT_LINE_BYTES], "...")
+                } else {
+                    (text.strip_suffix(b"\r").unwrap_or(text), "")
+                };
+                let marker = if *number == line { "  <-- here" } else { "" };
+                format!(
+                    "{number:>width$} | {}{ellipsis}{marker}",
+                    String::from_utf8_lossy(text)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
-    scanner.finish()
 }
 
 struct Scanner<'a, W, F> {
     writer: &'a mut W,
-    on_reference: &'a mut F,
+    on_placeholder: &'a mut F,
     literal: Vec<u8>,
-    first_brace: Option<u64>,
+    first_brace: Option<Position>,
     candidate: Option<Candidate>,
-    replay: VecDeque<(u8, u64)>,
+    replay: VecDeque<(u8, Position)>,
 }
 
 impl<'a, W, F> Scanner<'a, W, F>
 where
     W: Write,
-    F: FnMut(ParsedReference, &mut W) -> Result<()>,
+    F: FnMut(Placeholder, &mut W) -> Result<()>,
 {
-    fn new(writer: &'a mut W, on_reference: &'a mut F) -> Self {
+    fn new(writer: &'a mut W, on_placeholder: &'a mut F) -> Self {
         Self {
             writer,
-            on_reference,
+            on_placeholder,
             literal: Vec::with_capacity(LITERAL_BUFFER_SIZE),
             first_brace: None,
             candidate: None,
@@ -90,20 +480,20 @@ where
         }
     }
 
-    fn feed(&mut self, byte: u8, offset: u64) -> Result<()> {
-        self.replay.push_back((byte, offset));
-        while let Some((next, next_offset)) = self.replay.pop_front() {
+    fn feed(&mut self, byte: u8, position: Position) -> Result<()> {
+        self.replay.push_back((byte, position));
+        while let Some((next, next_position)) = self.replay.pop_front() {
             if self.candidate.is_some() {
-                self.feed_candidate(next, next_offset)?;
+                self.feed_candidate(next, next_position)?;
             } else {
-                self.feed_literal(next, next_offset)?;
+                self.feed_literal(next, next_position)?;
             }
         }
         Ok(())
     }
 
-    fn feed_literal(&mut self, byte: u8, offset: u64) -> Result<()> {
-        if let Some(first_offset) = self.first_brace.take() {
+    fn feed_literal(&mut self, byte: u8, position: Position) -> Result<()> {
+        if let Some(first_position) = self.first_brace.take() {
             if byte == b'{' {
                 let slash_count = self
                     .literal
@@ -120,33 +510,33 @@ where
                 } else {
                     self.flush_literal()?;
                     self.candidate = Some(Candidate {
-                        start: first_offset,
+                        start: first_position,
                         bytes: vec![b'{', b'{'],
                         reference_like: false,
                     });
                 }
             } else {
                 self.push_literal(b'{')?;
-                self.feed_literal(byte, offset)?;
+                self.feed_literal(byte, position)?;
             }
             return Ok(());
         }
 
         if byte == b'{' {
-            self.first_brace = Some(offset);
+            self.first_brace = Some(position);
         } else {
             self.push_literal(byte)?;
         }
         Ok(())
     }
 
-    fn feed_candidate(&mut self, byte: u8, offset: u64) -> Result<()> {
+    fn feed_candidate(&mut self, byte: u8, position: Position) -> Result<()> {
         let candidate = self.candidate.as_mut().expect("candidate exists");
         candidate.bytes.push(byte);
         if candidate.bytes.len() > MAX_REFERENCE_BYTES {
             if candidate.reference_like {
                 return Err(TextconError::TemplateSyntax {
-                    offset: candidate.start,
+                    offset: candidate.start.offset,
                     message: format!("reference exceeds the {MAX_REFERENCE_BYTES}-byte limit"),
                 });
             }
@@ -157,7 +547,9 @@ where
         if !candidate.reference_like {
             let prefix = &candidate.bytes[2..];
             if let Some(&last) = prefix.last() {
-                if last == b'@' && prefix[..prefix.len() - 1].iter().all(|b| is_ws(*b)) {
+                if matches!(last, b'@' | b'$')
+                    && prefix[..prefix.len() - 1].iter().all(|b| is_ws(*b))
+                {
                     candidate.reference_like = true;
                 } else if !is_ws(last) {
                     self.release_unrelated_candidate()?;
@@ -170,21 +562,22 @@ where
             let length = candidate.bytes.len();
             if candidate.bytes[length - 2] == b'}' && !is_escaped(&candidate.bytes, length - 2) {
                 let completed = self.candidate.take().expect("candidate exists");
-                let parsed = parse_reference(&completed)?;
+                let parsed = parse_placeholder(&completed)?;
                 self.flush_literal()?;
-                (self.on_reference)(parsed, self.writer)?;
+                (self.on_placeholder)(parsed, self.writer)?;
             }
         }
-        let _ = offset;
+        let _ = position;
         Ok(())
     }
 
     fn release_unrelated_candidate(&mut self) -> Result<()> {
         let candidate = self.candidate.take().expect("candidate exists");
-        let start = candidate.start;
         self.push_literal(b'{')?;
-        for (index, &byte) in candidate.bytes[1..].iter().enumerate().rev() {
-            self.replay.push_front((byte, start + 1 + index as u64));
+        let mut position = candidate.start.after(b'{');
+        for (index, &byte) in candidate.bytes[1..].iter().enumerate() {
+            self.replay.insert(index, (byte, position));
+            position = position.after(byte);
         }
         Ok(())
     }
@@ -208,15 +601,14 @@ where
     }
 
     fn finish(mut self) -> Result<()> {
-        if let Some(offset) = self.first_brace.take() {
-            let _ = offset;
+        if self.first_brace.take().is_some() {
             self.push_literal(b'{')?;
         }
         if let Some(candidate) = self.candidate.take() {
             if candidate.reference_like {
                 return Err(TextconError::TemplateSyntax {
-                    offset: candidate.start,
-                    message: "unterminated reference".to_owned(),
+                    offset: candidate.start.offset,
+                    message: "unterminated placeholder".to_owned(),
                 });
             }
             for byte in candidate.bytes {
@@ -227,25 +619,59 @@ where
     }
 }
 
-fn parse_reference(candidate: &Candidate) -> Result<ParsedReference> {
+fn parse_placeholder(candidate: &Candidate) -> Result<Placeholder> {
     let inner = &candidate.bytes[2..candidate.bytes.len() - 2];
     let mut start = 0;
     while start < inner.len() && is_ws(inner[start]) {
         start += 1;
     }
+    if inner.get(start) == Some(&b'$') {
+        return parse_variable(candidate, &inner[start + 1..]).map(Placeholder::Variable);
+    }
     debug_assert_eq!(inner.get(start), Some(&b'@'));
-    start += 1;
+    reference_from_candidate(candidate, inner, start + 1).map(Placeholder::Reference)
+}
+
+fn parse_variable(candidate: &Candidate, body: &[u8]) -> Result<ParsedVariable> {
+    let name = trim_ascii(body);
+    if name.is_empty() {
+        return syntax(candidate, "variable name is empty");
+    }
+    if !is_variable_name(name) {
+        return syntax(
+            candidate,
+            &format!("invalid variable name '{}'", String::from_utf8_lossy(name)),
+        );
+    }
+    Ok(ParsedVariable {
+        name: String::from_utf8_lossy(name).into_owned(),
+        source: candidate.bytes.clone(),
+        offset: candidate.start.offset,
+    })
+}
+
+/// Variable names are ASCII identifiers: a letter or underscore followed by
+/// letters, digits, or underscores.
+pub(crate) fn is_variable_name(name: &[u8]) -> bool {
+    name.first()
+        .is_some_and(|first| first.is_ascii_alphabetic() || *first == b'_')
+        && name
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b'_')
+}
 
-    let mut pipe = None;
+fn reference_from_candidate(
+    candidate: &Candidate,
+    inner: &[u8],
+    start: usize,
+) -> Result<ParsedReference> {
+    let mut pipes = Vec::new();
     let mut index = start;
     while index < inner.len() {
         if inner[index] == b'|' && !is_escaped(inner, index) {
             let has_separator_space = index > start && is_ws(inner[index - 1]);
             if has_separator_space {
-                if pipe.is_some() {
-                    return syntax(candidate, "multiple reference processors");
-                }
-                pipe = Some(index);
+                pipes.push(index);
             }
         } else if inner[index] == b'}' && !is_escaped(inner, index) {
             return syntax(candidate, "unescaped '}' in reference path");
@@ -253,65 +679,346 @@ fn parse_reference(candidate: &Candidate) -> Result<ParsedReference> {
         index += 1;
     }
 
-    let (raw_path, processor) = if let Some(pipe_index) = pipe {
-        let path = trim_ascii(&inner[start..pipe_index]);
-        let processor_bytes = trim_ascii(&inner[pipe_index + 1..]);
-        let processor = match processor_bytes {
-            b"raw" => ReferenceProcessor::Raw,
-            b"markdown" => ReferenceProcessor::Markdown,
-            b"" => return syntax(candidate, "missing reference processor"),
-            _ => {
+    let mut raw_path = trim_ascii(&inner[start..pipes.first().copied().unwrap_or(inner.len())]);
+    let mut processor = None;
+    let mut options = ReferenceOptions::default();
+    if raw_path.first() != Some(&b'[')
+        && let Some(anchor) = find_line_anchor(raw_path)
+    {
+        let Some(range) = parse_line_range(&raw_path[anchor + 2..], b"-L") else {
+            return syntax(
+                candidate,
+                &format!(
+                    "invalid line anchor '{}'",
+                    String::from_utf8_lossy(&raw_path[anchor..])
+                ),
+            );
+        };
+        options.lines = Some(range);
+        raw_path = &raw_path[..anchor];
+    }
+    for (position, &pipe) in pipes.iter().enumerate() {
+        let end = pipes.get(position + 1).copied().unwrap_or(inner.len());
+        parse_stage(
+            candidate,
+            trim_ascii(&inner[pipe + 1..end]),
+            &mut processor,
+            &mut options,
+        )?;
+    }
+    let processor = processor.unwrap_or(ReferenceProcessor::Inherit);
+    if (!options.filters.is_empty()
+        || options.lines.is_some()
+        || options.limit.is_some()
+        || options.section.is_some()
+        || options.transform.is_some())
+        && matches!(
+            processor,
+            ReferenceProcessor::Mermaid
+                | ReferenceProcessor::Summary
+                | ReferenceProcessor::Template
+                | ReferenceProcessor::Tree
+        )
+    {
+        return syntax(
+            candidate,
+            "sections, line ranges, line filters, limits, and transforms apply only to raw and Markdown references",
+        );
+    }
+    if options.section.is_some() && options.transform.is_some() {
+        return syntax(candidate, "a transform cannot be combined with a section");
+    }
+
+    let list = raw_path.first() == Some(&b'[');
+    let paths = if list {
+        let length = raw_path.len();
+        if length < 2 || raw_path[length - 1] != b']' || is_escaped(raw_path, length - 1) {
+            return syntax(candidate, "unterminated reference list");
+        }
+        split_list(&raw_path[1..length - 1])
+            .into_iter()
+            .map(|member| decode_path(candidate, trim_ascii(member), b"|},]"))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        vec![decode_path(candidate, raw_path, b"|}#")?]
+    };
+
+    Ok(ParsedReference {
+        paths,
+        list,
+        processor,
+        options,
+        offset: candidate.start.offset,
+        line: candidate.start.line,
+        column: candidate.start.column,
+    })
+}
+
+/// Apply one ` | stage` of a reference: at most one processor, each flag
+/// modifier at most once, and any number of `name=value` line filters.
+fn parse_stage(
+    candidate: &Candidate,
+    stage: &[u8],
+    processor: &mut Option<ReferenceProcessor>,
+    options: &mut ReferenceOptions,
+) -> Result<()> {
+    if let Some(equals) = stage.iter().position(|&byte| byte == b'=') {
+        return parse_option(
+            candidate,
+            &stage[..equals],
+            trim_ascii(&stage[equals + 1..]),
+            options,
+        );
+    }
+    let selected = match stage {
+        b"raw" => ReferenceProcessor::Raw,
+        b"markdown" => ReferenceProcessor::Markdown,
+        b"template" => ReferenceProcessor::Template,
+        b"tree" => ReferenceProcessor::Tree,
+        b"mermaid" => ReferenceProcessor::Mermaid,
+        b"summary" => ReferenceProcessor::Summary,
+        b"reverse" => {
+            if options.reverse {
+                return syntax(candidate, "duplicate reference option 'reverse'");
+            }
+            options.reverse = true;
+            return Ok(());
+        }
+        b"" => return syntax(candidate, "missing reference processor"),
+        _ => {
+            return syntax(
+                candidate,
+                &format!(
+                    "unknown reference processor '{}'",
+                    String::from_utf8_lossy(stage)
+                ),
+            );
+        }
+    };
+    if processor.replace(selected).is_some() {
+        return syntax(candidate, "multiple reference processors");
+    }
+    Ok(())
+}
+
+/// Apply one ` | name=value` modifier stage.
+fn parse_option(
+    candidate: &Candidate,
+    name: &[u8],
+    value: &[u8],
+    options: &mut ReferenceOptions,
+) -> Result<()> {
+    let inverted = match name {
+        b"grep" => false,
+        b"grep-v" => true,
+        b"head" | b"tail" => {
+            let count = std::str::from_utf8(value)
+                .ok()
+                .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_digit()))
+                .and_then(|digits| digits.parse().ok());
+            let Some(count) = count else {
+                return syntax(
+                    candidate,
+                    &format!("invalid line count '{}'", String::from_utf8_lossy(value)),
+                );
+            };
+            if options.limit.is_some() {
+                return syntax(candidate, "multiple line limits");
+            }
+            options.limit = Some(if name == b"head" {
+                LineLimit::Head(count)
+            } else {
+                LineLimit::Tail(count)
+            });
+            return Ok(());
+        }
+        b"lines" => return set_line_range(candidate, value, options),
+        b"base" => {
+            if !is_variable_name(value) {
                 return syntax(
                     candidate,
                     &format!(
-                        "unknown reference processor '{}'",
-                        String::from_utf8_lossy(processor_bytes)
+                        "invalid base alias name '{}'",
+                        String::from_utf8_lossy(value)
                     ),
                 );
             }
-        };
-        (path, processor)
-    } else {
-        (trim_ascii(&inner[start..]), ReferenceProcessor::Inherit)
+            let name = String::from_utf8_lossy(value).into_owned();
+            if options.base.replace(name).is_some() {
+                return syntax(candidate, "multiple reference bases");
+            }
+            return Ok(());
+        }
+        b"transform" => {
+            let transform = match value {
+                b"pretty" => Transform::Pretty,
+                b"minify" => Transform::Minify,
+                _ => {
+                    return syntax(
+                        candidate,
+                        &format!("unknown transform '{}'", String::from_utf8_lossy(value)),
+                    );
+                }
+            };
+            if options.transform.replace(transform).is_some() {
+                return syntax(candidate, "multiple reference transforms");
+            }
+            return Ok(());
+        }
+        b"section" => {
+            if value.is_empty() {
+                return syntax(candidate, "section name is empty");
+            }
+            let Ok(name) = String::from_utf8(unescape(value, b"|}")) else {
+                return syntax(candidate, "section name is not valid UTF-8");
+            };
+            if options.section.replace(name).is_some() {
+                return syntax(candidate, "multiple reference sections");
+            }
+            return Ok(());
+        }
+        _ => {
+            return syntax(
+                candidate,
+                &format!(
+                    "unknown reference option '{}'",
+                    String::from_utf8_lossy(name)
+                ),
+            );
+        }
     };
+    if value.is_empty() {
+        return syntax(candidate, "line filter pattern is empty");
+    }
+    let Ok(pattern) = String::from_utf8(unescape(value, b"|}")) else {
+        return syntax(candidate, "line filter pattern is not valid UTF-8");
+    };
+    let filter = LineFilter::new(&pattern, inverted).map_err(|error| TextconError::Regex {
+        offset: candidate.start.offset,
+        message: error.to_string(),
+    })?;
+    options.filters.push(filter);
+    Ok(())
+}
+
+/// Split bracketed list contents at unescaped commas.
+fn split_list(input: &[u8]) -> Vec<&[u8]> {
+    let mut members = Vec::new();
+    let mut start = 0;
+    for index in 0..input.len() {
+        if input[index] == b',' && !is_escaped(input, index) {
+            members.push(&input[start..index]);
+            start = index + 1;
+        }
+    }
+    members.push(&input[start..]);
+    members
+}
 
+fn decode_path(candidate: &Candidate, raw_path: &[u8], escapable: &[u8]) -> Result<PathBuf> {
     if raw_path.is_empty() {
         return syntax(
             candidate,
             "reference path is empty; use '.' for the base directory",
         );
     }
-    let path_bytes = unescape_path(raw_path);
+    let path_bytes = unescape_path(raw_path, escapable);
     if path_bytes.contains(&0) {
         return syntax(candidate, "reference path contains NUL");
     }
     let path_string = String::from_utf8(path_bytes).map_err(|_| TextconError::TemplateSyntax {
-        offset: candidate.start,
+        offset: candidate.start.offset,
         message: "reference path is not valid UTF-8".to_owned(),
     })?;
+    Ok(PathBuf::from(path_string))
+}
 
-    Ok(ParsedReference {
-        path: PathBuf::from(path_string),
-        processor,
-        offset: candidate.start,
-    })
+/// Apply a ` | lines=N-M` or ` | lines=N` modifier stage.
+fn set_line_range(
+    candidate: &Candidate,
+    value: &[u8],
+    options: &mut ReferenceOptions,
+) -> Result<()> {
+    let Some(range) = parse_line_range(value, b"-") else {
+        return syntax(
+            candidate,
+            &format!("invalid line range '{}'", String::from_utf8_lossy(value)),
+        );
+    };
+    if options.lines.replace(range).is_some() {
+        return syntax(candidate, "multiple line ranges");
+    }
+    Ok(())
+}
+
+/// Where a trailing, unescaped `#L` anchor that is followed only by digits,
+/// `-`, and `L` begins in a single reference path.
+fn find_line_anchor(raw_path: &[u8]) -> Option<usize> {
+    let anchor = raw_path.windows(2).rposition(|pair| pair == b"#L")?;
+    let suffix = &raw_path[anchor + 2..];
+    (anchor > 0
+        && !is_escaped(raw_path, anchor)
+        && suffix.first().is_some_and(u8::is_ascii_digit)
+        && suffix
+            .iter()
+            .all(|&byte| byte.is_ascii_digit() || byte == b'-' || byte == b'L'))
+    .then_some(anchor)
+}
+
+/// Parse `N` or `N{separator}M`, with `1 <= N <= M`.
+fn parse_line_range(value: &[u8], separator: &[u8]) -> Option<LineRange> {
+    let number = |digits: &[u8]| {
+        std::str::from_utf8(digits)
+            .ok()
+            .filter(|digits| {
+                !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit())
+            })?
+            .parse::<usize>()
+            .ok()
+            .filter(|&line| line > 0)
+    };
+    let (start, end) = if let Some(split) = value
+        .windows(separator.len())
+        .position(|window| window == separator)
+    {
+        (
+            number(&value[..split])?,
+            number(&value[split + separator.len()..])?,
+        )
+    } else {
+        let line = number(value)?;
+        (line, line)
+    };
+    (start <= end).then_some(LineRange { start, end })
 }
 
 fn syntax<T>(candidate: &Candidate, message: &str) -> Result<T> {
     Err(TextconError::TemplateSyntax {
-        offset: candidate.start,
+        offset: candidate.start.offset,
         message: message.to_owned(),
     })
 }
 
-fn unescape_path(input: &[u8]) -> Vec<u8> {
+/// Remove the backslash before any `escapable` byte, and before a leading
+/// `[` so a path may begin with a literal bracket.
+fn unescape_path(input: &[u8], escapable: &[u8]) -> Vec<u8> {
+    if input.starts_with(br"\[") {
+        let mut output = vec![b'['];
+        output.extend(unescape(&input[2..], escapable));
+        return output;
+    }
+    unescape(input, escapable)
+}
+
+/// Remove the backslash before any `escapable` byte.
+fn unescape(input: &[u8], escapable: &[u8]) -> Vec<u8> {
     let mut output = Vec::with_capacity(input.len());
     let mut index = 0;
     while index < input.len() {
         if input[index] == b'\\'
             && input
                 .get(index + 1)
-                .is_some_and(|next| matches!(next, b'|' | b'}'))
+                .is_some_and(|next| escapable.contains(next))
         {
             output.push(input[index + 1]);
             index += 2;
@@ -369,11 +1076,23 @@ mod tests {
         }
     }
 
-    fn run(input: &[u8]) -> Result<(Vec<u8>, Vec<ParsedReference>)> {
+    fn run(input: &[u8]) -> Result<(Vec<u8>, Vec<Placeholder>)> {
         run_chunked(input, input.len().max(1))
     }
 
-    fn run_chunked(input: &[u8], maximum: usize) -> Result<(Vec<u8>, Vec<ParsedReference>)> {
+    fn run_references(input: &[u8]) -> Result<(Vec<u8>, Vec<ParsedReference>)> {
+        let (output, placeholders) = run(input)?;
+        let references = placeholders
+            .into_iter()
+            .filter_map(|placeholder| match placeholder {
+                Placeholder::Reference(reference) => Some(reference),
+                Placeholder::Variable(_) => None,
+            })
+            .collect();
+        Ok((output, references))
+    }
+
+    fn run_chunked(input: &[u8], maximum: usize) -> Result<(Vec<u8>, Vec<Placeholder>)> {
         let mut output = Vec::new();
         let mut refs = Vec::new();
         expand(
@@ -383,8 +1102,9 @@ mod tests {
             },
             &mut output,
             "test",
-            |reference, _| {
-                refs.push(reference);
+            false,
+            |placeholder, _| {
+                refs.push(placeholder);
                 Ok(())
             },
         )?;
@@ -393,21 +1113,174 @@ mod tests {
 
     #[test]
     fn parses_processors_and_literal_pipes() {
-        let (_, refs) =
-            run(b"{{ @a|b }} {{ @dir | markdown }} {{ @x | raw }} {{ @  spaced  }}").unwrap();
-        assert_eq!(refs[0].path, PathBuf::from("a|b"));
+        let (_, refs) = run_references(
+            b"{{ @a|b }} {{ @dir | markdown }} {{ @x | raw }} {{ @  spaced  }} {{ @d | mermaid }} \
+              {{ @d | summary }}",
+        )
+        .unwrap();
+        assert_eq!(refs[0].paths, [PathBuf::from("a|b")]);
         assert_eq!(refs[0].processor, ReferenceProcessor::Inherit);
         assert_eq!(refs[1].processor, ReferenceProcessor::Markdown);
         assert_eq!(refs[2].processor, ReferenceProcessor::Raw);
-        assert_eq!(refs[3].path, PathBuf::from("spaced"));
+        assert_eq!(refs[3].paths, [PathBuf::from("spaced")]);
+        assert_eq!(refs[4].processor, ReferenceProcessor::Mermaid);
+        assert_eq!(refs[5].processor, ReferenceProcessor::Summary);
+    }
+
+    #[test]
+    fn modifier_stages_combine_with_one_processor() {
+        let (_, refs) =
+            run_references(b"{{ @logs | reverse }} {{ @logs | markdown | reverse }}").unwrap();
+        assert_eq!(refs[0].processor, ReferenceProcessor::Inherit);
+        assert!(refs[0].options.reverse);
+        assert_eq!(refs[1].processor, ReferenceProcessor::Markdown);
+        assert!(refs[1].options.reverse);
+        assert!(run(b"{{ @logs | raw | markdown }}").is_err());
+        assert!(run(b"{{ @logs | reverse | reverse }}").is_err());
+        assert!(run(b"{{ @logs | reverse | }}").is_err());
+    }
+
+    #[test]
+    fn line_limits_are_counts_and_exclusive() {
+        let (_, refs) =
+            run_references(b"{{ @app.log | grep=ERROR | head=20 }} {{ @app.log | tail=0 }}")
+                .unwrap();
+        assert_eq!(refs[0].options.limit, Some(LineLimit::Head(20)));
+        assert_eq!(refs[0].options.filters.len(), 1);
+        assert_eq!(refs[1].options.limit, Some(LineLimit::Tail(0)));
+        assert!(run(b"{{ @app.log | head=3 | tail=3 }}").is_err());
+        assert!(run(b"{{ @app.log | head=3 | head=4 }}").is_err());
+        assert!(run(b"{{ @app.log | head=-1 }}").is_err());
+        assert!(run(b"{{ @app.log | head=+1 }}").is_err());
+        assert!(run(b"{{ @logs | tail=5 | tree }}").is_err());
+    }
+
+    #[test]
+    fn formatting_respaces_placeholders_and_keeps_their_meaning() {
+        let template: &[u8] = br"\{{ @kept  }} {{@a\}}} {{ @ b|c  |raw  |  grep=x\| y  }}{{$v}}";
+        let mut formatted = Vec::new();
+        format_template(&mut &template[..], &mut formatted).unwrap();
+        assert_eq!(
+            formatted,
+            br"\{{ @kept  }} {{ @a\} }} {{ @b|c | raw | grep=x\| y }}{{ $v }}"
+        );
+        let meaning = |input: &[u8]| {
+            let (literal, placeholders) = run(input).unwrap();
+            let placeholders = placeholders
+                .into_iter()
+                .map(|placeholder| match placeholder {
+                    Placeholder::Reference(reference) => format!(
+                        "{:?} {:?} {:?}",
+                        reference.paths, reference.processor, reference.options
+                    ),
+                    Placeholder::Variable(variable) => variable.name,
+                })
+                .collect::<Vec<_>>();
+            (literal, placeholders)
+        };
+        assert_eq!(meaning(&formatted), meaning(template));
+
+        let mut again = Vec::new();
+        format_template(&mut formatted.as_slice(), &mut again).unwrap();
+        assert_eq!(again, formatted);
+        assert!(format_template(&mut &b"{{ @a | bogus }}"[..], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn line_ranges_come_from_stages_or_path_anchors() {
+        let range = Some(LineRange { start: 5, end: 10 });
+        let (_, refs) = run_references(
+            b"{{ @main.rs | lines=5-10 }} {{ @main.rs#L5-L10 | raw }} {{ @main.rs#L7 }}",
+        )
+        .unwrap();
+        assert_eq!(refs[0].options.lines, range);
+        assert_eq!(refs[1].paths, [PathBuf::from("main.rs")]);
+        assert_eq!(refs[1].options.lines, range);
+        assert_eq!(refs[2].options.lines, Some(LineRange { start: 7, end: 7 }));
+
+        let (_, refs) =
+            run_references(br"{{ @notes#Lx }} {{ @a\#L5 }} {{ @[a#L5] }} {{ @./a#L5 }}").unwrap();
+        assert_eq!(refs[0].paths, [PathBuf::from("notes#Lx")]);
+        assert_eq!(refs[0].options.lines, None);
+        assert_eq!(refs[1].paths, [PathBuf::from("a#L5")]);
+        assert_eq!(refs[2].paths, [PathBuf::from("a#L5")]);
+        assert_eq!(refs[3].paths, [PathBuf::from("./a")]);
+        assert_eq!(refs[3].options.lines, Some(LineRange { start: 5, end: 5 }));
+        assert!(run(b"{{ @main.rs#L10-L5 }}").is_err());
+        assert!(run(b"{{ @main.rs#L0 }}").is_err());
+        assert!(run(b"{{ @main.rs | lines=3- }}").is_err());
+        assert!(run(b"{{ @main.rs#L1 | lines=2 }}").is_err());
+        assert!(run(b"{{ @src#L1 | tree }}").is_err());
+    }
+
+    #[test]
+    fn section_names_a_single_region() {
+        let (_, refs) = run_references(b"{{ @lib.rs | section=public api | markdown }}").unwrap();
+        assert_eq!(refs[0].options.section.as_deref(), Some("public api"));
+        assert!(run(b"{{ @lib.rs | section=a | section=b }}").is_err());
+        assert!(run(b"{{ @lib.rs | section= }}").is_err());
+    }
+
+    #[test]
+    fn transform_stage_names_a_json_rewrite() {
+        let (_, refs) = run_references(b"{{ @data.json | transform=pretty | head=3 }}").unwrap();
+        assert_eq!(refs[0].options.transform, Some(Transform::Pretty));
+        assert_eq!(refs[0].options.limit, Some(LineLimit::Head(3)));
+        let (_, refs) = run_references(b"{{ @data.json | raw | transform=minify }}").unwrap();
+        assert_eq!(refs[0].options.transform, Some(Transform::Minify));
+        assert!(run(b"{{ @data.json | transform=yaml }}").is_err());
+        assert!(run(b"{{ @data.json | transform=pretty | transform=minify }}").is_err());
+        assert!(run(b"{{ @data | tree | transform=pretty }}").is_err());
+        assert!(run(b"{{ @data.json | transform=pretty | section=a }}").is_err());
+    }
+
+    #[test]
+    fn base_names_one_alias() {
+        let (_, refs) = run_references(b"{{ @src | base=proj_2 | tree }}").unwrap();
+        assert_eq!(refs[0].options.base.as_deref(), Some("proj_2"));
+        assert_eq!(refs[0].processor, ReferenceProcessor::Tree);
+        assert!(run(b"{{ @src | base=a | base=b }}").is_err());
+        assert!(run(b"{{ @src | base=../lib }}").is_err());
+        assert!(run(b"{{ @src | base= }}").is_err());
+    }
+
+    #[test]
+    fn line_filters_keep_order_escapes_and_reject_bad_patterns() {
+        let (_, refs) =
+            run_references(br"{{ @lib.rs | grep=^pub (fn|struct) | grep-v=\}$ | raw }}").unwrap();
+        let filters = &refs[0].options.filters;
+        assert_eq!(filters[0].pattern(), "^pub (fn|struct)");
+        assert!(!filters[0].is_inverted());
+        assert_eq!(filters[1].pattern(), "}$");
+        assert!(filters[1].is_inverted());
+        assert_eq!(refs[0].processor, ReferenceProcessor::Raw);
+
+        assert!(matches!(
+            run(b"{{ @lib.rs | grep=( }}"),
+            Err(TextconError::Regex { offset: 0, .. })
+        ));
+        assert!(run(b"{{ @src | grep=pub | tree }}").is_err());
+        assert!(run(b"{{ @lib.rs | grep= }}").is_err());
+        assert!(run(b"{{ @lib.rs | sort=3 }}").is_err());
     }
 
     #[test]
     fn escape_and_overlap_are_preserved() {
-        let (output, refs) = run(br"\{{ @literal }} {{{ @real }}}").unwrap();
+        let (output, refs) = run_references(br"\{{ @literal }} {{{ @real }}}").unwrap();
         assert_eq!(output, b"{{ @literal }} {}");
         assert_eq!(refs.len(), 1);
-        assert_eq!(refs[0].path, PathBuf::from("real"));
+        assert_eq!(refs[0].paths, [PathBuf::from("real")]);
+    }
+
+    #[test]
+    fn references_record_line_and_character_column() {
+        let input = "a\n  {{ @one }}\n\u{e9}{{\n x {{ @two }}".as_bytes();
+        let (_, refs) = run_references(input).unwrap();
+        let positions = refs
+            .iter()
+            .map(|reference| (reference.offset, reference.line, reference.column))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, [(4, 2, 3), (23, 4, 4)]);
     }
 
     #[test]
@@ -419,6 +1292,42 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn bracketed_lists_keep_member_order_and_escapes() {
+        let (_, refs) =
+            run_references(br"{{ @[b.rs, a\,1.rs ,c\].rs] | markdown }} {{ @\[x] }}").unwrap();
+        assert_eq!(
+            refs[0].paths,
+            [
+                PathBuf::from("b.rs"),
+                PathBuf::from("a,1.rs"),
+                PathBuf::from("c].rs")
+            ]
+        );
+        assert!(refs[0].list);
+        assert_eq!(refs[0].processor, ReferenceProcessor::Markdown);
+        assert_eq!(refs[1].paths, [PathBuf::from("[x]")]);
+        assert!(!refs[1].list);
+        assert!(run(b"{{ @[a.rs, ] }}").is_err());
+        assert!(run(b"{{ @[a.rs }}").is_err());
+    }
+
+    #[test]
+    fn variables_are_identifiers_with_original_source() {
+        let (output, placeholders) = run(b"a {{  $task_1 }} b \\{{ $literal }}").unwrap();
+        assert_eq!(output, b"a  b {{ $literal }}");
+        assert_eq!(
+            placeholders,
+            vec![Placeholder::Variable(ParsedVariable {
+                name: "task_1".to_owned(),
+                source: b"{{  $task_1 }}".to_vec(),
+                offset: 2,
+            })]
+        );
+        assert!(run(b"{{ $1st }}").is_err());
+        assert!(run(b"{{ $ }}").is_err());
+    }
+
     #[test]
     fn unrelated_braces_pass_through() {
         let (output, refs) = run(b"{{ value }} and {{").unwrap();
@@ -434,6 +1343,7 @@ mod tests {
             b"{{ value }} {{ @dir | markdown }}\r\n",
             b"arbitrary \xff bytes {{ @file }}",
             b"{{ @  spaced  | raw }}",
+            b"{{ $name }}{{ @file }} {{ $ other }}",
         ];
         for fixture in fixtures {
             let expected = run(fixture);
@@ -450,10 +1360,10 @@ mod tests {
 
     #[test]
     fn escape_parity_is_deterministic() {
-        let (output, refs) = run(br"\{{ @a }} \\{{ @b }} \\\{{ @c }}").unwrap();
+        let (output, refs) = run_references(br"\{{ @a }} \\{{ @b }} \\\{{ @c }}").unwrap();
         assert_eq!(output, br"{{ @a }} \ \{{ @c }}");
         assert_eq!(refs.len(), 1);
-        assert_eq!(refs[0].path, PathBuf::from("b"));
+        assert_eq!(refs[0].paths, [PathBuf::from("b")]);
     }
 
     #[test]
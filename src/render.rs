@@ -1,12 +1,19 @@
 #![allow(clippy::missing_const_for_fn, clippy::redundant_pub_crate)]
 
 use std::ffi::OsStr;
-use std::io::{self, Read, Write};
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use sha2::{Digest as _, Sha256};
+
+use crate::engine::FileMarkers;
 use crate::error::{Result, TextconError};
 
 const COPY_BUFFER_SIZE: usize = 64 * 1024;
+/// Leading bytes searched for a NUL when guessing whether a file is binary.
+const BINARY_SNIFF_BYTES: u64 = 8 * 1024;
 
 pub(crate) fn copy_raw<R: Read, W: Write>(
     reader: &mut R,
@@ -34,25 +41,57 @@ pub(crate) fn copy_raw<R: Read, W: Write>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn write_markdown_record<R: Read, W: Write>(
     logical_path: &Path,
     reader: &mut R,
     adaptive: bool,
+    fence: Option<&str>,
+    summary: Option<&str>,
+    modified: Option<SystemTime>,
+    checksum: bool,
     writer: &mut W,
 ) -> Result<()> {
     let label = encode_path(logical_path.as_os_str());
+    let heading = summary.map_or_else(
+        || format!("# `{label}`\n"),
+        |summary| format!("# `{label}` — {summary}\n"),
+    );
     writer
-        .write_all(format!("# `{label}`\n\n").as_bytes())
+        .write_all(heading.as_bytes())
         .map_err(TextconError::output)?;
+    if let Some(modified) = modified {
+        writeln!(writer, "<!-- modified: {} -->", rfc3339(modified))
+            .map_err(TextconError::output)?;
+    }
+    writer.write_all(b"\n").map_err(TextconError::output)?;
 
     let mut tail = TailWriter::new(writer);
-    if adaptive {
-        transform_markdown(reader, &mut tail, &label)?;
+    let digest = if checksum {
+        let mut hashing = HashingReader {
+            inner: reader,
+            hasher: Sha256::new(),
+        };
+        write_record_body(
+            logical_path,
+            &mut hashing,
+            adaptive,
+            fence,
+            &mut tail,
+            &label,
+        )?;
+        Some(hashing.hasher.finalize())
     } else {
-        copy_raw(reader, &mut tail, &label)?;
-    }
+        write_record_body(logical_path, reader, adaptive, fence, &mut tail, &label)?;
+        None
+    };
 
-    if tail.bytes_written() != 0 {
+    if let Some(digest) = digest {
+        if tail.bytes_written() != 0 && tail.trailing_line_endings() == 0 {
+            tail.write_all(b"\n").map_err(TextconError::output)?;
+        }
+        writeln!(tail, "<!-- sha256: {} -->\n", hex(&digest)).map_err(TextconError::output)?;
+    } else if tail.bytes_written() != 0 {
         let endings = tail.trailing_line_endings();
         let missing = if endings == 1 && tail.ends_with_lone_cr() {
             2
@@ -65,20 +104,518 @@ pub(crate) fn write_markdown_record<R: Read, W: Write>(
     Ok(())
 }
 
+fn write_record_body<R: Read, W: Write>(
+    logical_path: &Path,
+    reader: &mut R,
+    adaptive: bool,
+    fence: Option<&str>,
+    tail: &mut TailWriter<'_, W>,
+    label: &str,
+) -> Result<()> {
+    if let Some(fence) = fence {
+        write_fenced(logical_path, reader, fence, tail, label)
+    } else if adaptive {
+        transform_markdown(reader, tail, label)
+    } else {
+        copy_raw(reader, tail, label).map(|_| ())
+    }
+}
+
+/// Compute the SHA-256 digest of a reader's remaining bytes, in hex.
+///
+/// # Errors
+///
+/// Returns the first read error.
+pub(crate) fn sha256_hex<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut hashing = HashingReader {
+        inner: reader,
+        hasher: Sha256::new(),
+    };
+    io::copy(&mut hashing, &mut io::sink())?;
+    Ok(hex(&hashing.hasher.finalize()))
+}
+
+/// Format a timestamp as an RFC 3339 UTC instant with whole seconds, such as
+/// `2024-01-15T10:30:00Z`. Instants before 1970 round down to the earlier second.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    format_utc(time, "%Y-%m-%dT%H:%M:%SZ").expect("the RFC 3339 format is supported")
+}
+
+/// Format a timestamp in UTC with a `strftime`-style `format`. `%Y`, `%y`,
+/// `%m`, `%d`, `%j`, `%H`, `%M`, `%S`, `%s`, `%F`, `%T`, and `%%` are
+/// supported; any other specifier is returned as the error.
+pub(crate) fn format_utc(time: SystemTime, format: &str) -> std::result::Result<String, String> {
+    use std::fmt::Write as _;
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => i64::try_from(elapsed.as_secs()).unwrap_or(i64::MAX),
+        Err(error) => {
+            let before = error.duration();
+            let whole = i64::try_from(before.as_secs()).unwrap_or(i64::MAX);
+            -whole - i64::from(before.subsec_nanos() != 0)
+        }
+    };
+    let days = seconds.div_euclid(86_400);
+    let second_of_day = seconds.rem_euclid(86_400);
+    // Civil-from-days conversion over 400-year eras of 146,097 days.
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    // Day of the calendar year, from the March-based day of year.
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let ordinal = if month_index < 10 {
+        day_of_year + 60 + i64::from(leap)
+    } else {
+        day_of_year - 305
+    };
+    let (hour, minute, second) = (
+        second_of_day / 3600,
+        second_of_day % 3600 / 60,
+        second_of_day % 60,
+    );
+    let mut formatted = String::with_capacity(format.len() + 16);
+    let mut characters = format.chars();
+    while let Some(character) = characters.next() {
+        if character != '%' {
+            formatted.push(character);
+            continue;
+        }
+        let written = match characters.next() {
+            Some('Y') => write!(formatted, "{year:04}"),
+            Some('y') => write!(formatted, "{:02}", year.rem_euclid(100)),
+            Some('m') => write!(formatted, "{month:02}"),
+            Some('d') => write!(formatted, "{day:02}"),
+            Some('j') => write!(formatted, "{ordinal:03}"),
+            Some('H') => write!(formatted, "{hour:02}"),
+            Some('M') => write!(formatted, "{minute:02}"),
+            Some('S') => write!(formatted, "{second:02}"),
+            Some('s') => write!(formatted, "{seconds}"),
+            Some('F') => write!(formatted, "{year:04}-{month:02}-{day:02}"),
+            Some('T') => write!(formatted, "{hour:02}:{minute:02}:{second:02}"),
+            Some('%') => write!(formatted, "%"),
+            Some(other) => return Err(format!("%{other}")),
+            None => return Err("%".into()),
+        };
+        written.expect("writing to String cannot fail");
+    }
+    Ok(formatted)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            write!(hex, "{byte:02x}").expect("writing to String cannot fail");
+            hex
+        })
+}
+
+/// Feeds every byte read from `inner` to a SHA-256 hasher.
+struct HashingReader<'a, R> {
+    inner: &'a mut R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buffer)?;
+        self.hasher.update(&buffer[..count]);
+        Ok(count)
+    }
+}
+
+const BYTE_ORDER_MARK: &[u8] = b"\xEF\xBB\xBF";
+
+/// Drops a leading UTF-8 byte order mark from `inner`, passing every other
+/// byte through unchanged.
+pub(crate) struct SkipBom<R> {
+    inner: R,
+    /// Leading bytes read while checking for the mark, not yet returned.
+    prefix: Option<([u8; 3], usize)>,
+    position: usize,
+}
+
+impl<R: Read> SkipBom<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            prefix: None,
+            position: 0,
+        }
+    }
+
+    /// Read up to three leading bytes, discarding them if they are the mark.
+    fn read_prefix(&mut self) -> io::Result<([u8; 3], usize)> {
+        let mut prefix = [0_u8; 3];
+        let mut length = 0;
+        while length < prefix.len() {
+            match self.inner.read(&mut prefix[length..]) {
+                Ok(0) => break,
+                Ok(count) => length += count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        if prefix[..length] == *BYTE_ORDER_MARK {
+            length = 0;
+        }
+        self.prefix = Some((prefix, length));
+        Ok((prefix, length))
+    }
+}
+
+impl<R: Read> Read for SkipBom<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let (prefix, length) = match self.prefix {
+            Some(prefix) => prefix,
+            None => self.read_prefix()?,
+        };
+        if self.position < length {
+            let count = buffer.len().min(length - self.position);
+            buffer[..count].copy_from_slice(&prefix[self.position..self.position + count]);
+            self.position += count;
+            return Ok(count);
+        }
+        self.inner.read(buffer)
+    }
+}
+
+/// Converts CRLF and lone CR line endings from `inner` to LF, including a
+/// CRLF pair split across reads.
+pub(crate) struct LfLineEndings<R> {
+    inner: R,
+    after_cr: bool,
+}
+
+impl<R: Read> LfLineEndings<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            after_cr: false,
+        }
+    }
+}
+
+impl<R: Read> Read for LfLineEndings<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = self.inner.read(buffer)?;
+            if count == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for index in 0..count {
+                let byte = buffer[index];
+                if byte == b'\n' && self.after_cr {
+                    self.after_cr = false;
+                    continue;
+                }
+                self.after_cr = byte == b'\r';
+                buffer[kept] = if self.after_cr { b'\n' } else { byte };
+                kept += 1;
+            }
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+/// Expands each LF from `inner`, whose line endings are already LF, to CRLF,
+/// including when the caller's buffer holds a single byte.
+pub(crate) struct CrlfLineEndings<R> {
+    inner: R,
+    pending_lf: bool,
+}
+
+impl<R: Read> CrlfLineEndings<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending_lf: false,
+        }
+    }
+}
+
+impl<R: Read> Read for CrlfLineEndings<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        if self.pending_lf {
+            self.pending_lf = false;
+            buffer[0] = b'\n';
+            return Ok(1);
+        }
+        if buffer.len() == 1 {
+            let count = self.inner.read(buffer)?;
+            if count == 1 && buffer[0] == b'\n' {
+                buffer[0] = b'\r';
+                self.pending_lf = true;
+            }
+            return Ok(count);
+        }
+        // Reading into the upper half leaves room to expand every byte while
+        // copying forward, without overtaking the bytes still to be copied.
+        let half = buffer.len() / 2;
+        let count = self.inner.read(&mut buffer[half..half * 2])?;
+        let mut end = 0;
+        for index in half..half + count {
+            let byte = buffer[index];
+            if byte == b'\n' {
+                buffer[end] = b'\r';
+                end += 1;
+            }
+            buffer[end] = byte;
+            end += 1;
+        }
+        Ok(end)
+    }
+}
+
+/// Drops spaces, tabs, and other ASCII whitespace before each LF and at the
+/// end of `inner`, keeping a CR that precedes the LF. A whitespace run is
+/// held in memory until the byte after it decides its fate.
+pub(crate) struct TrimTrailingWhitespace<R> {
+    inner: R,
+    input: Box<[u8]>,
+    pending: Vec<u8>,
+    ready: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> TrimTrailingWhitespace<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            input: vec![0_u8; COPY_BUFFER_SIZE].into_boxed_slice(),
+            pending: Vec::new(),
+            ready: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for TrimTrailingWhitespace<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.ready.len() {
+            self.ready.clear();
+            self.position = 0;
+            let count = self.inner.read(&mut self.input)?;
+            if count == 0 {
+                self.pending.clear();
+                return Ok(0);
+            }
+            for &byte in &self.input[..count] {
+                match byte {
+                    b'\n' => {
+                        if self.pending.last() == Some(&b'\r') {
+                            self.ready.push(b'\r');
+                        }
+                        self.pending.clear();
+                        self.ready.push(b'\n');
+                    }
+                    b' ' | b'\t' | b'\r' | 0x0b | 0x0c => self.pending.push(byte),
+                    _ => {
+                        self.ready.append(&mut self.pending);
+                        self.ready.push(byte);
+                    }
+                }
+            }
+        }
+        let count = buffer.len().min(self.ready.len() - self.position);
+        buffer[..count].copy_from_slice(&self.ready[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// Appends an LF to a nonempty `inner` that does not already end with one.
+pub(crate) struct TerminateLastLine<R> {
+    inner: R,
+    last: Option<u8>,
+}
+
+impl<R: Read> TerminateLastLine<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, last: None }
+    }
+}
+
+impl<R: Read> Read for TerminateLastLine<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let count = self.inner.read(buffer)?;
+        if count > 0 {
+            self.last = Some(buffer[count - 1]);
+            return Ok(count);
+        }
+        if self.last.is_some_and(|last| last != b'\n') {
+            self.last = Some(b'\n');
+            buffer[0] = b'\n';
+            return Ok(1);
+        }
+        Ok(0)
+    }
+}
+
 pub(crate) fn write_body<R: Read, W: Write>(
     logical_path: &Path,
     reader: &mut R,
     adaptive: bool,
+    fence: Option<&str>,
     writer: &mut W,
 ) -> Result<()> {
     let label = encode_path(logical_path.as_os_str());
-    if adaptive {
+    if let Some(fence) = fence {
+        write_fenced(
+            logical_path,
+            reader,
+            fence,
+            &mut TailWriter::new(writer),
+            &label,
+        )
+    } else if adaptive {
         transform_markdown(reader, writer, &label)
     } else {
         copy_raw(reader, writer, &label).map(|_| ())
     }
 }
 
+/// Choose a backtick fence longer than any backtick run in `reader`, then
+/// rewind it so the body can be streamed inside that fence.
+pub(crate) fn choose_fence<R: Read + Seek>(reader: &mut R) -> io::Result<String> {
+    let mut buffer = vec![0_u8; COPY_BUFFER_SIZE].into_boxed_slice();
+    let mut longest = 0_usize;
+    let mut run = 0_usize;
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        for &byte in &buffer[..count] {
+            if byte == b'`' {
+                run += 1;
+                longest = longest.max(run);
+            } else {
+                run = 0;
+            }
+        }
+    }
+    reader.rewind()?;
+    Ok("`".repeat(longest.max(2) + 1))
+}
+
+/// A rewindable file body that knows its size and when it was last modified.
+pub(crate) trait Source: Read + Seek + 'static {
+    fn modified(&self) -> io::Result<SystemTime>;
+
+    fn size(&self) -> io::Result<u64>;
+}
+
+impl Source for File {
+    fn modified(&self) -> io::Result<SystemTime> {
+        self.metadata()?.modified()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// Whether the first bytes of `reader` hold a NUL, as binary formats usually
+/// do and text almost never does. The reader is rewound.
+pub(crate) fn starts_binary<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut prefix = Vec::new();
+    reader
+        .by_ref()
+        .take(BINARY_SNIFF_BYTES)
+        .read_to_end(&mut prefix)?;
+    reader.rewind()?;
+    Ok(prefix.contains(&0))
+}
+
+/// Map a file name to the info string of its fenced code block.
+pub(crate) fn fence_language(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    match name {
+        "Dockerfile" | "Containerfile" => return Some("dockerfile"),
+        "Makefile" | "GNUmakefile" => return Some("make"),
+        _ => {}
+    }
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "zig" => "zig",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "sh" | "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "nix" => "nix",
+        "diff" | "patch" => "diff",
+        _ => return None,
+    })
+}
+
+/// Stream `reader` inside `fence`, ending the body with a line break before
+/// the closing fence when it lacks one, and the closing fence with another.
+fn write_fenced<R: Read, W: Write>(
+    logical_path: &Path,
+    reader: &mut R,
+    fence: &str,
+    writer: &mut TailWriter<'_, W>,
+    label: &str,
+) -> Result<()> {
+    let language = fence_language(logical_path).unwrap_or_default();
+    writeln!(writer, "{fence}{language}").map_err(TextconError::output)?;
+    let start = writer.bytes_written();
+    copy_raw(reader, writer, label)?;
+    if writer.bytes_written() != start && writer.trailing_line_endings() == 0 {
+        writer.write_all(b"\n").map_err(TextconError::output)?;
+    }
+    writeln!(writer, "{fence}").map_err(TextconError::output)
+}
+
 pub(crate) fn is_markdown_path(path: &Path) -> bool {
     path.extension().is_some_and(|extension| {
         let bytes = extension.as_encoded_bytes();
@@ -385,6 +922,102 @@ impl MarkdownTransformer {
     }
 }
 
+/// Writes `prefix` before the first byte of every line written to `inner`.
+/// A prefix is never written after a final line feed.
+pub(crate) struct IndentWriter<'a, W> {
+    inner: W,
+    prefix: &'a [u8],
+    at_line_start: bool,
+}
+
+impl<'a, W: Write> IndentWriter<'a, W> {
+    pub(crate) const fn new(inner: W, prefix: &'a str) -> Self {
+        Self {
+            inner,
+            prefix: prefix.as_bytes(),
+            at_line_start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for IndentWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if self.prefix.is_empty() || buffer.is_empty() {
+            return self.inner.write(buffer);
+        }
+        if self.at_line_start {
+            self.inner.write_all(self.prefix)?;
+            self.at_line_start = false;
+        }
+        let line = buffer
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(buffer, |end| &buffer[..=end]);
+        let written = self.inner.write(line)?;
+        self.at_line_start = written == line.len() && line.ends_with(b"\n");
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the begin marker of a file, then tracks the end of its output so
+/// that the end marker starts on a line of its own. Without a marker style,
+/// writes pass straight through.
+pub(crate) struct MarkedWriter<'a, W> {
+    tail: TailWriter<'a, W>,
+    marker: Option<(FileMarkers, String)>,
+}
+
+impl<'a, W: Write> MarkedWriter<'a, W> {
+    pub(crate) fn begin(
+        style: Option<FileMarkers>,
+        logical_path: &Path,
+        index: u64,
+        writer: &'a mut W,
+    ) -> Result<Self> {
+        let marker = style.map(|style| (style, encode_path(logical_path.as_os_str())));
+        if let Some((style, label)) = &marker {
+            writer
+                .write_all(style.begin(label, index).as_bytes())
+                .map_err(TextconError::output)?;
+        }
+        Ok(Self {
+            tail: TailWriter::new(writer),
+            marker,
+        })
+    }
+
+    pub(crate) fn finish(self) -> Result<()> {
+        let Some((style, label)) = self.marker else {
+            return Ok(());
+        };
+        let unterminated = self.tail.written != 0 && self.tail.trailing_line_endings() == 0;
+        let writer = self.tail.inner;
+        if unterminated {
+            writer.write_all(b"\n").map_err(TextconError::output)?;
+        }
+        writer
+            .write_all(style.end(&label).as_bytes())
+            .map_err(TextconError::output)
+    }
+}
+
+impl<W: Write> Write for MarkedWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if self.marker.is_none() {
+            return self.tail.inner.write(buffer);
+        }
+        self.tail.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tail.flush()
+    }
+}
+
 struct TailWriter<'a, W> {
     inner: &'a mut W,
     tail: Vec<u8>,
@@ -457,6 +1090,49 @@ impl<W: Write> Write for TailWriter<'_, W> {
 mod tests {
     use super::*;
     use std::io::Cursor;
+    use std::time::Duration;
+
+    #[test]
+    fn indent_writer_prefixes_every_line() {
+        let mut output = Vec::new();
+        let mut indented = IndentWriter::new(&mut output, "> ");
+        indented.write_all(b"a\n\nb").unwrap();
+        indented.write_all(b"c\r\nd\n").unwrap();
+        assert_eq!(output, b"> a\n> \n> bc\r\n> d\n");
+    }
+
+    #[test]
+    fn fenced_body_outgrows_inner_backtick_runs() {
+        let mut source = Cursor::new(b"let s = \"```\";".to_vec());
+        let fence = choose_fence(&mut source).unwrap();
+        assert_eq!(fence, "````");
+
+        let mut output = Vec::new();
+        write_body(
+            Path::new("src/main.rs"),
+            &mut source,
+            false,
+            Some(&fence),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output, b"````rust\nlet s = \"```\";\n````\n");
+
+        let mut record = Vec::new();
+        write_markdown_record(
+            Path::new("notes"),
+            &mut Cursor::new(b"plain\n"),
+            false,
+            Some("```"),
+            None,
+            None,
+            false,
+            &mut record,
+        )
+        .unwrap();
+        assert_eq!(record, b"# `notes`\n\n```\nplain\n```\n\n");
+        assert_eq!(fence_language(Path::new("lib.PY")), Some("python"));
+    }
 
     #[test]
     fn shifts_atx_headings_outside_fences() {
@@ -476,10 +1152,164 @@ mod tests {
             Path::new("src/main.rs"),
             &mut Cursor::new(b"fn main() {}"),
             false,
+            None,
+            None,
+            None,
+            false,
             &mut output,
         )
         .unwrap();
         assert_eq!(output, b"# `src/main.rs`\n\nfn main() {}\n\n");
+
+        let mut checked = Vec::new();
+        write_markdown_record(
+            Path::new("abc"),
+            &mut Cursor::new(b"abc"),
+            false,
+            None,
+            None,
+            None,
+            true,
+            &mut checked,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(checked).unwrap(),
+            "# `abc`\n\nabc\n<!-- sha256: \
+             ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad -->\n\n"
+        );
+    }
+
+    #[test]
+    fn modified_time_follows_heading_in_rfc3339() {
+        let mut output = Vec::new();
+        write_markdown_record(
+            Path::new("notes.txt"),
+            &mut Cursor::new(b"text"),
+            false,
+            None,
+            None,
+            Some(UNIX_EPOCH + Duration::from_mins(28_421_910)),
+            false,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "# `notes.txt`\n<!-- modified: 2024-01-15T10:30:00Z -->\n\ntext\n\n"
+        );
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH - Duration::from_millis(500)),
+            "1969-12-31T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn utc_format_expands_supported_specifiers() {
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(
+            format_utc(leap_day, "%F %T|%y %j %s 100%%").unwrap(),
+            "2000-02-29 12:34:56|00 060 951827696 100%"
+        );
+        assert_eq!(
+            format_utc(UNIX_EPOCH + Duration::from_hours(24 * 364), "%Y-%j").unwrap(),
+            "1970-365"
+        );
+        assert_eq!(format_utc(leap_day, "%Y %q").unwrap_err(), "%q");
+        assert_eq!(format_utc(leap_day, "%Y %").unwrap_err(), "%");
+    }
+
+    #[test]
+    fn leading_byte_order_mark_is_dropped_once() {
+        for (input, expected) in [
+            (
+                &b"\xEF\xBB\xBFtext\xEF\xBB\xBF"[..],
+                &b"text\xEF\xBB\xBF"[..],
+            ),
+            (&b"\xEF\xBB"[..], &b"\xEF\xBB"[..]),
+            (&b"ab"[..], &b"ab"[..]),
+            (&b""[..], &b""[..]),
+        ] {
+            let mut output = Vec::new();
+            SkipBom::new(input).read_to_end(&mut output).unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn line_endings_become_lf_across_reads() {
+        struct OneByte<'a>(&'a [u8]);
+        impl Read for OneByte<'_> {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                let Some((&first, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                buffer[0] = first;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+
+        let input = b"a\r\nb\rc\n\r\r\nd\r";
+        for chunked in [false, true] {
+            let mut output = Vec::new();
+            if chunked {
+                LfLineEndings::new(OneByte(input)).read_to_end(&mut output)
+            } else {
+                LfLineEndings::new(&input[..]).read_to_end(&mut output)
+            }
+            .unwrap();
+            assert_eq!(output, b"a\nb\nc\n\n\nd\n");
+        }
+    }
+
+    #[test]
+    fn crlf_line_endings_expand_every_line_feed() {
+        let input = b"\na\nb\n\nc";
+        let mut output = Vec::new();
+        CrlfLineEndings::new(&input[..])
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"\r\na\r\nb\r\n\r\nc");
+
+        let mut reader = CrlfLineEndings::new(&input[..]);
+        let mut output = Vec::new();
+        let mut byte = [0];
+        while reader.read(&mut byte).unwrap() == 1 {
+            output.push(byte[0]);
+        }
+        assert_eq!(output, b"\r\na\r\nb\r\n\r\nc");
+    }
+
+    #[test]
+    fn trailing_whitespace_is_trimmed_before_line_endings() {
+        let input = b"a \t\nb  c\x0c\r\n \n\td \r e\t ";
+        let mut output = Vec::new();
+        TrimTrailingWhitespace::new(&input[..])
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"a\nb  c\r\n\n\td \r e");
+    }
+
+    #[test]
+    fn last_line_gains_exactly_one_line_feed() {
+        for (input, expected) in [
+            (&b"a"[..], &b"a\n"[..]),
+            (&b"a\n"[..], &b"a\n"[..]),
+            (&b"a\r"[..], &b"a\r\n"[..]),
+            (&b""[..], &b""[..]),
+        ] {
+            let mut output = Vec::new();
+            TerminateLastLine::new(input)
+                .read_to_end(&mut output)
+                .unwrap();
+            assert_eq!(output, expected);
+        }
     }
 
     #[test]
@@ -507,6 +1337,10 @@ mod tests {
                 Path::new("file"),
                 &mut Cursor::new(body),
                 false,
+                None,
+                None,
+                None,
+                false,
                 &mut output,
             )
             .unwrap();
@@ -522,6 +1356,12 @@ mod tests {
         assert_eq!(encode_path(path), "a%25%60%0A%FF");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn unix_backslash_is_a_name_byte_not_a_separator() {
+        assert_eq!(encode_path(OsStr::new(r"docs/a\b.md")), "docs/a%5Cb.md");
+    }
+
     #[cfg(windows)]
     #[test]
     fn path_encoding_normalizes_windows_separators() {
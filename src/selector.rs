@@ -5,48 +5,257 @@
     clippy::too_many_lines
 )]
 
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Read};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
 
 use cap_std::fs::Dir;
 use ignore::Match;
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use same_file::Handle;
 
-use crate::engine::SelectionOptions;
+use crate::engine::{SelectionOptions, SkipCallback, SkipReason, WalkOrder};
 use crate::error::{Result, TextconError};
 
+/// Filesystem access to a directory being visited, in its selection authority.
+pub(crate) enum Directory<'a> {
+    Ambient(&'a Path),
+    Sandbox(&'a Dir),
+}
+
+impl Directory<'_> {
+    pub(crate) fn open_file(&self, name: &str) -> std::io::Result<File> {
+        match self {
+            Self::Ambient(path) => File::open(path.join(name)),
+            Self::Sandbox(directory) => directory.open(name).map(cap_std::fs::File::into_std),
+        }
+    }
+}
+
+/// A selected directory entry, reported before its siblings are visited.
+pub(crate) struct Child<'a> {
+    pub(crate) name: &'a OsStr,
+    pub(crate) is_dir: bool,
+}
+
+/// Receives selected descendants in deterministic depth-first order.
+///
+/// `last` reports whether an entry is the final selected sibling in its
+/// directory. The selected root is entered before and left after its
+/// descendants. After a directory is entered, `children` receives its
+/// selected entries; returning `false` skips them.
+pub(crate) trait Visitor {
+    fn file(&mut self, path: &Path, file: File, last: bool) -> Result<()>;
+
+    fn enter_directory(
+        &mut self,
+        _path: &Path,
+        _directory: Directory<'_>,
+        _last: bool,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn children(&mut self, _children: &[Child<'_>]) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether discovered symlinks are selected and passed to `symlink`
+    /// rather than skipped. They are never followed either way.
+    fn lists_symlinks(&self) -> bool {
+        false
+    }
+
+    fn symlink(&mut self, _path: &Path, _target: &Path, _last: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> Visitor for F
+where
+    F: FnMut(&Path, File) -> Result<()>,
+{
+    fn file(&mut self, path: &Path, file: File, _last: bool) -> Result<()> {
+        self(path, file)
+    }
+}
+
+/// Counts the files a walk selects.
+struct FileCount(usize);
+
+impl Visitor for FileCount {
+    fn file(&mut self, _path: &Path, _file: File, _last: bool) -> Result<()> {
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
 pub(crate) struct Selector<'a> {
     options: &'a SelectionOptions,
-    output_identity: Option<&'a Handle>,
+    output_identities: &'a [Handle],
+    skipped: Option<&'a SkipCallback>,
+    /// Reverse the walk order of each directory's entries.
+    reverse: bool,
+    /// Files last modified before this instant are not selected.
+    modified_after: Option<SystemTime>,
+    /// With `git_tracked_only` inside a repository, the logical paths of
+    /// tracked files beneath the walk root and of their ancestor directories.
+    tracked: Option<&'a HashSet<PathBuf>>,
 }
 
 impl<'a> Selector<'a> {
-    pub(crate) fn new(options: &'a SelectionOptions, output_identity: Option<&'a Handle>) -> Self {
+    pub(crate) fn new(
+        options: &'a SelectionOptions,
+        output_identities: &'a [Handle],
+        skipped: Option<&'a SkipCallback>,
+        reverse: bool,
+    ) -> Self {
         Self {
             options,
-            output_identity,
+            output_identities,
+            skipped,
+            reverse,
+            modified_after: options.modified_within.map(|window| {
+                SystemTime::now()
+                    .checked_sub(window)
+                    .unwrap_or(SystemTime::UNIX_EPOCH)
+            }),
+            tracked: None,
         }
     }
 
-    pub(crate) fn select_ambient<F>(
+    pub(crate) fn select_ambient<V: Visitor>(
         &self,
         root: &Path,
         logical_root: &Path,
         policy_root: &Path,
-        callback: &mut F,
-    ) -> Result<()>
+        visitor: &mut V,
+    ) -> Result<()> {
+        let tracked = self.git_tracked(root, logical_root);
+        let selector = Selector {
+            tracked: tracked.as_ref(),
+            ..*self
+        };
+        selector.check_file_count(logical_root, |counter, count| {
+            counter.visit_ambient(root, logical_root, policy_root, count)
+        })?;
+        selector.visit_ambient(root, logical_root, policy_root, visitor)
+    }
+
+    pub(crate) fn select_sandbox<V: Visitor>(
+        &self,
+        capability_root: &Dir,
+        root_relative: &Path,
+        logical_root: &Path,
+        display_root: &Path,
+        visitor: &mut V,
+    ) -> Result<()> {
+        let tracked = self.git_tracked(&display_root.join(root_relative), logical_root);
+        let selector = Selector {
+            tracked: tracked.as_ref(),
+            ..*self
+        };
+        selector.check_file_count(logical_root, |counter, count| {
+            counter.visit_sandbox(
+                capability_root,
+                root_relative,
+                logical_root,
+                display_root,
+                count,
+            )
+        })?;
+        selector.visit_sandbox(
+            capability_root,
+            root_relative,
+            logical_root,
+            display_root,
+            visitor,
+        )
+    }
+
+    /// With `git_tracked_only`, ask Git which files beneath `physical_root`
+    /// are tracked, keyed by logical path together with their ancestor
+    /// directories. Outside a repository, or without a `git` executable,
+    /// nothing is filtered.
+    fn git_tracked(&self, physical_root: &Path, logical_root: &Path) -> Option<HashSet<PathBuf>> {
+        if !self.options.git_tracked_only {
+            return None;
+        }
+        debug_event!(directory = %physical_root.display(), "list tracked files");
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(physical_root)
+            .args(["ls-files", "-z"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        let mut tracked = HashSet::new();
+        for name in output.stdout.split(|&byte| byte == 0) {
+            if name.is_empty() {
+                continue;
+            }
+            let mut path = logical_root.join(path_from_bytes(name));
+            while tracked.insert(path.clone()) && path.pop() && path != logical_root {}
+        }
+        Some(tracked)
+    }
+
+    fn untracked(&self, logical_dir: &Path, name: &OsStr) -> bool {
+        self.tracked
+            .is_some_and(|tracked| !tracked.contains(&logical_dir.join(name)))
+    }
+
+    /// With a `max_files` limit, walk once without reporting skips and fail
+    /// before anything is visited if the walk would select too many files.
+    fn check_file_count<F>(&self, logical_root: &Path, walk: F) -> Result<()>
     where
-        F: FnMut(&Path, File) -> Result<()>,
+        F: FnOnce(&Selector<'_>, &mut FileCount) -> Result<()>,
     {
+        let Some(limit) = self.options.max_files else {
+            return Ok(());
+        };
+        let counter = Selector {
+            skipped: None,
+            ..*self
+        };
+        let mut count = FileCount(0);
+        walk(&counter, &mut count)?;
+        if count.0 > limit {
+            return Err(TextconError::TooManyFiles {
+                path: logical_root.to_path_buf(),
+                count: count.0,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    fn visit_ambient<V: Visitor>(
+        &self,
+        root: &Path,
+        logical_root: &Path,
+        policy_root: &Path,
+        visitor: &mut V,
+    ) -> Result<()> {
         let cli = build_cli_matcher(policy_root, &self.options.excludes)?;
         let mut ignores = Vec::new();
         self.load_ambient_ancestor_ignores(policy_root, root, &mut ignores)?;
         let mut ancestors = Vec::new();
         let root_handle = Handle::from_path(root)
             .map_err(|error| TextconError::path_io("identify directory", root, error))?;
-        ancestors.push(root_handle);
+        ancestors.push((root_handle, root.to_path_buf()));
+        visitor.enter_directory(logical_root, Directory::Ambient(root), true)?;
         self.walk_ambient(
             root,
             logical_root,
@@ -56,21 +265,19 @@ impl<'a> Selector<'a> {
             &mut ignores,
             &mut ancestors,
             true,
-            callback,
-        )
+            visitor,
+        )?;
+        visitor.leave_directory()
     }
 
-    pub(crate) fn select_sandbox<F>(
+    fn visit_sandbox<V: Visitor>(
         &self,
         capability_root: &Dir,
         root_relative: &Path,
         logical_root: &Path,
         display_root: &Path,
-        callback: &mut F,
-    ) -> Result<()>
-    where
-        F: FnMut(&Path, File) -> Result<()>,
-    {
+        visitor: &mut V,
+    ) -> Result<()> {
         let cli = build_cli_matcher(display_root, &self.options.excludes)?;
         let mut ignores = Vec::new();
         self.load_sandbox_ancestor_ignores(
@@ -105,9 +312,10 @@ impl<'a> Selector<'a> {
                 error,
             )
         })?;
-        let mut ancestors = vec![root_handle];
+        let mut ancestors = vec![(root_handle, display_root.join(root_relative))];
+        visitor.enter_directory(logical_root, Directory::Sandbox(&root_dir), true)?;
         self.walk_sandbox(
-            root_dir,
+            &root_dir,
             root_relative,
             logical_root,
             display_root,
@@ -116,12 +324,13 @@ impl<'a> Selector<'a> {
             &mut ignores,
             &mut ancestors,
             true,
-            callback,
-        )
+            visitor,
+        )?;
+        visitor.leave_directory()
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn walk_ambient<F>(
+    fn walk_ambient<V: Visitor>(
         &self,
         physical_dir: &Path,
         logical_dir: &Path,
@@ -129,13 +338,10 @@ impl<'a> Selector<'a> {
         depth: usize,
         cli: &Gitignore,
         ignores: &mut Vec<Gitignore>,
-        ancestors: &mut Vec<Handle>,
+        ancestors: &mut Vec<(Handle, PathBuf)>,
         ignore_already_loaded: bool,
-        callback: &mut F,
-    ) -> Result<()>
-    where
-        F: FnMut(&Path, File) -> Result<()>,
-    {
+        visitor: &mut V,
+    ) -> Result<()> {
         let pushed = if ignore_already_loaded {
             false
         } else {
@@ -145,42 +351,72 @@ impl<'a> Selector<'a> {
             .map_err(|error| TextconError::path_io("read directory", physical_dir, error))?
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|error| TextconError::path_io("read directory entry", physical_dir, error))?;
-        entries.sort_by_key(fs::DirEntry::file_name);
+        self.sort(&mut entries, fs::DirEntry::file_name, |entry| {
+            let metadata = entry.metadata()?;
+            Ok((metadata.len(), metadata.modified()?))
+        })
+        .map_err(|error| TextconError::path_io("inspect directory entry", physical_dir, error))?;
+        debug_event!(directory = %physical_dir.display(), entries = entries.len(), "walk directory");
 
+        let child_depth = depth.saturating_add(1);
+        let lists_symlinks = visitor.lists_symlinks();
+        let mut selected = Vec::with_capacity(entries.len());
         for entry in entries {
             let name = entry.file_name();
             let physical = entry.path();
-            let logical = logical_dir.join(&name);
             let metadata = fs::symlink_metadata(&physical)
                 .map_err(|error| TextconError::path_io("inspect", &physical, error))?;
             let file_type = metadata.file_type();
-            if file_type.is_symlink() || self.is_hidden(&name) {
-                continue;
-            }
-            let relative = physical.strip_prefix(policy_root).unwrap_or(&physical);
             let is_dir = file_type.is_dir();
-            if Self::is_ignored(&policy_root.join(relative), is_dir, cli, ignores) {
-                continue;
+            let is_symlink = file_type.is_symlink();
+            let rejection = if is_symlink && !lists_symlinks {
+                Some(SkipReason::Symlink)
+            } else if self.is_hidden(&name) {
+                Some(SkipReason::Hidden)
+            } else {
+                let relative = physical.strip_prefix(policy_root).unwrap_or(&physical);
+                match Self::ignored(&policy_root.join(relative), is_dir, cli, ignores) {
+                    Some(reason) => Some(reason),
+                    None if self.untracked(logical_dir, &name) => Some(SkipReason::Untracked),
+                    None => self.inadmissible(
+                        &name,
+                        is_dir,
+                        file_type.is_file() || is_symlink,
+                        child_depth,
+                        || Ok(is_symlink || self.is_recent(metadata.modified(), &physical)?),
+                    )?,
+                }
+            };
+            match rejection {
+                Some(reason) => self.skip(logical_dir, &name, reason),
+                None => selected.push((name, physical, is_dir, is_symlink)),
             }
-            let child_depth = depth.saturating_add(1);
+        }
+
+        let descend = visitor.children(
+            &selected
+                .iter()
+                .map(|(name, _, is_dir, _)| Child {
+                    name,
+                    is_dir: *is_dir,
+                })
+                .collect::<Vec<_>>(),
+        )?;
+        if !descend {
+            selected.clear();
+        }
+
+        let count = selected.len();
+        for (index, (name, physical, is_dir, is_symlink)) in selected.into_iter().enumerate() {
+            let last = index + 1 == count;
+            let logical = logical_dir.join(&name);
             if is_dir {
-                if self
-                    .options
-                    .max_depth
-                    .is_some_and(|maximum| child_depth >= maximum)
-                {
-                    continue;
-                }
                 let handle = Handle::from_path(&physical).map_err(|error| {
                     TextconError::path_io("identify directory", &physical, error)
                 })?;
-                if ancestors.contains(&handle) {
-                    return Err(TextconError::Config(format!(
-                        "directory cycle detected at {}",
-                        physical.display()
-                    )));
-                }
-                ancestors.push(handle);
+                check_cycle(ancestors, &handle, &physical)?;
+                ancestors.push((handle, physical.clone()));
+                visitor.enter_directory(&logical, Directory::Ambient(&physical), last)?;
                 self.walk_ambient(
                     &physical,
                     &logical,
@@ -190,21 +426,23 @@ impl<'a> Selector<'a> {
                     ignores,
                     ancestors,
                     false,
-                    callback,
+                    visitor,
                 )?;
+                visitor.leave_directory()?;
                 ancestors.pop();
-            } else if file_type.is_file()
-                && self
-                    .options
-                    .max_depth
-                    .is_none_or(|maximum| child_depth <= maximum)
-            {
+            } else if is_symlink {
+                let target = fs::read_link(&physical)
+                    .map_err(|error| TextconError::path_io("read symlink", &physical, error))?;
+                visitor.symlink(&logical, &target, last)?;
+            } else {
+                debug_event!(path = %physical.display(), "open file");
                 let file = File::open(&physical)
                     .map_err(|error| TextconError::path_io("open", &physical, error))?;
                 if self.file_is_output(&file) {
+                    self.skip(logical_dir, &name, SkipReason::Output);
                     continue;
                 }
-                callback(&logical, file)?;
+                visitor.file(&logical, file, last)?;
             }
         }
 
@@ -215,26 +453,23 @@ impl<'a> Selector<'a> {
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn walk_sandbox<F>(
+    fn walk_sandbox<V: Visitor>(
         &self,
-        directory: Dir,
+        directory: &Dir,
         relative_dir: &Path,
         logical_dir: &Path,
         display_root: &Path,
         depth: usize,
         cli: &Gitignore,
         ignores: &mut Vec<Gitignore>,
-        ancestors: &mut Vec<Handle>,
+        ancestors: &mut Vec<(Handle, PathBuf)>,
         ignore_already_loaded: bool,
-        callback: &mut F,
-    ) -> Result<()>
-    where
-        F: FnMut(&Path, File) -> Result<()>,
-    {
+        visitor: &mut V,
+    ) -> Result<()> {
         let pushed = if ignore_already_loaded {
             false
         } else {
-            self.load_sandbox_ignore(&directory, relative_dir, display_root, ignores)?
+            self.load_sandbox_ignore(directory, relative_dir, display_root, ignores)?
         };
         let mut entries = directory
             .entries()
@@ -253,12 +488,29 @@ impl<'a> Selector<'a> {
                     error,
                 )
             })?;
-        entries.sort_by_key(cap_std::fs::DirEntry::file_name);
+        self.sort(&mut entries, cap_std::fs::DirEntry::file_name, |entry| {
+            let metadata = entry.metadata()?;
+            Ok((metadata.len(), metadata.modified()?.into_std()))
+        })
+        .map_err(|error| {
+            TextconError::path_io(
+                "inspect sandboxed directory entry",
+                display_root.join(relative_dir),
+                error,
+            )
+        })?;
+        debug_event!(
+            directory = %display_root.join(relative_dir).display(),
+            entries = entries.len(),
+            "walk sandboxed directory"
+        );
 
+        let child_depth = depth.saturating_add(1);
+        let lists_symlinks = visitor.lists_symlinks();
+        let mut selected = Vec::with_capacity(entries.len());
         for entry in entries {
             let name = entry.file_name();
             let relative = relative_dir.join(&name);
-            let logical = logical_dir.join(&name);
             let file_type = entry.file_type().map_err(|error| {
                 TextconError::path_io(
                     "inspect sandboxed entry",
@@ -266,22 +518,59 @@ impl<'a> Selector<'a> {
                     error,
                 )
             })?;
-            if file_type.is_symlink() || self.is_hidden(&name) {
-                continue;
-            }
             let is_dir = file_type.is_dir();
-            if Self::is_ignored(&display_root.join(&relative), is_dir, cli, ignores) {
-                continue;
+            let is_symlink = file_type.is_symlink();
+            let rejection = if is_symlink && !lists_symlinks {
+                Some(SkipReason::Symlink)
+            } else if self.is_hidden(&name) {
+                Some(SkipReason::Hidden)
+            } else {
+                match Self::ignored(&display_root.join(&relative), is_dir, cli, ignores) {
+                    Some(reason) => Some(reason),
+                    None if self.untracked(logical_dir, &name) => Some(SkipReason::Untracked),
+                    None => self.inadmissible(
+                        &name,
+                        is_dir,
+                        file_type.is_file() || is_symlink,
+                        child_depth,
+                        || {
+                            Ok(is_symlink
+                                || self.is_recent(
+                                    entry
+                                        .metadata()
+                                        .and_then(|metadata| metadata.modified())
+                                        .map(cap_std::time::SystemTime::into_std),
+                                    &display_root.join(&relative),
+                                )?)
+                        },
+                    )?,
+                }
+            };
+            match rejection {
+                Some(reason) => self.skip(logical_dir, &name, reason),
+                None => selected.push((entry, name, relative, is_dir, is_symlink)),
             }
-            let child_depth = depth.saturating_add(1);
+        }
+
+        let descend = visitor.children(
+            &selected
+                .iter()
+                .map(|(_, name, _, is_dir, _)| Child {
+                    name,
+                    is_dir: *is_dir,
+                })
+                .collect::<Vec<_>>(),
+        )?;
+        if !descend {
+            selected.clear();
+        }
+
+        let count = selected.len();
+        for (index, (entry, name, relative, is_dir, is_symlink)) in selected.into_iter().enumerate()
+        {
+            let last = index + 1 == count;
+            let logical = logical_dir.join(&name);
             if is_dir {
-                if self
-                    .options
-                    .max_depth
-                    .is_some_and(|maximum| child_depth >= maximum)
-                {
-                    continue;
-                }
                 let child = entry.open_dir().map_err(|error| {
                     TextconError::path_io(
                         "open sandboxed directory",
@@ -308,15 +597,12 @@ impl<'a> Selector<'a> {
                         error,
                     )
                 })?;
-                if ancestors.contains(&handle) {
-                    return Err(TextconError::Config(format!(
-                        "directory cycle detected at {}",
-                        display_root.join(&relative).display()
-                    )));
-                }
-                ancestors.push(handle);
+                let display = display_root.join(&relative);
+                check_cycle(ancestors, &handle, &display)?;
+                ancestors.push((handle, display));
+                visitor.enter_directory(&logical, Directory::Sandbox(&child), last)?;
                 self.walk_sandbox(
-                    child,
+                    &child,
                     &relative,
                     &logical,
                     display_root,
@@ -325,15 +611,21 @@ impl<'a> Selector<'a> {
                     ignores,
                     ancestors,
                     false,
-                    callback,
+                    visitor,
                 )?;
+                visitor.leave_directory()?;
                 ancestors.pop();
-            } else if file_type.is_file()
-                && self
-                    .options
-                    .max_depth
-                    .is_none_or(|maximum| child_depth <= maximum)
-            {
+            } else if is_symlink {
+                let target = directory.read_link_contents(&name).map_err(|error| {
+                    TextconError::path_io(
+                        "read sandboxed symlink",
+                        display_root.join(&relative),
+                        error,
+                    )
+                })?;
+                visitor.symlink(&logical, &target, last)?;
+            } else {
+                debug_event!(path = %display_root.join(&relative).display(), "open sandboxed file");
                 let file = entry.open().map_err(|error| {
                     TextconError::path_io(
                         "open sandboxed file",
@@ -343,9 +635,10 @@ impl<'a> Selector<'a> {
                 })?;
                 let std_file = file.into_std();
                 if self.file_is_output(&std_file) {
+                    self.skip(logical_dir, &name, SkipReason::Output);
                     continue;
                 }
-                callback(&logical, std_file)?;
+                visitor.file(&logical, std_file, last)?;
             }
         }
 
@@ -355,20 +648,125 @@ impl<'a> Selector<'a> {
         Ok(())
     }
 
-    fn is_ignored(path: &Path, is_dir: bool, cli: &Gitignore, ignores: &[Gitignore]) -> bool {
+    /// Order one directory's entries by [`SelectionOptions::order`], then
+    /// reverse them for `| reverse`. `stats` supplies an entry's size and
+    /// modification time, and is only called for orders that need them.
+    fn sort<E>(
+        &self,
+        entries: &mut Vec<E>,
+        name: impl Fn(&E) -> OsString,
+        stats: impl Fn(&E) -> io::Result<(u64, SystemTime)>,
+    ) -> io::Result<()> {
+        let order = self.options.order;
+        match order {
+            WalkOrder::None => {}
+            WalkOrder::Name => entries.sort_by_key(name),
+            WalkOrder::ModifiedAsc
+            | WalkOrder::ModifiedDesc
+            | WalkOrder::SizeAsc
+            | WalkOrder::SizeDesc => {
+                let mut keyed = entries
+                    .drain(..)
+                    .map(|entry| Ok((stats(&entry)?, name(&entry), entry)))
+                    .collect::<io::Result<Vec<_>>>()?;
+                keyed.sort_by(
+                    |((size, modified), name, _), ((other_size, other_modified), other_name, _)| {
+                        let primary = match order {
+                            WalkOrder::ModifiedAsc => modified.cmp(other_modified),
+                            WalkOrder::ModifiedDesc => other_modified.cmp(modified),
+                            WalkOrder::SizeAsc => size.cmp(other_size),
+                            _ => other_size.cmp(size),
+                        };
+                        primary.then_with(|| name.cmp(other_name))
+                    },
+                );
+                entries.extend(keyed.into_iter().map(|(_, _, entry)| entry));
+            }
+        }
+        if self.reverse {
+            entries.reverse();
+        }
+        Ok(())
+    }
+
+    /// Apply the type, depth, and modification-window policies to an entry
+    /// that survived ignore rules. `recent` is only called for files.
+    fn inadmissible(
+        &self,
+        name: &OsStr,
+        is_dir: bool,
+        is_file: bool,
+        child_depth: usize,
+        recent: impl FnOnce() -> Result<bool>,
+    ) -> Result<Option<SkipReason>> {
+        if !is_dir && !is_file {
+            return Ok(Some(SkipReason::SpecialFile));
+        }
+        let within_depth = self.options.max_depth.is_none_or(|maximum| {
+            if is_dir {
+                child_depth < maximum
+            } else {
+                child_depth <= maximum
+            }
+        });
+        if !within_depth {
+            return Ok(Some(SkipReason::Depth));
+        }
+        if !is_dir && !self.extension_selected(name) {
+            return Ok(Some(SkipReason::Extension));
+        }
+        if !is_dir && !recent()? {
+            return Ok(Some(SkipReason::Unmodified));
+        }
+        Ok(None)
+    }
+
+    /// Report a discovered entry of `directory` that was not selected.
+    fn skip(&self, directory: &Path, name: &OsStr, reason: SkipReason) {
+        if let Some(skipped) = self.skipped {
+            skipped(&directory.join(name), reason);
+        }
+    }
+
+    /// Apply the modification window to a discovered file. The timestamp is
+    /// only consulted, and its failure only reported, when a window is set.
+    fn extension_selected(&self, name: &OsStr) -> bool {
+        let included = &self.options.include_extensions;
+        let listed = |extension: &String| has_extension(name, extension);
+        if included.iter().any(listed) {
+            return true;
+        }
+        included.is_empty() && !self.options.exclude_extensions.iter().any(listed)
+    }
+
+    fn is_recent(&self, modified: std::io::Result<SystemTime>, path: &Path) -> Result<bool> {
+        let Some(cutoff) = self.modified_after else {
+            return Ok(true);
+        };
+        let modified = modified
+            .map_err(|error| TextconError::path_io("read modification time", path, error))?;
+        Ok(modified >= cutoff)
+    }
+
+    fn ignored(
+        path: &Path,
+        is_dir: bool,
+        cli: &Gitignore,
+        ignores: &[Gitignore],
+    ) -> Option<SkipReason> {
         match cli.matched_path_or_any_parents(path, is_dir) {
-            Match::Ignore(_) => return true,
-            Match::Whitelist(_) => return false,
+            Match::Ignore(_) => return Some(SkipReason::Excluded),
+            Match::Whitelist(_) => return None,
             Match::None => {}
         }
         for matcher in ignores.iter().rev() {
             match matcher.matched_path_or_any_parents(path, is_dir) {
-                Match::Ignore(_) => return true,
-                Match::Whitelist(_) => return false,
+                Match::Ignore(_) => return Some(SkipReason::Gitignored),
+                Match::Whitelist(_) => return None,
                 Match::None => {}
             }
         }
-        false
+        None
     }
 
     fn is_hidden(&self, name: &std::ffi::OsStr) -> bool {
@@ -379,12 +777,12 @@ impl<'a> Selector<'a> {
     }
 
     fn file_is_output(&self, file: &File) -> bool {
-        self.output_identity.is_some_and(|output| {
-            file.try_clone()
+        !self.output_identities.is_empty()
+            && file
+                .try_clone()
                 .ok()
                 .and_then(|clone| Handle::from_file(clone).ok())
-                .is_some_and(|candidate| candidate == *output)
-        })
+                .is_some_and(|candidate| self.output_identities.contains(&candidate))
     }
 
     fn load_ambient_ancestor_ignores(
@@ -482,6 +880,37 @@ impl<'a> Selector<'a> {
     }
 }
 
+/// Fail when `handle` names a directory already open above it in the walk,
+/// reporting the chain from the re-entered ancestor down to `path`.
+fn check_cycle(ancestors: &[(Handle, PathBuf)], handle: &Handle, path: &Path) -> Result<()> {
+    let Some(index) = ancestors
+        .iter()
+        .position(|(ancestor, _)| ancestor == handle)
+    else {
+        return Ok(());
+    };
+    let mut stack = ancestors[index..]
+        .iter()
+        .map(|(_, ancestor)| ancestor.clone())
+        .collect::<Vec<_>>();
+    stack.push(path.to_path_buf());
+    Err(TextconError::CircularReference {
+        path: path.to_path_buf(),
+        stack,
+    })
+}
+
+/// Whether `name` ends in `.EXTENSION`, ignoring ASCII case. Multi-part
+/// extensions such as `tar.gz` are matched whole.
+fn has_extension(name: &OsStr, extension: &str) -> bool {
+    let extension = extension.strip_prefix('.').unwrap_or(extension).as_bytes();
+    let name = name.as_encoded_bytes();
+    !extension.is_empty()
+        && name.len() > extension.len() + 1
+        && name[name.len() - extension.len() - 1] == b'.'
+        && name[name.len() - extension.len()..].eq_ignore_ascii_case(extension)
+}
+
 fn build_cli_matcher(root: &Path, patterns: &[String]) -> Result<Gitignore> {
     let mut builder = GitignoreBuilder::new(root);
     for pattern in patterns {
@@ -530,3 +959,48 @@ fn build_ignore_file<R: Read>(root: &Path, path: &Path, reader: R) -> Result<Git
         message: error.to_string(),
     })
 }
+
+/// A path from Git's NUL-separated output, which holds raw bytes on Unix
+/// and UTF-8 elsewhere.
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt as _;
+        PathBuf::from(OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn reentered_ancestor_reports_the_directory_chain() {
+        let temporary = TempDir::new().unwrap();
+        let root = temporary.path().join("root");
+        let nested = root.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        let ancestors = vec![
+            (Handle::from_path(&root).unwrap(), root.clone()),
+            (Handle::from_path(&nested).unwrap(), nested.clone()),
+        ];
+        let mount = nested.join("mount");
+
+        let error =
+            check_cycle(&ancestors, &Handle::from_path(&root).unwrap(), &mount).unwrap_err();
+        let TextconError::CircularReference { path, stack } = error else {
+            panic!("expected a circular reference, got {error}");
+        };
+        assert_eq!(path, mount);
+        assert_eq!(stack, [root, nested, mount]);
+
+        let sibling = temporary.path().join("sibling");
+        fs::create_dir(&sibling).unwrap();
+        check_cycle(&ancestors, &Handle::from_path(&sibling).unwrap(), &sibling).unwrap();
+    }
+}
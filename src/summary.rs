@@ -0,0 +1,154 @@
+#![allow(clippy::redundant_pub_crate)]
+
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes inspected when looking for a leading summary line.
+pub(crate) const SUMMARY_READ_LIMIT: u64 = 4 * 1024;
+
+#[derive(Clone, Copy)]
+enum DocSyntax {
+    Rust,
+    Python,
+}
+
+impl DocSyntax {
+    fn for_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.as_encoded_bytes();
+        if extension.eq_ignore_ascii_case(b"rs") {
+            Some(Self::Rust)
+        } else if extension.eq_ignore_ascii_case(b"py") || extension.eq_ignore_ascii_case(b"pyi") {
+            Some(Self::Python)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` has a language whose leading documentation is recognized.
+pub(crate) fn has_doc_syntax(path: &Path) -> bool {
+    DocSyntax::for_path(path).is_some()
+}
+
+/// Read at most [`SUMMARY_READ_LIMIT`] bytes for summary detection.
+pub(crate) fn read_prefix<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::new();
+    reader.take(SUMMARY_READ_LIMIT).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// Extract the first line of a file's leading documentation: a Rust `//!` or
+/// `///` comment, or a Python module docstring.
+pub(crate) fn doc_summary(path: &Path, prefix: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(prefix);
+    let text = text.trim_start_matches('\u{feff}');
+    let summary = match DocSyntax::for_path(path)? {
+        DocSyntax::Rust => rust_summary(text),
+        DocSyntax::Python => python_summary(text),
+    }?;
+    Some(single_line(summary))
+}
+
+fn rust_summary(text: &str) -> Option<&str> {
+    let mut attribute_depth = 0_usize;
+    for line in text.lines() {
+        let line = line.trim();
+        if attribute_depth > 0 || line.starts_with("#!") || line.starts_with("#[") {
+            attribute_depth = bracket_depth(attribute_depth, line);
+            continue;
+        }
+        if let Some(doc) = line.strip_prefix("//!").or_else(|| {
+            line.strip_prefix("///")
+                .filter(|rest| !rest.starts_with('/'))
+        }) {
+            let doc = doc.trim();
+            if !doc.is_empty() {
+                return Some(doc);
+            }
+        } else if !line.is_empty() && !line.starts_with("//") {
+            return None;
+        }
+    }
+    None
+}
+
+fn bracket_depth(depth: usize, line: &str) -> usize {
+    line.bytes().fold(depth, |depth, byte| match byte {
+        b'[' => depth + 1,
+        b']' => depth.saturating_sub(1),
+        _ => depth,
+    })
+}
+
+fn python_summary(text: &str) -> Option<&str> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let first = lines.next()?;
+    let unprefixed = first.trim_start_matches(['r', 'R', 'u', 'U']);
+    let quote = ["\"\"\"", "'''"]
+        .into_iter()
+        .find(|quote| unprefixed.starts_with(quote))?;
+    let opening = unprefixed[quote.len()..].trim();
+    let line = if opening.is_empty() {
+        lines.next()?
+    } else {
+        opening
+    };
+    let line = line.split(quote).next().unwrap_or(line).trim();
+    (!line.is_empty()).then_some(line)
+}
+
+/// Replace control characters so a summary stays on one output line.
+pub(crate) fn single_line(text: &str) -> String {
+    text.chars()
+        .map(|character| {
+            if character.is_control() {
+                ' '
+            } else {
+                character
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rust_summary_skips_attributes_and_plain_comments() {
+        let source = b"// SPDX-License-Identifier: EUPL-1.2\n#![allow(\n    clippy::all\n)]\n\n//!\n//! Streaming reference scanner.\n//! More.\nuse std::io;\n";
+        assert_eq!(
+            doc_summary(Path::new("src/parser.rs"), source).as_deref(),
+            Some("Streaming reference scanner.")
+        );
+        assert_eq!(
+            doc_summary(Path::new("main.rs"), b"fn main() {}\n/// late\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn python_summary_reads_single_and_multi_line_docstrings() {
+        assert_eq!(
+            doc_summary(
+                Path::new("a.py"),
+                b"#!/usr/bin/env python3\n\"\"\"Bundle a codebase.\"\"\"\n"
+            )
+            .as_deref(),
+            Some("Bundle a codebase.")
+        );
+        assert_eq!(
+            doc_summary(
+                Path::new("b.py"),
+                b"r'''\n  Helpers for tests.\n\n  More.\n'''\n"
+            )
+            .as_deref(),
+            Some("Helpers for tests.")
+        );
+        assert_eq!(doc_summary(Path::new("c.py"), b"import os\n"), None);
+        assert_eq!(doc_summary(Path::new("c.txt"), b"\"\"\"No.\"\"\"\n"), None);
+    }
+}
@@ -0,0 +1,620 @@
+#![allow(clippy::missing_const_for_fn, clippy::redundant_pub_crate)]
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead as _, BufReader, Read as _, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::engine::TreeOptions;
+use crate::error::{Result, TextconError};
+use crate::render::{encode_path, rfc3339};
+use crate::selector::{Child, Directory, Visitor};
+use crate::summary::{SUMMARY_READ_LIMIT, doc_summary, has_doc_syntax, read_prefix, single_line};
+
+const README_NAME: &str = "README.md";
+const BREADTH_SUMMARY_EXTENSIONS: usize = 3;
+/// Length of the `YYYY-MM-DD` prefix of an RFC 3339 timestamp.
+const DATE_LENGTH: usize = 10;
+
+/// Streams a box-drawn listing of selected descendants, one line per entry.
+pub(crate) struct TreeWriter<'a, W> {
+    options: &'a TreeOptions,
+    doc_summaries: bool,
+    output: &'a mut W,
+    depth: usize,
+    last_ancestors: Vec<bool>,
+    /// README annotation for a directory line awaiting its children.
+    pending_summary: Option<String>,
+}
+
+impl<'a, W: Write> TreeWriter<'a, W> {
+    pub(crate) fn new(options: &'a TreeOptions, doc_summaries: bool, output: &'a mut W) -> Self {
+        Self {
+            options,
+            doc_summaries,
+            output,
+            depth: 0,
+            last_ancestors: Vec::new(),
+            pending_summary: None,
+        }
+    }
+
+    fn write_entry(&mut self, name: &str, last: bool) -> Result<()> {
+        self.output
+            .write_all(entry_line(&self.last_ancestors, name, last).as_bytes())
+            .map_err(TextconError::output)
+    }
+
+    fn finish_line(&mut self, summary: Option<&str>) -> Result<()> {
+        if let Some(summary) = summary {
+            write!(self.output, " # {summary}").map_err(TextconError::output)?;
+        }
+        self.output.write_all(b"\n").map_err(TextconError::output)
+    }
+
+    fn summary(&self, path: &Path, directory: &Directory<'_>) -> Result<Option<String>> {
+        if !self.options.readme_summaries {
+            return Ok(None);
+        }
+        readme_summary(directory)
+            .map_err(|error| TextconError::path_io("read summary", path.join(README_NAME), error))
+    }
+}
+
+impl<W: Write> Visitor for TreeWriter<'_, W> {
+    fn file(&mut self, path: &Path, mut file: File, last: bool) -> Result<()> {
+        let summary = if self.doc_summaries && has_doc_syntax(path) {
+            let prefix = read_prefix(&mut file)
+                .map_err(|error| TextconError::path_io("read summary", path, error))?;
+            doc_summary(path, &prefix)
+        } else {
+            None
+        };
+        if self.depth == 0 {
+            self.output
+                .write_all(listing_label(path, false).as_bytes())
+                .map_err(TextconError::output)?;
+        } else {
+            self.write_entry(&entry_name(path), last)?;
+        }
+        if self.options.file_sizes {
+            let length = file
+                .metadata()
+                .map_err(|error| TextconError::path_io("inspect", path, error))?
+                .len();
+            write!(self.output, " ({})", human_size(length)).map_err(TextconError::output)?;
+        }
+        self.finish_line(summary.as_deref())
+    }
+
+    fn enter_directory(&mut self, path: &Path, directory: Directory<'_>, last: bool) -> Result<()> {
+        let summary = self.summary(path, &directory)?;
+        if self.depth == 0 {
+            self.output
+                .write_all(listing_label(path, true).as_bytes())
+                .map_err(TextconError::output)?;
+        } else {
+            self.write_entry(&format!("{}/", entry_name(path)), last)?;
+            self.last_ancestors.push(last);
+        }
+        self.depth += 1;
+        self.pending_summary = summary;
+        Ok(())
+    }
+
+    fn children(&mut self, children: &[Child<'_>]) -> Result<bool> {
+        let collapse = self
+            .options
+            .summarize_breadth_over
+            .is_some_and(|limit| children.len() > limit);
+        if collapse {
+            write!(self.output, " ({})", describe_breadth(children))
+                .map_err(TextconError::output)?;
+        }
+        let summary = self.pending_summary.take();
+        self.finish_line(summary.as_deref())?;
+        Ok(!collapse)
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        self.depth -= 1;
+        if self.depth > 0 {
+            self.last_ancestors.pop();
+        }
+        Ok(())
+    }
+
+    fn lists_symlinks(&self) -> bool {
+        self.options.symlink_targets
+    }
+
+    fn symlink(&mut self, path: &Path, target: &Path, last: bool) -> Result<()> {
+        let entry = format!(
+            "{} -> {}",
+            entry_name(path),
+            encode_path(target.as_os_str())
+        );
+        self.write_entry(&entry, last)?;
+        self.finish_line(None)
+    }
+}
+
+/// Streams a Mermaid `graph TD` diagram of selected descendants, with one
+/// node per entry and an edge from each directory to its children.
+pub(crate) struct MermaidWriter<'a, W> {
+    output: &'a mut W,
+    next_id: usize,
+    parents: Vec<usize>,
+}
+
+impl<'a, W: Write> MermaidWriter<'a, W> {
+    pub(crate) fn new(output: &'a mut W) -> Self {
+        Self {
+            output,
+            next_id: 0,
+            parents: Vec::new(),
+        }
+    }
+
+    /// Write the node for `path`, and its edge from the enclosing directory,
+    /// returning its id. Encoded labels never contain quotes.
+    fn write_node(&mut self, path: &Path, suffix: &str) -> Result<usize> {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(&parent) = self.parents.last() {
+            let label = entry_name(path);
+            writeln!(self.output, "    n{parent} --> n{id}[\"{label}{suffix}\"]")
+        } else {
+            let label = if path.as_os_str().is_empty() {
+                ".".to_owned()
+            } else {
+                encode_path(path.as_os_str())
+            };
+            writeln!(self.output, "graph TD\n    n{id}[\"{label}{suffix}\"]")
+        }
+        .map_err(TextconError::output)?;
+        Ok(id)
+    }
+}
+
+impl<W: Write> Visitor for MermaidWriter<'_, W> {
+    fn file(&mut self, path: &Path, _file: File, _last: bool) -> Result<()> {
+        self.write_node(path, "").map(|_| ())
+    }
+
+    fn enter_directory(
+        &mut self,
+        path: &Path,
+        _directory: Directory<'_>,
+        _last: bool,
+    ) -> Result<()> {
+        let id = self.write_node(path, "/")?;
+        self.parents.push(id);
+        Ok(())
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        self.parents.pop();
+        Ok(())
+    }
+}
+
+/// Writes one line totalling the selected files of a reference, such as
+/// `Directory src/: 12 files, 47,832 bytes, last modified 2024-03-01`.
+pub(crate) struct SummaryWriter<'a, W> {
+    output: &'a mut W,
+    /// The encoded root label, once the root directory has been entered.
+    root: Option<String>,
+    depth: usize,
+    files: usize,
+    bytes: u64,
+    newest: Option<SystemTime>,
+}
+
+impl<'a, W: Write> SummaryWriter<'a, W> {
+    pub(crate) fn new(output: &'a mut W) -> Self {
+        Self {
+            output,
+            root: None,
+            depth: 0,
+            files: 0,
+            bytes: 0,
+            newest: None,
+        }
+    }
+
+    fn write_line(&mut self, kind: &str, label: &str) -> Result<()> {
+        let mut line = format!(
+            "{kind} {label}: {}, {}",
+            count_noun(self.files, "file", "files"),
+            count_noun(self.bytes, "byte", "bytes")
+        );
+        if let Some(newest) = self.newest {
+            line.push_str(", last modified ");
+            line.push_str(&rfc3339(newest)[..DATE_LENGTH]);
+        }
+        writeln!(self.output, "{line}").map_err(TextconError::output)
+    }
+}
+
+impl<W: Write> Visitor for SummaryWriter<'_, W> {
+    fn file(&mut self, path: &Path, file: File, _last: bool) -> Result<()> {
+        let metadata = file
+            .metadata()
+            .map_err(|error| TextconError::path_io("inspect", path, error))?;
+        self.files += 1;
+        self.bytes += metadata.len();
+        if let Ok(modified) = metadata.modified() {
+            self.newest = self.newest.max(Some(modified));
+        }
+        if self.depth == 0 {
+            return self.write_line("File", &root_label(path));
+        }
+        Ok(())
+    }
+
+    fn enter_directory(
+        &mut self,
+        path: &Path,
+        _directory: Directory<'_>,
+        _last: bool,
+    ) -> Result<()> {
+        if self.depth == 0 {
+            self.root = Some(root_label(path));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        self.depth -= 1;
+        match self.root.take() {
+            Some(label) if self.depth == 0 => self.write_line("Directory", &format!("{label}/")),
+            root => {
+                self.root = root;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The files and directories selected beneath a target, in the order and
+/// under the selection rules `| tree` lists them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct DirectoryTree {
+    /// The target itself: a directory, or a file with no children.
+    pub root: TreeNode,
+}
+
+impl DirectoryTree {
+    /// Logical paths of the selected files, depth first in walk order.
+    #[must_use]
+    pub fn to_flat_list(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        self.root
+            .visit_files(&mut |node| files.push(node.path.clone()));
+        files
+    }
+
+    /// Number of selected files.
+    #[must_use]
+    pub fn file_count(&self) -> usize {
+        let mut count = 0;
+        self.root.visit_files(&mut |_| count += 1);
+        count
+    }
+
+    /// Combined size of the selected files in bytes.
+    #[must_use]
+    pub fn total_size(&self) -> u64 {
+        let mut total = 0;
+        self.root.visit_files(&mut |node| total += node.size);
+        total
+    }
+
+    /// The box-drawn listing that `| tree` writes for the same target with
+    /// default [`TreeOptions`], one line per entry.
+    #[must_use]
+    pub fn to_tree_string(&self) -> String {
+        let mut listing = listing_label(&self.root.path, self.root.is_dir);
+        listing.push('\n');
+        self.root.list_children(&mut Vec::new(), &mut listing);
+        listing
+    }
+}
+
+/// A selected file or directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct TreeNode {
+    /// Logical path, as a rendered reference labels it.
+    pub path: PathBuf,
+    /// Whether the entry is a directory rather than a file.
+    pub is_dir: bool,
+    /// File size in bytes; zero for directories.
+    pub size: u64,
+    /// Selected entries of a directory, in walk order.
+    pub children: Vec<Self>,
+}
+
+impl TreeNode {
+    fn visit_files(&self, visit: &mut impl FnMut(&Self)) {
+        if !self.is_dir {
+            visit(self);
+        }
+        for child in &self.children {
+            child.visit_files(visit);
+        }
+    }
+
+    /// Append a line for each descendant, as [`TreeWriter`] writes it.
+    fn list_children(&self, last_ancestors: &mut Vec<bool>, listing: &mut String) {
+        for (index, child) in self.children.iter().enumerate() {
+            let last = index + 1 == self.children.len();
+            let mut name = entry_name(&child.path);
+            if child.is_dir {
+                name.push('/');
+            }
+            listing.push_str(&entry_line(last_ancestors, &name, last));
+            listing.push('\n');
+            if child.is_dir {
+                last_ancestors.push(last);
+                child.list_children(last_ancestors, listing);
+                last_ancestors.pop();
+            }
+        }
+    }
+}
+
+/// Collects a walk into a [`DirectoryTree`].
+#[derive(Default)]
+pub(crate) struct TreeBuilder {
+    /// Directories entered but not yet left, outermost first.
+    open: Vec<TreeNode>,
+    root: Option<TreeNode>,
+}
+
+impl TreeBuilder {
+    fn add(&mut self, node: TreeNode) {
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.root = Some(node),
+        }
+    }
+
+    pub(crate) fn finish(self) -> DirectoryTree {
+        DirectoryTree {
+            root: self.root.expect("a walk visits its target"),
+        }
+    }
+}
+
+impl Visitor for TreeBuilder {
+    fn file(&mut self, path: &Path, file: File, _last: bool) -> Result<()> {
+        let metadata = file
+            .metadata()
+            .map_err(|error| TextconError::path_io("inspect file", path, error))?;
+        self.add(TreeNode {
+            path: path.to_path_buf(),
+            is_dir: false,
+            size: metadata.len(),
+            children: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn enter_directory(
+        &mut self,
+        path: &Path,
+        _directory: Directory<'_>,
+        _last: bool,
+    ) -> Result<()> {
+        self.open.push(TreeNode {
+            path: path.to_path_buf(),
+            is_dir: true,
+            size: 0,
+            children: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn leave_directory(&mut self) -> Result<()> {
+        if let Some(node) = self.open.pop() {
+            self.add(node);
+        }
+        Ok(())
+    }
+}
+
+/// The encoded label of a reference target, with `.` for the base directory.
+fn root_label(path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        ".".to_owned()
+    } else {
+        encode_path(path.as_os_str())
+    }
+}
+
+/// The first line of a listing: the target's label, followed by `/` for a
+/// directory.
+fn listing_label(path: &Path, is_dir: bool) -> String {
+    let mut label = root_label(path);
+    if is_dir {
+        label.push('/');
+    }
+    label
+}
+
+/// An entry's line, without its ending, beneath ancestors that were or were
+/// not the last of their siblings.
+fn entry_line(last_ancestors: &[bool], name: &str, last: bool) -> String {
+    let mut line = String::new();
+    for &ancestor_last in last_ancestors {
+        line.push_str(if ancestor_last { "    " } else { "│   " });
+    }
+    line.push_str(if last { "└── " } else { "├── " });
+    line.push_str(name);
+    line
+}
+
+fn entry_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(|| encode_path(path.as_os_str()), encode_path)
+}
+
+/// Describe collapsed entries by kind and their most common file extensions,
+/// for example `2,104 files: *.sql`.
+fn describe_breadth(children: &[Child<'_>]) -> String {
+    let directories = children.iter().filter(|child| child.is_dir).count();
+    let files = children.len() - directories;
+    let mut extensions = BTreeMap::<String, usize>::new();
+    for child in children.iter().filter(|child| !child.is_dir) {
+        if let Some(extension) = Path::new(child.name).extension() {
+            *extensions.entry(encode_path(extension)).or_default() += 1;
+        }
+    }
+    let mut dominant = extensions.into_iter().collect::<Vec<_>>();
+    dominant.sort_by(|left, right| right.1.cmp(&left.1).then_with(|| left.0.cmp(&right.0)));
+
+    let mut parts = Vec::new();
+    if directories > 0 {
+        parts.push(count_noun(directories, "directory", "directories"));
+    }
+    if files > 0 || directories == 0 {
+        parts.push(count_noun(files, "file", "files"));
+    }
+    let mut description = parts.join(", ");
+    if !dominant.is_empty() {
+        let patterns = dominant
+            .iter()
+            .take(BREADTH_SUMMARY_EXTENSIONS)
+            .map(|(extension, _)| format!("*.{extension}"))
+            .collect::<Vec<_>>();
+        description.push_str(": ");
+        description.push_str(&patterns.join(", "));
+    }
+    description
+}
+
+/// Format a byte count rounded to one decimal in the largest binary unit
+/// that keeps it at least 1, such as `4.2 KB`; counts below 1 KB stay exact.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KB", "MB", "GB", "TB", "PB", "EB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let bytes = u128::from(bytes);
+    let mut divisor = 1024;
+    let mut unit = 0;
+    while bytes >= divisor * 1024 && unit + 1 < UNITS.len() {
+        divisor *= 1024;
+        unit += 1;
+    }
+    let tenths = (bytes * 10 + divisor / 2) / divisor;
+    format!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit])
+}
+
+fn count_noun<T>(count: T, singular: &str, plural: &str) -> String
+where
+    T: Copy + std::fmt::Display + PartialEq + From<u8>,
+{
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!(
+        "{grouped} {}",
+        if count == T::from(1) {
+            singular
+        } else {
+            plural
+        }
+    )
+}
+
+/// Read the first nonblank line of a directory's README, without its ATX
+/// heading markers, as a single-line annotation.
+fn readme_summary(directory: &Directory<'_>) -> io::Result<Option<String>> {
+    let file = match directory.open_file(README_NAME) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let mut reader = BufReader::new(file.take(SUMMARY_READ_LIMIT));
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        let text = String::from_utf8_lossy(&line);
+        let summary = text
+            .trim_start_matches('\u{feff}')
+            .trim()
+            .trim_start_matches('#')
+            .trim();
+        if !summary.is_empty() {
+            return Ok(Some(single_line(summary)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use tempfile::TempDir;
+
+    #[test]
+    fn readme_summary_skips_blank_lines_and_heading_markers() {
+        let temporary = TempDir::new().unwrap();
+        std::fs::write(
+            temporary.path().join(README_NAME),
+            "\n\n## Parser internals\nMore text\n",
+        )
+        .unwrap();
+        let summary = readme_summary(&Directory::Ambient(temporary.path())).unwrap();
+        assert_eq!(summary.as_deref(), Some("Parser internals"));
+    }
+
+    #[test]
+    fn breadth_description_groups_digits_and_ranks_extensions() {
+        let names =
+            ["a.sql", "b.sql", "c.md", "d", "sub"].map(|name| (OsStr::new(name), name == "sub"));
+        let children = names
+            .iter()
+            .map(|&(name, is_dir)| Child { name, is_dir })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            describe_breadth(&children),
+            "1 directory, 4 files: *.sql, *.md"
+        );
+        assert_eq!(count_noun(2104, "file", "files"), "2,104 files");
+        assert_eq!(count_noun(1_000_000, "file", "files"), "1,000,000 files");
+    }
+
+    #[test]
+    fn human_sizes_round_to_one_decimal_in_binary_units() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1024), "1.0 KB");
+        assert_eq!(human_size(4300), "4.2 KB");
+        assert_eq!(human_size(12_700_000), "12.1 MB");
+        assert_eq!(human_size(u64::MAX), "16.0 EB");
+    }
+
+    #[test]
+    fn missing_readme_has_no_summary() {
+        let temporary = TempDir::new().unwrap();
+        let summary = readme_summary(&Directory::Ambient(temporary.path())).unwrap();
+        assert_eq!(summary, None);
+    }
+}
@@ -17,6 +17,41 @@ fn no_input_is_usage_error() {
     assert!(output.stdout.is_empty());
 }
 
+#[test]
+fn bare_invocation_expands_the_default_template() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("project")).unwrap();
+    fs::write(temporary.path().join("project/notes"), "NOTES").unwrap();
+    fs::write(
+        temporary.path().join("project/textcon.template"),
+        "[{{ @notes }}]",
+    )
+    .unwrap();
+    fs::write(temporary.path().join("project/custom"), "({{ @notes }})").unwrap();
+
+    let bare = textcon()
+        .current_dir(temporary.path().join("project"))
+        .output()
+        .unwrap();
+    assert!(
+        bare.status.success(),
+        "{}",
+        String::from_utf8_lossy(&bare.stderr)
+    );
+    assert_eq!(bare.stdout, b"[NOTES]");
+
+    let based = textcon()
+        .current_dir(temporary.path())
+        .args(["--base-dir", "project", "--default-template", "custom"])
+        .output()
+        .unwrap();
+    assert_eq!(based.stdout, b"(NOTES)");
+
+    let absent = textcon().current_dir(temporary.path()).output().unwrap();
+    assert_eq!(absent.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&absent.stderr).contains("textcon.template"));
+}
+
 #[test]
 fn direct_markdown_is_labelled_and_adaptive() {
     let temporary = TempDir::new().unwrap();
@@ -161,6 +196,73 @@ fn parent_directory_spelling_uses_the_selected_gitignore_hierarchy() {
     assert_eq!(template.stdout, b"KEEP");
 }
 
+#[cfg(unix)]
+#[test]
+fn symlink_cycle_in_referenced_directory_terminates() {
+    use std::os::unix::fs::symlink;
+
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("project/nested")).unwrap();
+    fs::write(temporary.path().join("project/nested/file"), "F").unwrap();
+    symlink("..", temporary.path().join("project/nested/up")).unwrap();
+    symlink("project", temporary.path().join("loop")).unwrap();
+
+    fs::write(temporary.path().join("template"), "{{ @loop | tree }}").unwrap();
+
+    for sandbox in [&[][..], &["--sandbox"]] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--template", "template"])
+            .args(sandbox)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            output.stdout,
+            "loop/\n└── nested/\n    └── file\n".as_bytes()
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_targets_list_symlinks_without_following_them() {
+    use std::os::unix::fs::symlink;
+
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("project/nested")).unwrap();
+    fs::write(temporary.path().join("project/nested/file"), "F").unwrap();
+    symlink("/some/target", temporary.path().join("project/mylink")).unwrap();
+    symlink("..", temporary.path().join("project/nested/up")).unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @project | tree }}{{ @project | raw }}",
+    )
+    .unwrap();
+
+    for sandbox in [&[][..], &["--sandbox"]] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--template", "template", "--symlink-targets"])
+            .args(sandbox)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "project/\n├── mylink -> /some/target\n└── nested/\n    ├── file\n    └── up -> ..\nF"
+        );
+    }
+}
+
 #[cfg(unix)]
 #[test]
 fn explicitly_selected_directory_symlink_keeps_its_filter_namespace() {
@@ -335,6 +437,47 @@ fn hidden_and_depth_policies_are_shared() {
     assert!(output.stdout.contains(&b'H'));
 }
 
+#[test]
+fn hidden_exposes_dotfiles_in_tree_listings_and_bodies() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("project/src")).unwrap();
+    fs::write(temporary.path().join("project/.env"), "KEY=1\n").unwrap();
+    fs::write(temporary.path().join("project/.gitignore"), "target/\n").unwrap();
+    fs::write(
+        temporary.path().join("project/src/main.rs"),
+        "fn main() {}\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @project | tree }}{{ @project | raw }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "project/\n└── src/\n    └── main.rs\nfn main() {}\n"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--hidden"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "project/\n├── .env\n├── .gitignore\n└── src/\n    └── main.rs\n\
+         KEY=1\ntarget/\nfn main() {}\n"
+    );
+}
+
 #[test]
 fn filename_cannot_inject_template_references() {
     let temporary = TempDir::new().unwrap();
@@ -518,3 +661,2135 @@ fn template_stdin_streams() {
     assert!(output.status.success());
     assert_eq!(output.stdout, b"x=VALUE");
 }
+
+#[test]
+fn mermaid_reference_draws_selected_descendants() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("src/parser")).unwrap();
+    fs::write(temporary.path().join("src/lib.rs"), "").unwrap();
+    fs::write(temporary.path().join("src/parser/mod.rs"), "").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "```mermaid\n{{ @src | mermaid }}```\n{{ @src/lib.rs | mermaid }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "```mermaid\ngraph TD\n    n0[\"src/\"]\n    n0 --> n1[\"lib.rs\"]\n    \
+         n0 --> n2[\"parser/\"]\n    n2 --> n3[\"mod.rs\"]\n```\n\
+         graph TD\n    n0[\"src/lib.rs\"]\n"
+    );
+}
+
+#[test]
+fn summary_reference_totals_selected_files() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let temporary = TempDir::new().unwrap();
+    let project = temporary.path().join("project");
+    fs::create_dir_all(project.join("src/empty")).unwrap();
+    for (name, size, day) in [("src/lib.rs", 1000, 19_000), ("src/main.rs", 234, 19_783)] {
+        fs::write(project.join(name), vec![b'x'; size]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(project.join(name))
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_hours(day * 24))
+            .unwrap();
+    }
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @. | summary }}{{ @[src/main.rs, src/empty] | summary }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--base-dir", "project"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Directory ./: 2 files, 1,234 bytes, last modified 2024-03-01\n\
+         File src/main.rs: 1 file, 234 bytes, last modified 2024-03-01\n\
+         Directory src/empty/: 0 files, 0 bytes\n"
+    );
+}
+
+#[test]
+fn separator_goes_between_included_files() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/a"), "A\n").unwrap();
+    fs::write(temporary.path().join("src/b"), "B\n").unwrap();
+    fs::write(temporary.path().join("c"), "C\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "<{{ @src }}|{{ @[c, src/b] }}|{{ @c }}>",
+    )
+    .unwrap();
+
+    for (arguments, expected) in [
+        (&["src", "c"][..], "A\n---\nB\n---\nC\n"),
+        (&["--template", "template"], "<A\n---\nB\n|C\n---\nB\n|C\n>"),
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--render", "raw", "--separator", "---\\n"])
+            .args(arguments)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    let invalid = textcon()
+        .args(["--separator", "\\q", "c"])
+        .output()
+        .unwrap();
+    assert_eq!(invalid.status.code(), Some(2));
+}
+
+#[test]
+fn header_and_footer_frame_the_output() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("c"), "C\n").unwrap();
+    fs::write(temporary.path().join("preamble"), "Context:\n").unwrap();
+    fs::write(temporary.path().join("template"), "{{ @c }}").unwrap();
+
+    for (arguments, expected) in [
+        (
+            &["--header", "You are helpful.\\n", "--footer", "# END"][..],
+            "You are helpful.\nC\n# END",
+        ),
+        (
+            &["--header-file", "preamble", "--footer", "-- \\t"],
+            "Context:\nC\n-- \t",
+        ),
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--render", "raw", "--template", "template"])
+            .args(arguments)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    let validate = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--validate", "--footer", "# END"])
+        .output()
+        .unwrap();
+    assert_eq!(validate.status.code(), Some(2));
+}
+
+#[test]
+fn indent_prefixes_every_included_line() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(
+        temporary.path().join("src/a.rs"),
+        "fn a() {}\n\nfn b() {}\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("nested.md"),
+        "Nested:\n{{ @src | tree }}",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "Context:\n{{ @src | markdown }}{{ @nested.md | template }}Done\n",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--indent", "> "])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Context:\n> # `src/a.rs`\n> \n> fn a() {}\n> \n> fn b() {}\n> \n\
+         > Nested:\n> src/\n> └── a.rs\nDone\n"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--render", "raw", "--indent", "    ", "src/a.rs"])
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"    fn a() {}\n    \n    fn b() {}\n");
+}
+
+#[test]
+fn file_markers_wrap_every_included_file() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temporary.path().join("src/unterminated.rs"), "fn x() {}").unwrap();
+    fs::write(temporary.path().join("template"), "{{ @src }}").unwrap();
+
+    for (style, expected) in [
+        (
+            "banner",
+            "=== File: src/main.rs ===\nfn main() {}\n=== End: src/main.rs ===\n\
+             === File: src/unterminated.rs ===\nfn x() {}\n=== End: src/unterminated.rs ===\n",
+        ),
+        (
+            "c-style",
+            "/* File: src/main.rs */\nfn main() {}\n/* End: src/main.rs */\n\
+             /* File: src/unterminated.rs */\nfn x() {}\n/* End: src/unterminated.rs */\n",
+        ),
+        (
+            "xml",
+            "<document index=\"1\">\n<source>src/main.rs</source>\n<document_content>\n\
+             fn main() {}\n</document_content>\n</document>\n\
+             <document index=\"2\">\n<source>src/unterminated.rs</source>\n<document_content>\n\
+             fn x() {}\n</document_content>\n</document>\n",
+        ),
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args([
+                "--render",
+                "raw",
+                "--template",
+                "template",
+                "--file-markers",
+                style,
+            ])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--file-markers", "html-comment", "src/main.rs"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "<!-- File: src/main.rs -->\n# `src/main.rs`\n\nfn main() {}\n\n<!-- End: src/main.rs -->\n"
+    );
+}
+
+#[test]
+fn show_context_prints_template_lines_around_a_failing_reference() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "# Review\n\nHere is the code:\n{{ @nonexistent.rs }}\n",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--show-context"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.ends_with(
+            "(template line 4, col 1)\n  3 | Here is the code:\n  4 | {{ @nonexistent.rs }}  <-- here\n  5 | \n"
+        ),
+        "{stderr}"
+    );
+    assert!(stderr.contains("nonexistent.rs"), "{stderr}");
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("(template line 4, col 1)\n"), "{stderr}");
+}
+
+#[test]
+fn walk_order_sorts_entries_by_size_or_modification_time() {
+    use std::time::{Duration, SystemTime};
+
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("files")).unwrap();
+    let now = SystemTime::now();
+    for (name, size, age) in [("a", 1, 30), ("b", 30, 10), ("c", 10, 20)] {
+        let file = fs::File::create(temporary.path().join("files").join(name)).unwrap();
+        file.set_len(size).unwrap();
+        file.set_modified(now - Duration::from_secs(age)).unwrap();
+    }
+    fs::write(temporary.path().join("template"), "{{ @files | tree }}").unwrap();
+    fs::write(
+        temporary.path().join("reversed"),
+        "{{ @files | tree | reverse }}",
+    )
+    .unwrap();
+
+    for (template, order, expected) in [
+        ("template", "size-desc", "b c a"),
+        ("template", "size-asc", "a c b"),
+        ("reversed", "size-desc", "a c b"),
+        ("template", "modified-desc", "b c a"),
+        ("template", "modified-asc", "a c b"),
+        ("template", "name", "a b c"),
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--template", template, "--walk-order", order])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let listing = String::from_utf8(output.stdout).unwrap();
+        let names = listing
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(names, expected, "{order}");
+    }
+}
+
+#[test]
+fn git_tracked_discovers_only_tracked_files() {
+    let temporary = TempDir::new().unwrap();
+    let project = temporary.path().join("project");
+    fs::create_dir_all(project.join("src/generated")).unwrap();
+    fs::write(project.join("src/lib.rs"), "TRACKED\n").unwrap();
+    fs::write(project.join("src/scratch.rs"), "UNTRACKED\n").unwrap();
+    fs::write(project.join("src/generated/out.rs"), "GENERATED\n").unwrap();
+    let git = |arguments: &[&str]| {
+        Command::new("git")
+            .current_dir(&project)
+            .args(arguments)
+            .output()
+            .unwrap()
+    };
+    if !git(&["init", "--quiet"]).status.success() {
+        return;
+    }
+    assert!(git(&["add", "src/lib.rs"]).status.success());
+    fs::write(
+        project.join("template"),
+        "{{ @src | tree }}{{ @src | raw }}",
+    )
+    .unwrap();
+
+    for sandbox in [&[][..], &["--sandbox"]] {
+        let output = textcon()
+            .current_dir(&project)
+            .args(["--template", "template", "--git-tracked"])
+            .args(sandbox)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "src/\n└── lib.rs\nTRACKED\n"
+        );
+    }
+
+    let outside = TempDir::new().unwrap();
+    fs::write(outside.path().join("loose.rs"), "LOOSE").unwrap();
+    let output = textcon()
+        .current_dir(outside.path())
+        .args(["--render", "raw", "--git-tracked", "."])
+        .env("GIT_CEILING_DIRECTORIES", outside.path().parent().unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"LOOSE");
+}
+
+#[test]
+fn git_references_need_allow_commands() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("template"), "{{ @git:branch }}").unwrap();
+
+    for extra in [None, Some("--validate")] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--template", "template"])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("reference git:branch runs a program, which is not allowed")
+        );
+    }
+}
+
+#[test]
+fn git_references_render_repository_metadata() {
+    let temporary = TempDir::new().unwrap();
+    let project = temporary.path();
+    let git = |arguments: &[&str]| {
+        Command::new("git")
+            .current_dir(project)
+            .args([
+                "-c",
+                "user.name=textcon",
+                "-c",
+                "user.email=textcon@example.com",
+            ])
+            .args(arguments)
+            .output()
+            .unwrap()
+    };
+    if !git(&["init", "--quiet", "--initial-branch=trunk"])
+        .status
+        .success()
+    {
+        return;
+    }
+    fs::write(project.join("notes.txt"), "first\n").unwrap();
+    assert!(git(&["add", "notes.txt"]).status.success());
+    assert!(git(&["commit", "--quiet", "-m", "first"]).status.success());
+    assert!(git(&["tag", "v1.0.0"]).status.success());
+    let commit = git(&["rev-parse", "--short", "HEAD"]).stdout;
+    let commit = String::from_utf8(commit).unwrap();
+    fs::write(project.join("notes.txt"), "second\n").unwrap();
+    fs::write(project.join("new.txt"), "new\n").unwrap();
+    fs::write(
+        project.join("template"),
+        "on {{ @git:branch }} at {{ @git:commit }} ({{ @git:tag }})\n\
+         {{ @git:status | grep=notes }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(project)
+        .args(["--template", "template", "--allow-commands"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("on trunk at {} (v1.0.0)\n M notes.txt\n", commit.trim())
+    );
+
+    let output = textcon()
+        .current_dir(project)
+        .args(["--template", "template", "--allow-commands", "--sandbox"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("sandbox denied reference git:branch")
+    );
+
+    let outside = TempDir::new().unwrap();
+    fs::write(outside.path().join("template"), "{{ @git:commit }}").unwrap();
+    let output = textcon()
+        .current_dir(outside.path())
+        .args(["--template", "template", "--allow-commands"])
+        .env("GIT_CEILING_DIRECTORIES", outside.path().parent().unwrap())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("git rev-parse --short HEAD failed"));
+
+    fs::write(project.join("other.txt"), "other\n").unwrap();
+    assert!(git(&["add", "other.txt"]).status.success());
+    assert!(git(&["commit", "--quiet", "-m", "second"]).status.success());
+    assert!(git(&["add", "new.txt"]).status.success());
+    fs::write(
+        project.join("template"),
+        "{{ @git:log:3 }}--\n{{ @git:log:notes.txt }}--\n\
+         {{ @git:diff | grep=^[-+][a-z] }}--\n{{ @git:diff:staged | grep=^[-+][a-z] }}",
+    )
+    .unwrap();
+    let output = textcon()
+        .current_dir(project)
+        .args(["--template", "template", "--allow-commands"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let head = String::from_utf8(git(&["rev-parse", "--short", "HEAD"]).stdout).unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{} second\n{} first\n--\n{} first\n--\n+new\n-first\n+second\n--\n+new\n",
+            head.trim(),
+            commit.trim(),
+            commit.trim()
+        )
+    );
+}
+
+#[test]
+fn builtin_references_render_run_values() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("hostname"), "FILE\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @:date:%Y }}|{{ @:username }}|{{ @:cwd }}|{{ @hostname }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--sandbox"])
+        .env("USER", "ada")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<_> = stdout.split('|').collect();
+    assert_eq!(fields.len(), 4, "{stdout}");
+    assert!(
+        fields[0].len() == 4 && fields[0].parse::<u32>().unwrap() >= 2024,
+        "{stdout}"
+    );
+    assert_eq!(fields[1], "ada");
+    assert_eq!(
+        std::path::Path::new(fields[2]).canonicalize().unwrap(),
+        temporary.path().canonicalize().unwrap()
+    );
+    assert_eq!(fields[3], "FILE\n");
+
+    fs::write(temporary.path().join("template"), "{{ @:date:%Q }}").unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unsupported date specifier %Q"));
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn zip_references_list_and_expand_text_members() {
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    let temporary = TempDir::new().unwrap();
+    let mut archive = ZipWriter::new(fs::File::create(temporary.path().join("dist.zip")).unwrap());
+    for (name, content) in [
+        ("README.md", &b"Read me.\n"[..]),
+        ("bin/tool", b"\x7fELF\0\0\x01"),
+        ("src/lib.rs", b"pub fn f() {}\n"),
+    ] {
+        archive
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(content).unwrap();
+    }
+    archive.finish().unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @zip:dist.zip | tree }}\n{{ @zip:dist.zip | markdown }}",
+    )
+    .unwrap();
+    let report = temporary.path().join("skipped.tsv");
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--sandbox", "--skipped-report"])
+        .arg(&report)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "dist.zip\n\
+         ├── README.md\n\
+         ├── bin/\n\
+         │   └── tool\n\
+         └── src/\n    \
+             └── lib.rs\n\
+         \n\
+         # `dist.zip/README.md`\n\nRead me.\n\n\
+         # `dist.zip/src/lib.rs`\n\npub fn f() {}\n\n"
+    );
+    assert!(
+        fs::read_to_string(&report)
+            .unwrap()
+            .contains("dist.zip/bin/tool")
+    );
+}
+
+#[test]
+fn transform_stage_reformats_json() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("data.json"),
+        "{\"b\":[1,2.50],\"a\":{}}",
+    )
+    .unwrap();
+    fs::write(temporary.path().join("broken.json"), "{\"a\":").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @data.json | transform=pretty }}{{ @data.json | transform=minify | raw }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\n  \"b\": [\n    1,\n    2.50\n  ],\n  \"a\": {}\n}\n{\"b\":[1,2.50],\"a\":{}}\n"
+    );
+
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @broken.json | transform=pretty }}",
+    )
+    .unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid JSON in broken.json"));
+}
+
+#[test]
+fn repeated_templates_expand_in_order() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("notes.txt"), "notes\n").unwrap();
+    fs::write(temporary.path().join("first"), "1 {{ @notes.txt }}").unwrap();
+    fs::write(temporary.path().join("second"), "2 {{ @notes.txt }}").unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["-t", "first", "-t", "second", "--separator", "--\n"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1 notes\n--\n2 notes\n"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--validate",
+            "-t",
+            "first",
+            "-t",
+            "first",
+            "--separator",
+            "SEP",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["-t", "first", "-t", "-"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn stats_reports_totals_on_stderr() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+    fs::write(temporary.path().join("notes.txt"), "notes\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @notes.txt }}{{ @src | tree }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--stats"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "notes\nsrc/\n└── lib.rs\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(
+            "textcon: files read: 1, directories walked: 1, bytes read: 6, bytes written: 28, estimated tokens: 7, time: "
+        ),
+        "{stderr}"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--stats", "notes.txt", "src"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let bytes_read = ["notes.txt", "src/lib.rs"]
+        .iter()
+        .map(|path| fs::metadata(temporary.path().join(path)).unwrap().len())
+        .sum::<u64>();
+    assert!(
+        stderr.starts_with(&format!(
+            "textcon: files read: 2, directories walked: 1, bytes read: {bytes_read}, "
+        )),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn manifest_lists_referenced_files_for_sha256sum() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+    fs::write(temporary.path().join("notes.txt"), "notes\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @notes.txt }}{{ @src }}{{ @notes.txt | raw }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--manifest", "manifest.sha256"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let manifest = fs::read_to_string(temporary.path().join("manifest.sha256")).unwrap();
+    let paths: Vec<_> = manifest
+        .lines()
+        .map(|line| {
+            let (digest, path) = line.split_once("  ").unwrap();
+            assert_eq!(digest.len(), 64, "{manifest}");
+            path
+        })
+        .collect();
+    assert_eq!(paths, ["notes.txt", "src/lib.rs"]);
+
+    if let Ok(check) = Command::new("sha256sum")
+        .current_dir(temporary.path())
+        .args(["--check", "--strict", "manifest.sha256"])
+        .output()
+    {
+        assert!(
+            check.status.success(),
+            "{}",
+            String::from_utf8_lossy(&check.stdout)
+        );
+    }
+
+    let outside = TempDir::new().unwrap();
+    let output = textcon()
+        .current_dir(outside.path())
+        .args(["--template"])
+        .arg(temporary.path().join("template"))
+        .args(["--base-dir"])
+        .arg(temporary.path())
+        .args(["--manifest", "manifest.sha256"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let manifest = fs::read_to_string(outside.path().join("manifest.sha256")).unwrap();
+    let base = temporary.path().display();
+    assert!(
+        manifest.contains(&format!("  {base}/notes.txt\n"))
+            && manifest.ends_with(&format!("  {base}/src/lib.rs\n")),
+        "{manifest}"
+    );
+    fs::create_dir(outside.path().join("project")).unwrap();
+    fs::rename(
+        temporary.path().join("src"),
+        outside.path().join("project/src"),
+    )
+    .unwrap();
+    fs::write(outside.path().join("project/template"), "{{ @src }}").unwrap();
+    let output = textcon()
+        .current_dir(outside.path())
+        .args(["-t", "project/template", "--base-dir", "project"])
+        .args(["--manifest", "manifest.sha256"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let manifest = fs::read_to_string(outside.path().join("manifest.sha256")).unwrap();
+    assert!(manifest.ends_with("  project/src/lib.rs\n"), "{manifest}");
+    if let Ok(check) = Command::new("sha256sum")
+        .current_dir(outside.path())
+        .args(["--check", "--strict", "manifest.sha256"])
+        .output()
+    {
+        assert!(
+            check.status.success(),
+            "{}",
+            String::from_utf8_lossy(&check.stdout)
+        );
+    }
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "-", "--manifest", "manifest.sha256"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("--manifest needs template files, not stdin")
+    );
+}
+
+#[test]
+fn reject_binary_fails_on_files_with_nul_bytes() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("bin")).unwrap();
+    fs::write(temporary.path().join("bin/notes.txt"), "notes\n").unwrap();
+    fs::write(
+        temporary.path().join("bin/tool"),
+        b"\x7fELF\x02\x01\x01\0\0",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--render", "raw", "bin"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.ends_with(b"\0\0"));
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--render", "raw", "--reject-binary", "bin"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"notes\n");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("bin/tool looks binary"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn max_total_size_stops_before_passing_the_limit() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("a.txt"), "a".repeat(600)).unwrap();
+    fs::write(temporary.path().join("b.txt"), "b".repeat(600)).unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--render",
+            "raw",
+            "--max-total-size",
+            "1K",
+            "a.txt",
+            "b.txt",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.len() <= 1000, "{}", output.stdout.len());
+    assert!(output.stdout.starts_with("a".repeat(600).as_bytes()));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("output would exceed the limit of 1000 bytes"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    for size in ["2Ki", "2ki", "2KI", "1m", "1G"] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args([
+                "--render",
+                "raw",
+                "--max-total-size",
+                size,
+                "a.txt",
+                "b.txt",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{size}");
+        assert_eq!(output.stdout.len(), 1200);
+    }
+
+    let output = textcon()
+        .args(["--max-total-size", "10X", "a.txt"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn strict_lists_every_unresolved_reference_before_writing() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("present.txt"), "present\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @present.txt }}\n{{ @missing.txt }}\n{{ @gone/ }}\n",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--strict"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("textcon: 2 of 3 references cannot be resolved:\n"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("missing.txt") && stderr.contains("line 2"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("gone") && stderr.contains("line 3"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("<-- here"), "{stderr}");
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--strict", "--show-context"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("  2 | {{ @missing.txt }}  <-- here\n"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("  3 | {{ @gone/ }}  <-- here\n"),
+        "{stderr}"
+    );
+
+    fs::write(temporary.path().join("missing.txt"), "found\n").unwrap();
+    fs::create_dir(temporary.path().join("gone")).unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--strict"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.starts_with(b"present\n\nfound\n"));
+}
+
+#[test]
+fn missing_placeholder_replaces_references_to_absent_paths() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("present.txt"), "present\n").unwrap();
+    fs::write(
+        temporary.path().join("nested.md"),
+        "nested {{ @absent.txt }}\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @present.txt }}A {{ @missing.txt }}\n{{ @nested.md | template }}B {{ @gone | tree }}\n",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--template",
+            "template",
+            "--missing-placeholder",
+            "[N/A: {path}]",
+            "--strict",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "present\nA [N/A: missing.txt]\nnested [N/A: absent.txt]\nB [N/A: gone]\n"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn line_anchors_match_line_range_stages() {
+    let temporary = TempDir::new().unwrap();
+    let body = (1..=20)
+        .map(|line| format!("line {line}\n"))
+        .collect::<Vec<_>>()
+        .concat();
+    fs::write(temporary.path().join("file.rs"), body).unwrap();
+    let expand = |template: &str| {
+        fs::write(temporary.path().join("template"), template).unwrap();
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--template", "template"])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let anchored = expand("{{ @file.rs#L5-L10 }}");
+    assert_eq!(anchored, expand("{{ @file.rs | lines=5-10 }}"));
+    assert!(anchored.contains("line 5\n"), "{anchored}");
+    assert!(anchored.contains("line 10\n"), "{anchored}");
+    assert!(!anchored.contains("line 4\n") && !anchored.contains("line 11\n"));
+    assert_eq!(expand("{{ @file.rs#L7 | raw }}"), "line 7\n");
+}
+
+#[test]
+fn fmt_prints_templates_with_canonical_placeholders() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "# {{@missing.txt}}\n{{ @ src  |tree }} \\{{ @kept  }}\n",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--fmt", "--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# {{ @missing.txt }}\n{{ @src | tree }} \\{{ @kept  }}\n"
+    );
+
+    fs::write(temporary.path().join("broken"), "{{ @a | bogus }}").unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--fmt", "--template", "broken"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn init_creates_a_starter_template_once() {
+    let temporary = TempDir::new().unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .arg("--init")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+    let template = fs::read_to_string(temporary.path().join("textcon.template")).unwrap();
+    assert!(template.contains("{{ @README.md }}"), "{template}");
+    assert!(template.contains("{{ @. | tree }}"), "{template}");
+
+    fs::write(temporary.path().join("README.md"), "# Demo\n").unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    let output = textcon().current_dir(temporary.path()).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("fn main() {}")
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .arg("--init")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        fs::read_to_string(temporary.path().join("textcon.template")).unwrap(),
+        template
+    );
+}
+
+#[test]
+fn reverse_modifier_emits_newest_logs_first() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("logs")).unwrap();
+    for stamp in ["2024-01-01", "2024-01-02", "2024-01-03"] {
+        fs::write(
+            temporary.path().join(format!("logs/{stamp}.log")),
+            format!("{stamp}\n"),
+        )
+        .unwrap();
+    }
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @logs | markdown | reverse }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let headings = stdout
+        .lines()
+        .filter(|line| line.starts_with("# "))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        headings,
+        [
+            "# `logs/2024-01-03.log`",
+            "# `logs/2024-01-02.log`",
+            "# `logs/2024-01-01.log`"
+        ]
+    );
+}
+
+#[test]
+fn grep_stages_keep_only_matching_lines() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("lib.rs"),
+        "pub fn a() {}\nfn b() {}\npub struct C;\n// pub note\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @lib.rs | grep=^pub }}---\n{{ @lib.rs | grep-v=^pub | grep-v=^// }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "pub fn a() {}\npub struct C;\n---\nfn b() {}\n"
+    );
+}
+
+#[test]
+fn head_and_tail_limit_lines_after_filters() {
+    let temporary = TempDir::new().unwrap();
+    let lines = (1..=10).fold(String::new(), |lines, number| {
+        lines + &format!("line {number}\n")
+    });
+    fs::write(temporary.path().join("file.txt"), lines).unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @file.txt | head=3 }}{{ @file.txt | grep=[02468]$ | tail=2 }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "line 1\nline 2\nline 3\nline 8\nline 10\n"
+    );
+}
+
+#[test]
+fn section_stage_includes_only_the_named_region() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("lib.rs"),
+        "use std::io;\n// === BEGIN api ===\npub fn api() {}\n// === END api ===\n\
+         // === BEGIN tests ===\nfn test() {}\n// === END tests ===\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("run.py"),
+        "# === BEGIN main ===\nmain()\n# === END main ===\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @lib.rs | section=tests }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"fn test() {}\n");
+
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @run.py | section=main }}",
+    )
+    .unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--section-marker-prefix", "#"])
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"main()\n");
+
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @lib.rs | markdown | section=missing }}",
+    )
+    .unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("section 'missing' not found in lib.rs")
+    );
+}
+
+#[test]
+fn markdown_rendering_drops_a_leading_byte_order_mark() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("notes.txt"),
+        b"\xEF\xBB\xBFpub notes\n",
+    )
+    .unwrap();
+
+    let markdown = textcon()
+        .current_dir(temporary.path())
+        .arg("notes.txt")
+        .output()
+        .unwrap();
+    assert!(markdown.status.success());
+    let text = String::from_utf8(markdown.stdout).unwrap();
+    assert!(!text.contains('\u{feff}'), "{text:?}");
+    assert_eq!(text, "# `notes.txt`\n\npub notes\n\n");
+
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @notes.txt | grep=^pub }}",
+    )
+    .unwrap();
+    let filtered = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert_eq!(filtered.stdout, b"pub notes\n");
+
+    let raw = textcon()
+        .current_dir(temporary.path())
+        .args(["--render", "raw", "notes.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(raw.stdout, b"\xEF\xBB\xBFpub notes\n");
+}
+
+#[test]
+fn line_endings_convert_included_bodies() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("dos.txt"), b"one\r\ntwo\rthree\r\n").unwrap();
+    fs::write(temporary.path().join("mixed.txt"), b"one\r\ntwo\nthree").unwrap();
+
+    for (render, mode, file, expected) in [
+        (
+            "markdown",
+            "lf",
+            "dos.txt",
+            &b"# `dos.txt`\n\none\ntwo\nthree\n\n"[..],
+        ),
+        ("raw", "lf", "dos.txt", &b"one\ntwo\nthree\n"[..]),
+        ("raw", "lf", "mixed.txt", &b"one\ntwo\nthree"[..]),
+        ("raw", "crlf", "mixed.txt", &b"one\r\ntwo\r\nthree"[..]),
+        (
+            "markdown",
+            "crlf",
+            "mixed.txt",
+            &b"# `mixed.txt`\n\none\r\ntwo\r\nthree\n\n"[..],
+        ),
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--render", render, "--line-endings", mode, file])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, expected, "{render} {mode} {file}");
+    }
+
+    let preserved = textcon()
+        .current_dir(temporary.path())
+        .args(["--render", "raw", "dos.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(preserved.stdout, b"one\r\ntwo\rthree\r\n");
+}
+
+#[test]
+fn strip_trailing_whitespace_trims_every_line() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(
+        temporary.path().join("padded.txt"),
+        "one  \ntwo\t\r\nthree ",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--render",
+            "raw",
+            "--strip-trailing-whitespace",
+            "padded.txt",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"one\ntwo\r\nthree");
+}
+
+#[test]
+fn ensure_trailing_newline_adds_one_only_when_missing() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("open"), "no newline").unwrap();
+    fs::write(temporary.path().join("closed"), "newline\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @open }}{{ @closed }}|",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--ensure-trailing-newline"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"no newline\nnewline\n|");
+}
+
+#[test]
+fn base_alias_resolves_references_beneath_its_directory() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("main")).unwrap();
+    fs::create_dir_all(temporary.path().join("lib/src")).unwrap();
+    fs::write(temporary.path().join("lib/src/lib.rs"), "pub fn lib() {}\n").unwrap();
+    fs::write(
+        temporary.path().join("lib/src/nested.md"),
+        "nested: {{ @lib.rs }}",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("main/template"),
+        "{{ @src | base=lib | markdown }}{{ @src/nested.md | base=lib | template }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path().join("main"))
+        .args(["--template", "template", "--sandbox"])
+        .args(["--base-alias", "lib=../lib"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# `../lib/src/lib.rs`\n\npub fn lib() {}\n\n# `../lib/src/nested.md`\n\n\
+         nested: {{ @lib.rs }}\n\nnested: pub fn lib() {}\n"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path().join("main"))
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("unknown base alias 'lib'"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn template_relative_resolves_beneath_the_template_directory() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("src")).unwrap();
+    fs::create_dir_all(temporary.path().join("templates")).unwrap();
+    fs::write(temporary.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(
+        temporary.path().join("templates/context.tmpl"),
+        "{{ @../src/main.rs }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "templates/context.tmpl"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--template",
+            "templates/context.tmpl",
+            "--template-relative",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"fn main() {}\n");
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "-", "--template-relative"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn template_relative_resolves_each_template_beneath_its_own_directory() {
+    let temporary = TempDir::new().unwrap();
+    for directory in ["api", "web"] {
+        fs::create_dir_all(temporary.path().join(directory)).unwrap();
+        fs::write(
+            temporary.path().join(directory).join("notes.txt"),
+            format!("{directory} notes\n"),
+        )
+        .unwrap();
+        fs::write(
+            temporary.path().join(directory).join("context.tmpl"),
+            "{{ @notes.txt }}",
+        )
+        .unwrap();
+    }
+    let templates = ["-t", "api/context.tmpl", "-t", "web/context.tmpl"];
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(templates)
+        .args(["--template-relative", "--separator", "--\n"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "api notes\n--\nweb notes\n"
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(templates)
+        .args(["--template-relative", "--validate"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(templates)
+        .args(["--template-relative", "--manifest", "SHA256SUMS"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let manifest = fs::read_to_string(temporary.path().join("SHA256SUMS")).unwrap();
+    assert!(manifest.contains("  api/notes.txt\n"), "{manifest}");
+    assert!(manifest.contains("  web/notes.txt\n"), "{manifest}");
+}
+
+#[test]
+fn max_trees_replaces_later_listings_with_a_note() {
+    let temporary = TempDir::new().unwrap();
+    for directory in ["a", "b", "c"] {
+        fs::create_dir(temporary.path().join(directory)).unwrap();
+        fs::write(temporary.path().join(directory).join("file"), "").unwrap();
+    }
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @a | tree }}{{ @[b, c] | tree }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--max-trees", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "a/\n└── file\n\
+         <!-- tree of b omitted (max_trees reached) -->\n\
+         <!-- tree of c omitted (max_trees reached) -->\n"
+    );
+}
+
+#[test]
+fn tree_reference_annotates_directories_with_readme_summaries() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("src/parser")).unwrap();
+    fs::write(temporary.path().join("src/lib.rs"), "").unwrap();
+    fs::write(temporary.path().join("src/parser/mod.rs"), "").unwrap();
+    fs::write(
+        temporary.path().join("src/parser/README.md"),
+        "# Streaming reference scanner\n\nDetails.\n",
+    )
+    .unwrap();
+    fs::write(temporary.path().join("template"), "{{ @src | tree }}").unwrap();
+
+    let plain = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        plain.status.success(),
+        "{}",
+        String::from_utf8_lossy(&plain.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(plain.stdout).unwrap(),
+        "src/\n├── lib.rs\n└── parser/\n    ├── README.md\n    └── mod.rs\n"
+    );
+
+    let annotated = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--readme-summaries"])
+        .output()
+        .unwrap();
+    assert!(annotated.status.success());
+    assert_eq!(
+        String::from_utf8(annotated.stdout).unwrap(),
+        "src/\n├── lib.rs\n└── parser/ # Streaming reference scanner\n    ├── README.md\n    └── mod.rs\n"
+    );
+}
+
+#[test]
+fn file_sizes_follow_tree_file_entries() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("src/bin")).unwrap();
+    fs::write(temporary.path().join("src/main.rs"), vec![b'x'; 4300]).unwrap();
+    fs::write(temporary.path().join("src/bin/tool.rs"), "fn main() {}\n").unwrap();
+    fs::write(temporary.path().join("template"), "{{ @src | tree }}").unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template", "--file-sizes"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "src/\n├── bin/\n│   └── tool.rs (13 B)\n└── main.rs (4.2 KB)\n"
+    );
+}
+
+#[test]
+fn variables_substitute_beside_references_and_are_not_reparsed() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("notes.txt"), "NOTES").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "Task: {{ $task }}\n{{ @notes.txt }} {{ $unset }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--template",
+            "template",
+            "--replace",
+            "task={{ @notes.txt }}",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"Task: {{ @notes.txt }}\nNOTES ");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("undefined variable 'unset'"));
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--template",
+            "template",
+            "--replace",
+            "task=draft",
+            "--replace",
+            "task=refactor the parser",
+            "--allow-undefined",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        output.stdout,
+        b"Task: refactor the parser\nNOTES {{ $unset }}"
+    );
+}
+
+#[test]
+fn validate_checks_references_without_output() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("project")).unwrap();
+    fs::write(temporary.path().join("project/inside.txt"), "INSIDE").unwrap();
+    fs::write(temporary.path().join("outside.txt"), "OUTSIDE").unwrap();
+    fs::write(temporary.path().join("valid"), "{{ @inside.txt }}").unwrap();
+    fs::write(
+        temporary.path().join("escaping"),
+        "{{ @inside.txt }}{{ @../outside.txt }}",
+    )
+    .unwrap();
+
+    let validate = |template: &str| {
+        textcon()
+            .current_dir(temporary.path())
+            .args(["--validate", "--sandbox", "--base-dir", "project"])
+            .args(["--template", template])
+            .output()
+            .unwrap()
+    };
+    let output = validate("valid");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+
+    let output = validate("escaping");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("sandbox denied reference ../outside.txt")
+    );
+}
+
+#[test]
+fn bracketed_list_reference_keeps_member_order() {
+    let temporary = TempDir::new().unwrap();
+    fs::write(temporary.path().join("a.rs"), "A\n").unwrap();
+    fs::write(temporary.path().join("b.rs"), "B\n").unwrap();
+    fs::write(temporary.path().join("c.rs"), "C\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @[c.rs, a.rs, b.rs] | markdown }}bare={{ @[b.rs, a.rs] }}",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        output.stdout,
+        b"# `c.rs`\n\nC\n\n# `a.rs`\n\nA\n\n# `b.rs`\n\nB\n\nbare=B\nA\n"
+    );
+
+    fs::write(temporary.path().join("missing"), "{{ @[a.rs, gone.rs] }}").unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "missing"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"A\n");
+}
+
+#[test]
+fn tree_summarizes_directories_wider_than_the_breadth_limit() {
+    let temporary = TempDir::new().unwrap();
+    let migrations = temporary.path().join("db/migrations");
+    fs::create_dir_all(&migrations).unwrap();
+    for index in 0..500 {
+        fs::write(migrations.join(format!("{index:04}.sql")), "").unwrap();
+    }
+    fs::write(temporary.path().join("db/schema.rs"), "").unwrap();
+
+    let mut child = textcon()
+        .current_dir(temporary.path())
+        .args(["--template", "-", "--summarize-breadth-over", "100"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"{{ @db | tree }}")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "db/\n├── migrations/ (500 files: *.sql)\n└── schema.rs\n"
+    );
+}
+
+#[test]
+fn doc_summaries_extend_headings_and_tree_entries() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(
+        temporary.path().join("src/lib.rs"),
+        "#![forbid(unsafe_code)]\n//! A template processing library\npub mod x;\n",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("src/tool.py"),
+        "\"\"\"Command-line helper.\"\"\"\n",
+    )
+    .unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--doc-summaries", "src"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# `src/lib.rs` — A template processing library\n\n#![forbid(unsafe_code)]\n//! A template processing library\npub mod x;\n\n# `src/tool.py` — Command-line helper.\n\n\"\"\"Command-line helper.\"\"\"\n\n"
+    );
+
+    fs::write(temporary.path().join("template"), "{{ @src | tree }}").unwrap();
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--doc-summaries", "--template", "template"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "src/\n├── lib.rs # A template processing library\n└── tool.py # Command-line helper.\n"
+    );
+}
+
+#[test]
+fn template_processor_expands_nested_templates_relative_to_their_directory() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir_all(temporary.path().join("prompts/parts")).unwrap();
+    fs::write(
+        temporary.path().join("prompts/review.md"),
+        "Review {{ $task }}:\n{{ @parts/code.md | template }}literal {{ @parts/code.md }}",
+    )
+    .unwrap();
+    fs::write(
+        temporary.path().join("prompts/parts/code.md"),
+        "[{{ @../../main.rs }}]\n",
+    )
+    .unwrap();
+    fs::write(temporary.path().join("main.rs"), "fn main() {}").unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args(["--replace", "task=main", "--template", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(b"{{ @prompts/review.md | template }}")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Review main:\n[fn main() {}]\nliteral [{{ @../../main.rs }}]\n"
+    );
+}
+
+#[test]
+fn modified_within_skips_older_discovered_files() {
+    use std::time::{Duration, SystemTime};
+
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("logs")).unwrap();
+    fs::write(temporary.path().join("logs/recent"), "RECENT").unwrap();
+    let old = temporary.path().join("logs/old");
+    fs::write(&old, "OLD").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_hours(30 * 24))
+        .unwrap();
+    fs::write(temporary.path().join("template"), "{{ @logs }}").unwrap();
+
+    for arguments in [
+        &["logs"][..],
+        &["--template", "template"],
+        &["--template", "template", "--sandbox"],
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--render", "raw", "--modified-within", "7d"])
+            .args(arguments)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(output.stdout, b"RECENT");
+    }
+
+    let explicit = textcon()
+        .current_dir(temporary.path())
+        .args(["--render", "raw", "--modified-within", "1h", "logs/old"])
+        .output()
+        .unwrap();
+    assert_eq!(explicit.stdout, b"OLD");
+
+    let invalid = textcon()
+        .args(["--modified-within", "7 days", "logs"])
+        .output()
+        .unwrap();
+    assert_eq!(invalid.status.code(), Some(2));
+}
+
+#[test]
+fn extension_lists_select_discovered_files() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/a.rs"), "A").unwrap();
+    fs::write(temporary.path().join("src/b.PY"), "B").unwrap();
+    fs::write(temporary.path().join("src/c.pyc"), "C").unwrap();
+    fs::write(temporary.path().join("src/d.tar.gz"), "D").unwrap();
+    fs::write(temporary.path().join("template"), "{{ @src }}").unwrap();
+
+    for (arguments, expected) in [
+        (&["src"][..], &b"ABD"[..]),
+        (&["--template", "template"], b"ABD"),
+        (&["--template", "template", "--sandbox"], b"ABD"),
+        (&["--include-extension", "rs", "src"], b"A"),
+        (
+            &[
+                "--include-extension",
+                ".py",
+                "--include-extension",
+                "pyc",
+                "src",
+            ],
+            b"BC",
+        ),
+        (&["--exclude-extension", "tar.gz", "src"], b"AB"),
+        (&["src/c.pyc"], b"C"),
+    ] {
+        let output = textcon()
+            .current_dir(temporary.path())
+            .args(["--render", "raw"])
+            .args(arguments)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(output.stdout, expected, "{arguments:?}");
+    }
+}
+
+#[test]
+fn code_fences_wrap_source_bodies_with_their_language() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("src")).unwrap();
+    fs::write(temporary.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temporary.path().join("README.md"), "# Title\n").unwrap();
+    fs::write(
+        temporary.path().join("template"),
+        "{{ @src/main.rs }}\n{{ @README.md }}",
+    )
+    .unwrap();
+
+    let template = textcon()
+        .current_dir(temporary.path())
+        .args(["--code-fences", "--template", "template"])
+        .output()
+        .unwrap();
+    assert!(
+        template.status.success(),
+        "{}",
+        String::from_utf8_lossy(&template.stderr)
+    );
+    assert_eq!(template.stdout, b"```rust\nfn main() {}\n```\n\n## Title\n");
+
+    let operands = textcon()
+        .current_dir(temporary.path())
+        .args(["--code-fences", "src"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        operands.stdout,
+        b"# `src/main.rs`\n\n```rust\nfn main() {}\n```\n\n"
+    );
+
+    fs::write(temporary.path().join("src/lib.rs"), "fn lib() {}").unwrap();
+    fs::write(temporary.path().join("template"), "{{ @src }}").unwrap();
+    let directory = textcon()
+        .current_dir(temporary.path())
+        .args(["--code-fences", "--template", "template"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(directory.stdout).unwrap(),
+        "```rust\nfn lib() {}\n```\n```rust\nfn main() {}\n```\n"
+    );
+}
+
+#[test]
+fn skipped_report_lists_unselected_entries_with_reasons() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("project")).unwrap();
+    fs::write(temporary.path().join("project/.gitignore"), "*.log\n").unwrap();
+    fs::write(temporary.path().join("project/build.log"), "LOG").unwrap();
+    fs::write(temporary.path().join("project/notes.tmp"), "TMP").unwrap();
+    fs::write(temporary.path().join("project/keep"), "KEEP").unwrap();
+
+    let output = textcon()
+        .current_dir(temporary.path())
+        .args([
+            "--render",
+            "raw",
+            "--exclude",
+            "*.tmp",
+            "--skipped-report",
+            "project/skipped.tsv",
+            "project",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"KEEP");
+    assert_eq!(
+        fs::read_to_string(temporary.path().join("project/skipped.tsv")).unwrap(),
+        "hidden\tproject/.gitignore\n\
+         gitignored\tproject/build.log\n\
+         excluded\tproject/notes.tmp\n\
+         output\tproject/skipped.tsv\n"
+    );
+}
+
+#[cfg(feature = "completions")]
+#[test]
+fn completions_print_a_script_without_inputs() {
+    let output = textcon().args(["--completions", "bash"]).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("complete -F _textcon"));
+    assert!(script.contains("--template"));
+    assert!(output.stderr.is_empty());
+
+    let output = textcon()
+        .args(["--completions", "bash", "src"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+}
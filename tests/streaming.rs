@@ -1,9 +1,13 @@
 use std::cell::Cell;
 use std::io::{self, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
-use textcon::{Engine, EngineOptions, RenderMode};
+use textcon::{
+    Engine, EngineOptions, ExpansionStats, ProgressEvent, RenderMode, SelectionOptions,
+    TextconError, Warning, parse_reference,
+};
 
 struct GeneratedReader {
     remaining: u64,
@@ -83,3 +87,509 @@ fn included_placeholder_text_is_not_reparsed() {
         .unwrap();
     assert_eq!(output, b"{{ @missing }}");
 }
+
+#[test]
+fn progress_reports_reference_bytes_in_output_order() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir(temporary.path().join("docs")).unwrap();
+    std::fs::write(temporary.path().join("docs/a.md"), b"# A\n").unwrap();
+    std::fs::write(temporary.path().join("docs/b.txt"), b"B").unwrap();
+    std::fs::write(temporary.path().join("notes"), b"notes\n").unwrap();
+    let mut engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    engine.set_progress(move |event| sink.lock().unwrap().push(event));
+
+    let mut output = Vec::new();
+    engine
+        .expand_template(
+            &mut &b"{{ @notes }}{{ @docs | markdown }}{{ @[notes, docs/b.txt] }}"[..],
+            &mut output,
+        )
+        .unwrap();
+
+    let events = std::mem::take(&mut *events.lock().unwrap());
+    let finished = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::FinishReference { bytes, .. } => Some(*bytes),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(finished.len(), 3);
+    assert_eq!(finished.iter().sum::<u64>(), output.len() as u64);
+    assert_eq!(
+        events[1],
+        ProgressEvent::ReadFile {
+            path: PathBuf::from("notes"),
+            bytes: 6,
+        }
+    );
+    assert_eq!(
+        events[3],
+        ProgressEvent::StartReference {
+            reference: "docs".to_owned()
+        }
+    );
+    assert_eq!(
+        events[4],
+        ProgressEvent::StartDirectory {
+            path: PathBuf::from("docs")
+        }
+    );
+    assert_eq!(
+        events.last(),
+        Some(&ProgressEvent::Done {
+            total_bytes: output.len() as u64
+        })
+    );
+}
+
+#[test]
+fn checksum_manifest_has_one_entry_per_distinct_file() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir(temporary.path().join("docs")).unwrap();
+    std::fs::write(temporary.path().join("docs/a.md"), b"abc").unwrap();
+    std::fs::write(temporary.path().join("notes"), b"").unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let manifest = engine
+        .checksum_manifest(&mut &b"{{ @notes }}{{ @docs }}{{ @[docs/a.md, notes] | raw }}"[..])
+        .unwrap();
+    assert_eq!(
+        manifest.into_iter().collect::<Vec<_>>(),
+        [
+            (
+                PathBuf::from("docs/a.md"),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_owned()
+            ),
+            (
+                PathBuf::from("notes"),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn modified_times_annotate_labelled_records() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    let file = std::fs::File::create(temporary.path().join("notes")).unwrap();
+    file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_mins(28_421_910))
+        .unwrap();
+    drop(file);
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        modified_times: true,
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .expand_template(
+            &mut &b"{{ @notes | raw }}{{ @. | markdown }}"[..],
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "# `notes`\n<!-- modified: 2024-01-15T10:30:00Z -->\n\n"
+    );
+}
+
+#[test]
+fn max_files_fails_before_writing_an_oversized_directory() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir(temporary.path().join("vendor")).unwrap();
+    for index in 0..200 {
+        std::fs::write(temporary.path().join(format!("vendor/{index:03}")), b"x").unwrap();
+    }
+    for sandbox in [false, true] {
+        let engine = Engine::new(EngineOptions {
+            base_dir: temporary.path().to_path_buf(),
+            sandbox,
+            selection: SelectionOptions {
+                max_files: Some(10),
+                ..SelectionOptions::default()
+            },
+            ..EngineOptions::default()
+        })
+        .unwrap();
+
+        let mut output = Vec::new();
+        let error = engine
+            .expand_template(&mut &b"{{ @vendor/000 }}{{ @vendor }}"[..], &mut output)
+            .unwrap_err();
+        assert!(
+            matches!(
+                error.unlocated(),
+                TextconError::TooManyFiles {
+                    path,
+                    count: 200,
+                    limit: 10,
+                } if path == Path::new("vendor")
+            ),
+            "{error}"
+        );
+        assert_eq!(output, b"x");
+    }
+}
+
+#[test]
+fn template_references_split_into_one_file_each() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    let project = temporary.path().join("project");
+    std::fs::create_dir_all(project.join("src/bin")).unwrap();
+    std::fs::write(project.join("src/lib.rs"), b"lib\n").unwrap();
+    std::fs::write(project.join("src/bin/main.rs"), b"main\n").unwrap();
+    std::fs::write(project.join("Cargo.toml"), b"[package]\n").unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: project,
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let staging = temporary.path().join("staging");
+    let written = engine
+        .expand_template_to_directory(
+            &mut &b"# Files\n{{ @src/lib.rs }}\n{{ @src/bin/main.rs }}\n{{ @Cargo.toml }}\n"[..],
+            &staging,
+        )
+        .unwrap();
+
+    let names = ["src_lib.rs", "src_bin_main.rs", "Cargo.toml"];
+    assert_eq!(written, names.map(|name| staging.join(name)).to_vec());
+    assert_eq!(std::fs::read_dir(&staging).unwrap().count(), 3);
+    assert_eq!(
+        std::fs::read(staging.join("src_bin_main.rs")).unwrap(),
+        b"main\n"
+    );
+
+    let error = engine
+        .expand_template_to_directory(&mut &b"{{ @src/lib.rs }}{{ @src/lib.rs }}"[..], &staging)
+        .unwrap_err();
+    assert!(
+        matches!(error.unlocated(), TextconError::Config(_)),
+        "{error}"
+    );
+
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().join("project"),
+        max_total_output_bytes: Some(6),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    let error = engine
+        .expand_template_to_directory(
+            &mut &b"{{ @src/lib.rs }}{{ @src/bin/main.rs }}"[..],
+            &temporary.path().join("limited"),
+        )
+        .unwrap_err();
+    assert!(
+        matches!(
+            error.unlocated(),
+            TextconError::TotalSizeExceeded { limit: 6, .. }
+        ),
+        "{error}"
+    );
+}
+
+#[test]
+fn template_files_expand_in_order_with_separators() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    let project = temporary.path();
+    std::fs::write(project.join("notes.txt"), b"notes\n").unwrap();
+    for (name, template) in [
+        ("a.tpl", "A {{ @notes.txt }}"),
+        ("b.tpl", "B {{ $who }}\n"),
+        ("c.tpl", "C {{ @notes.txt | tree }}"),
+    ] {
+        std::fs::write(project.join(name), template).unwrap();
+    }
+    let engine = Engine::new(EngineOptions {
+        base_dir: project.to_path_buf(),
+        separator: "---\n".into(),
+        variables: [("who".to_owned(), "you".to_owned())].into(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .expand_template_files(
+            ["a.tpl", "b.tpl", "c.tpl"].map(|name| project.join(name)),
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "A notes\n---\nB you\n---\nC notes.txt\n"
+    );
+
+    let mut output = Vec::new();
+    let error = engine
+        .expand_template_files(
+            [project.join("a.tpl"), project.join("missing.tpl")],
+            &mut output,
+        )
+        .unwrap_err();
+    assert!(matches!(error, TextconError::Input { .. }), "{error}");
+    assert_eq!(output, b"A notes\n");
+}
+
+#[test]
+fn operands_separate_stdin_from_paths() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    let project = temporary.path();
+    std::fs::write(project.join("a.txt"), b"A\n").unwrap();
+    std::fs::write(project.join("b.txt"), b"B\n").unwrap();
+    let engine = Engine::new(EngineOptions {
+        render: RenderMode::Raw,
+        separator: "--\n".to_owned(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .render_operands(
+            [
+                project.join("a.txt"),
+                PathBuf::from("-"),
+                project.join("b.txt"),
+            ],
+            &mut &b"stdin\n"[..],
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(output, b"A\n--\nstdin\n--\nB\n");
+}
+
+#[test]
+fn template_relative_files_resolve_beside_each_template() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    let project = temporary.path();
+    std::fs::create_dir_all(project.join("prompts/review")).unwrap();
+    std::fs::write(project.join("prompts/notes.txt"), b"prompt notes\n").unwrap();
+    std::fs::write(project.join("prompts/review/notes.txt"), b"review notes\n").unwrap();
+    std::fs::write(project.join("prompts/a.tpl"), "{{ @notes.txt }}").unwrap();
+    std::fs::write(
+        project.join("prompts/review/b.tpl"),
+        "{{ @notes.txt }}{{ @notes.txt | tree }}",
+    )
+    .unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: project.to_path_buf(),
+        template_relative: true,
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .expand_template_files(
+            [
+                project.join("prompts/a.tpl"),
+                project.join("prompts/review/b.tpl"),
+            ],
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "prompt notes\nreview notes\nprompts/review/notes.txt\n"
+    );
+}
+
+#[test]
+fn reference_failures_report_their_template_line_and_column() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::write(temporary.path().join("present.rs"), b"ok\n").unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    let template = "# Context\n\n{{ @present.rs }}\nHere is the code:\n  {{ @missing.rs }}\n";
+
+    let error = engine
+        .expand_template(&mut template.as_bytes(), &mut Vec::new())
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            TextconError::Located {
+                line: 5,
+                column: 3,
+                ..
+            }
+        ),
+        "{error}"
+    );
+    assert!(matches!(error.unlocated(), TextconError::PathIo { .. }));
+    assert!(
+        error.to_string().ends_with("(template line 5, col 3)"),
+        "{error}"
+    );
+
+    let error = engine
+        .validate_template(&mut "\u{e9}\u{e9} {{ @missing.rs }}".as_bytes())
+        .unwrap_err();
+    assert!(
+        error.to_string().ends_with("(template line 1, col 4)"),
+        "{error}"
+    );
+}
+
+#[test]
+fn warnings_report_binary_files_and_empty_directories() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir_all(temporary.path().join("bin")).unwrap();
+    std::fs::create_dir_all(temporary.path().join("empty")).unwrap();
+    std::fs::write(
+        temporary.path().join("bin/tool"),
+        b"\x7fELF\x02\x01\x01\0\0",
+    )
+    .unwrap();
+    std::fs::write(temporary.path().join("bin/notes.txt"), b"text\n").unwrap();
+    let mut engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        render: RenderMode::Raw,
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&warnings);
+    engine.set_warning(move |warning| sink.lock().unwrap().push(warning));
+
+    let mut output = Vec::new();
+    engine
+        .expand_template(&mut &b"{{ @bin }}{{ @empty }}"[..], &mut output)
+        .unwrap();
+
+    assert_eq!(output, b"text\n\x7fELF\x02\x01\x01\0\0");
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        [
+            Warning::BinaryContent {
+                path: PathBuf::from("bin/tool"),
+            },
+            Warning::EmptyDirectory {
+                path: PathBuf::from("empty"),
+            },
+        ]
+    );
+}
+
+#[test]
+fn single_references_render_as_they_would_in_a_template() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir_all(temporary.path().join("src/nested")).unwrap();
+    std::fs::write(temporary.path().join("src/lib.rs"), "pub mod nested;\n").unwrap();
+    std::fs::write(temporary.path().join("src/nested/mod.rs"), "// nested\n").unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    for source in [
+        "{{ @src/lib.rs }}",
+        "{{ @src }}",
+        "{{ @src | tree }}",
+        "{{ @src | raw | reverse }}",
+    ] {
+        let mut expected = Vec::new();
+        engine
+            .expand_template(&mut source.as_bytes(), &mut expected)
+            .unwrap();
+        let mut output = Vec::new();
+        engine
+            .render_reference(&parse_reference(source).unwrap(), &mut output)
+            .unwrap();
+        assert_eq!(output, expected, "{source}");
+    }
+
+    let error = engine
+        .render_reference(&parse_reference("{{ @absent }}").unwrap(), &mut Vec::new())
+        .unwrap_err();
+    assert!(error.to_string().contains("absent"), "{error}");
+}
+
+#[test]
+fn directory_trees_list_selected_files_with_their_sizes() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir_all(temporary.path().join("src/nested")).unwrap();
+    std::fs::write(temporary.path().join("src/lib.rs"), "pub mod nested;\n").unwrap();
+    std::fs::write(temporary.path().join("src/nested/mod.rs"), "// nested\n").unwrap();
+    std::fs::write(temporary.path().join("src/.hidden"), "secret\n").unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let tree = engine.directory_tree("src").unwrap();
+    assert_eq!(tree.root.path, PathBuf::from("src"));
+    assert!(tree.root.is_dir);
+    assert_eq!(
+        tree.to_flat_list(),
+        [
+            PathBuf::from("src/lib.rs"),
+            PathBuf::from("src/nested/mod.rs")
+        ]
+    );
+    assert_eq!(tree.file_count(), 2);
+    assert_eq!(tree.total_size(), 26);
+    assert_eq!(tree.root.children[1].path, PathBuf::from("src/nested"));
+    let mut listing = Vec::new();
+    engine
+        .expand_template(&mut &b"{{ @src | tree }}"[..], &mut listing)
+        .unwrap();
+    assert_eq!(tree.to_tree_string().into_bytes(), listing);
+    assert_eq!(
+        tree.to_tree_string(),
+        "src/\n├── lib.rs\n└── nested/\n    └── mod.rs\n"
+    );
+
+    let file = engine.directory_tree("src/lib.rs").unwrap();
+    assert_eq!(file.to_flat_list(), [PathBuf::from("src/lib.rs")]);
+    assert!(file.root.children.is_empty());
+    assert_eq!(file.to_tree_string(), "src/lib.rs\n");
+}
+
+#[test]
+fn expansion_stats_total_files_and_bytes_read() {
+    let temporary = tempfile::TempDir::new().unwrap();
+    std::fs::write(temporary.path().join("a.txt"), "alpha\n").unwrap();
+    std::fs::write(temporary.path().join("b.txt"), "beta, longer\n").unwrap();
+    let mut engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        render: RenderMode::Raw,
+        ..EngineOptions::default()
+    })
+    .unwrap();
+    let stats = Arc::new(ExpansionStats::default());
+    let recorder = Arc::clone(&stats);
+    engine.set_progress(move |event| recorder.record(&event));
+
+    let mut output = Vec::new();
+    engine
+        .expand_template(&mut &b"{{ @a.txt }}-{{ @b.txt | head=1 }}"[..], &mut output)
+        .unwrap();
+
+    assert_eq!(stats.references(), 2);
+    assert_eq!(stats.files_read(), 2);
+    assert_eq!(stats.directories_walked(), 0);
+    assert_eq!(stats.bytes_read(), 19);
+    assert_eq!(stats.bytes_written(), output.len() as u64);
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "tracing")]
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tempfile::TempDir;
+use textcon::{Engine, EngineOptions};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Captured {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Captured {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[test]
+fn template_expansion_records_spans_and_file_events() {
+    let temporary = TempDir::new().unwrap();
+    fs::create_dir(temporary.path().join("docs")).unwrap();
+    fs::write(temporary.path().join("docs/a.txt"), "A").unwrap();
+    let engine = Engine::new(EngineOptions {
+        base_dir: temporary.path().to_path_buf(),
+        ..EngineOptions::default()
+    })
+    .unwrap();
+
+    let captured = Captured::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(captured.clone())
+        .with_max_level(tracing::Level::DEBUG)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(false)
+        .finish();
+    let template = b"x={{ @docs }}";
+    tracing::subscriber::with_default(subscriber, || {
+        engine
+            .expand_template(&mut Cursor::new(template), &mut Vec::new())
+            .unwrap();
+    });
+
+    let log = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert!(log.contains("expand_template{template_len=13}"), "{log}");
+    assert!(log.contains("render_reference{offset=2"), "{log}");
+    assert!(log.contains("walk directory"), "{log}");
+    assert!(log.contains("open file"), "{log}");
+}